@@ -0,0 +1,5 @@
+UPDATE todos SET priority = LEAST(GREATEST(priority, 1), 10);
+UPDATE archived SET priority = LEAST(GREATEST(priority, 1), 10);
+
+ALTER TABLE todos ADD CONSTRAINT todos_priority_range CHECK (priority BETWEEN 1 AND 10);
+ALTER TABLE archived ADD CONSTRAINT archived_priority_range CHECK (priority BETWEEN 1 AND 10);
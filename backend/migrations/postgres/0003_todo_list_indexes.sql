@@ -0,0 +1,2 @@
+CREATE INDEX IF NOT EXISTS todos_priority_id_idx ON todos (priority DESC, id);
+CREATE INDEX IF NOT EXISTS todos_title_id_idx ON todos (title, id);
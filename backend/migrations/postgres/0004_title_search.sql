@@ -0,0 +1,9 @@
+ALTER TABLE todos
+    ADD COLUMN IF NOT EXISTS search_vector tsvector
+    GENERATED ALWAYS AS (to_tsvector('simple', title)) STORED;
+ALTER TABLE archived
+    ADD COLUMN IF NOT EXISTS search_vector tsvector
+    GENERATED ALWAYS AS (to_tsvector('simple', title)) STORED;
+
+CREATE INDEX IF NOT EXISTS todos_search_vector_idx ON todos USING GIN (search_vector);
+CREATE INDEX IF NOT EXISTS archived_search_vector_idx ON archived USING GIN (search_vector);
@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS lists (
+    id SERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Everything that predates lists moves into the default list, which the
+-- backend expects to have id 1.
+INSERT INTO lists (id, name) VALUES (1, 'Default');
+SELECT setval(pg_get_serial_sequence('lists', 'id'), 1);
+
+ALTER TABLE todos ADD COLUMN list_id INT NOT NULL DEFAULT 1 REFERENCES lists (id) ON DELETE CASCADE;
+ALTER TABLE todos ALTER COLUMN list_id DROP DEFAULT;
+ALTER TABLE archived ADD COLUMN list_id INT NOT NULL DEFAULT 1 REFERENCES lists (id) ON DELETE CASCADE;
+ALTER TABLE archived ALTER COLUMN list_id DROP DEFAULT;
+
+DROP INDEX IF EXISTS todos_priority_id_idx;
+DROP INDEX IF EXISTS todos_title_id_idx;
+CREATE INDEX IF NOT EXISTS todos_list_priority_id_idx ON todos (list_id, priority DESC, id);
+CREATE INDEX IF NOT EXISTS todos_list_title_id_idx ON todos (list_id, title, id);
+CREATE INDEX IF NOT EXISTS archived_list_id_idx ON archived (list_id);
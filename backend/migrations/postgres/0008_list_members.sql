@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS list_members (
+    list_id INT NOT NULL REFERENCES lists (id) ON DELETE CASCADE,
+    user_id INT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    role TEXT NOT NULL CONSTRAINT list_members_role CHECK (role IN ('viewer', 'editor', 'owner')),
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (list_id, user_id)
+);
+CREATE INDEX IF NOT EXISTS list_members_user_id_idx ON list_members (user_id);
+
+-- Every list so far has only its owner.
+INSERT INTO list_members (list_id, user_id, role)
+    SELECT id, owner_id, 'owner' FROM lists WHERE owner_id IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS list_invites (
+    token_hash TEXT PRIMARY KEY,
+    list_id INT NOT NULL REFERENCES lists (id) ON DELETE CASCADE,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL CONSTRAINT list_invites_role CHECK (role IN ('viewer', 'editor', 'owner')),
+    created_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX IF NOT EXISTS list_invites_list_id_idx ON list_invites (list_id);
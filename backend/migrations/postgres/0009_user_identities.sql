@@ -0,0 +1,11 @@
+-- Accounts that signed in through an OpenID Connect provider, keyed by the
+-- provider's issuer and its stable subject id. Accounts created this way
+-- have an empty password hash, which never verifies.
+CREATE TABLE IF NOT EXISTS user_identities (
+    issuer TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    user_id INT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (issuer, subject)
+);
+CREATE INDEX IF NOT EXISTS user_identities_user_id_idx ON user_identities (user_id);
@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS tags (
+    id SERIAL PRIMARY KEY,
+    list_id INT NOT NULL REFERENCES lists (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    color TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (list_id, name)
+);
+
+CREATE TABLE IF NOT EXISTS todo_tags (
+    todo_id INT NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+    tag_id INT NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+    PRIMARY KEY (todo_id, tag_id)
+);
+CREATE INDEX IF NOT EXISTS todo_tags_tag_id_idx ON todo_tags (tag_id);
+
+-- Archiving a todo carries its tags over to the archived row.
+CREATE TABLE IF NOT EXISTS archived_tags (
+    archived_id INT NOT NULL REFERENCES archived (id) ON DELETE CASCADE,
+    tag_id INT NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+    PRIMARY KEY (archived_id, tag_id)
+);
+CREATE INDEX IF NOT EXISTS archived_tags_tag_id_idx ON archived_tags (tag_id);
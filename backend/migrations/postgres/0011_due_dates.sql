@@ -0,0 +1,7 @@
+ALTER TABLE todos ADD COLUMN due_at TIMESTAMPTZ;
+ALTER TABLE archived ADD COLUMN due_at TIMESTAMPTZ;
+
+-- Within a priority, todos are ordered by due date with undated ones last.
+DROP INDEX IF EXISTS todos_list_priority_id_idx;
+CREATE INDEX IF NOT EXISTS todos_list_priority_due_id_idx ON todos (list_id, priority DESC, due_at NULLS LAST, id);
+CREATE INDEX IF NOT EXISTS todos_list_due_idx ON todos (list_id, due_at) WHERE due_at IS NOT NULL;
@@ -0,0 +1,8 @@
+-- A todo is blocked by each of its blockers until they are completed. The
+-- rows go away with either todo, so archiving a blocker unblocks its todos.
+CREATE TABLE IF NOT EXISTS todo_dependencies (
+    todo_id INT NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+    blocker_id INT NOT NULL REFERENCES todos (id) ON DELETE CASCADE,
+    PRIMARY KEY (todo_id, blocker_id)
+);
+CREATE INDEX IF NOT EXISTS todo_dependencies_blocker_id_idx ON todo_dependencies (blocker_id);
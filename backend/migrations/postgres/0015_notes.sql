@@ -0,0 +1,22 @@
+-- Markdown notes, rendered to sanitized HTML on request, see notes.rs.
+ALTER TABLE todos ADD COLUMN notes TEXT;
+ALTER TABLE archived ADD COLUMN notes TEXT;
+
+-- Searches cover the notes as well, weighted below the title. A generated
+-- column cannot change its expression, so the vectors are made anew.
+DROP INDEX IF EXISTS todos_search_vector_idx;
+DROP INDEX IF EXISTS archived_search_vector_idx;
+ALTER TABLE todos DROP COLUMN search_vector;
+ALTER TABLE archived DROP COLUMN search_vector;
+ALTER TABLE todos
+    ADD COLUMN search_vector tsvector
+    GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', title), 'A') || setweight(to_tsvector('simple', coalesce(notes, '')), 'B')
+    ) STORED;
+ALTER TABLE archived
+    ADD COLUMN search_vector tsvector
+    GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', title), 'A') || setweight(to_tsvector('simple', coalesce(notes, '')), 'B')
+    ) STORED;
+CREATE INDEX IF NOT EXISTS todos_search_vector_idx ON todos USING GIN (search_vector);
+CREATE INDEX IF NOT EXISTS archived_search_vector_idx ON archived USING GIN (search_vector);
@@ -0,0 +1,17 @@
+-- Metadata of the files attached to todos; the content lives on disk under
+-- storage_key. A row belongs either to a todo or to an archived todo, and
+-- archiving moves it from one to the other.
+CREATE TABLE IF NOT EXISTS attachments (
+    id SERIAL PRIMARY KEY,
+    todo_id INT REFERENCES todos (id) ON DELETE CASCADE,
+    archived_id INT REFERENCES archived (id) ON DELETE CASCADE,
+    file_name TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    sha256 TEXT NOT NULL,
+    storage_key TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL,
+    CONSTRAINT attachments_owner CHECK ((todo_id IS NULL) <> (archived_id IS NULL))
+);
+CREATE INDEX IF NOT EXISTS attachments_todo_id_idx ON attachments (todo_id);
+CREATE INDEX IF NOT EXISTS attachments_archived_id_idx ON attachments (archived_id);
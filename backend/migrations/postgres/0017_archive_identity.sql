@@ -0,0 +1,36 @@
+-- Set when a todo is completed and cleared when it is reopened.
+ALTER TABLE todos ADD COLUMN completed_at TIMESTAMPTZ;
+ALTER TABLE archived ADD COLUMN completed_at TIMESTAMPTZ;
+
+-- Archived todos keep the id they had as todos. Until now the archive
+-- handed out ids of its own, so archived rows whose id is also taken by a
+-- todo move to fresh ids above both. References to archived rows follow
+-- along through ON UPDATE CASCADE.
+ALTER TABLE archived DROP CONSTRAINT archived_parent_id_fkey;
+ALTER TABLE archived ADD CONSTRAINT archived_parent_id_fkey
+    FOREIGN KEY (parent_id) REFERENCES archived (id) ON DELETE CASCADE ON UPDATE CASCADE;
+ALTER TABLE archived_tags DROP CONSTRAINT archived_tags_archived_id_fkey;
+ALTER TABLE archived_tags ADD CONSTRAINT archived_tags_archived_id_fkey
+    FOREIGN KEY (archived_id) REFERENCES archived (id) ON DELETE CASCADE ON UPDATE CASCADE;
+ALTER TABLE attachments DROP CONSTRAINT attachments_archived_id_fkey;
+ALTER TABLE attachments ADD CONSTRAINT attachments_archived_id_fkey
+    FOREIGN KEY (archived_id) REFERENCES archived (id) ON DELETE CASCADE ON UPDATE CASCADE;
+
+UPDATE archived
+    SET id = renumbered.new_id
+    FROM (
+        SELECT id AS old_id,
+               GREATEST((SELECT COALESCE(MAX(id), 0) FROM todos), (SELECT COALESCE(MAX(id), 0) FROM archived))
+                   + ROW_NUMBER() OVER (ORDER BY id) AS new_id
+        FROM archived
+        WHERE id IN (SELECT id FROM todos)
+    ) AS renumbered
+    WHERE archived.id = renumbered.old_id;
+
+-- Todos take their ids above every archived id from now on, so the two
+-- never collide again.
+SELECT setval(
+    pg_get_serial_sequence('todos', 'id'),
+    GREATEST((SELECT COALESCE(MAX(id), 0) FROM todos), (SELECT COALESCE(MAX(id), 0) FROM archived)) + 1,
+    FALSE
+);
@@ -0,0 +1,29 @@
+-- SQLite cannot add a constraint to an existing table, so both tables are
+-- rebuilt with the CHECK in place.
+UPDATE todos SET priority = MIN(MAX(priority, 1), 10);
+UPDATE archived SET priority = MIN(MAX(priority, 1), 10);
+
+CREATE TABLE todos_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    priority INTEGER NOT NULL CONSTRAINT todos_priority_range CHECK (priority BETWEEN 1 AND 10),
+    completed BOOLEAN DEFAULT FALSE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO todos_new (id, title, priority, completed, created_at)
+    SELECT id, title, priority, completed, created_at FROM todos;
+DROP TABLE todos;
+ALTER TABLE todos_new RENAME TO todos;
+
+CREATE TABLE archived_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    title TEXT NOT NULL,
+    priority INTEGER NOT NULL CONSTRAINT archived_priority_range CHECK (priority BETWEEN 1 AND 10),
+    completed BOOLEAN DEFAULT FALSE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    archived_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO archived_new (id, title, priority, completed, created_at, archived_at)
+    SELECT id, title, priority, completed, created_at, archived_at FROM archived;
+DROP TABLE archived;
+ALTER TABLE archived_new RENAME TO archived;
@@ -0,0 +1,42 @@
+CREATE TABLE IF NOT EXISTS lists (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Everything that predates lists moves into the default list, which the
+-- backend expects to have id 1.
+INSERT INTO lists (id, name) VALUES (1, 'Default');
+
+-- SQLite only adds a referencing column with a NULL default while foreign
+-- keys are enforced, so both tables are rebuilt instead.
+CREATE TABLE todos_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    list_id INTEGER NOT NULL REFERENCES lists (id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    priority INTEGER NOT NULL CONSTRAINT todos_priority_range CHECK (priority BETWEEN 1 AND 10),
+    completed BOOLEAN DEFAULT FALSE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO todos_new (id, list_id, title, priority, completed, created_at)
+    SELECT id, 1, title, priority, completed, created_at FROM todos;
+DROP TABLE todos;
+ALTER TABLE todos_new RENAME TO todos;
+
+CREATE TABLE archived_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    list_id INTEGER NOT NULL REFERENCES lists (id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    priority INTEGER NOT NULL CONSTRAINT archived_priority_range CHECK (priority BETWEEN 1 AND 10),
+    completed BOOLEAN DEFAULT FALSE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    archived_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO archived_new (id, list_id, title, priority, completed, created_at, archived_at)
+    SELECT id, 1, title, priority, completed, created_at, archived_at FROM archived;
+DROP TABLE archived;
+ALTER TABLE archived_new RENAME TO archived;
+
+CREATE INDEX IF NOT EXISTS todos_list_priority_id_idx ON todos (list_id, priority DESC, id);
+CREATE INDEX IF NOT EXISTS todos_list_title_id_idx ON todos (list_id, title, id);
+CREATE INDEX IF NOT EXISTS archived_list_id_idx ON archived (list_id);
@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS users (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    email TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS sessions (
+    token_hash TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMP NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sessions_user_id_idx ON sessions (user_id);
+
+-- Lists without an owner predate accounts; the first account to register
+-- takes them over, with the old default list becoming its default list.
+ALTER TABLE lists ADD COLUMN owner_id INTEGER REFERENCES users (id) ON DELETE CASCADE;
+ALTER TABLE lists ADD COLUMN is_default BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE lists SET is_default = TRUE WHERE id = 1;
+CREATE INDEX IF NOT EXISTS lists_owner_id_idx ON lists (owner_id);
+CREATE UNIQUE INDEX IF NOT EXISTS lists_one_default_per_owner ON lists (owner_id) WHERE is_default;
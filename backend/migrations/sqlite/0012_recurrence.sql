@@ -0,0 +1,6 @@
+-- A normalized rule such as 'FREQ=WEEKLY;BYDAY=MO,TH', see recurrence.rs.
+ALTER TABLE todos ADD COLUMN recurrence TEXT;
+-- Set once completing the todo has created its next occurrence, so that
+-- completing it again does not create another one.
+ALTER TABLE todos ADD COLUMN recurred BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE archived ADD COLUMN recurrence TEXT;
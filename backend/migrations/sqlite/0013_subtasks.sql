@@ -0,0 +1,5 @@
+-- Subtasks go wherever their parent goes: deleting a todo deletes its
+-- subtree, and archived subtasks point at their archived parent.
+ALTER TABLE todos ADD COLUMN parent_id INTEGER REFERENCES todos (id) ON DELETE CASCADE;
+ALTER TABLE archived ADD COLUMN parent_id INTEGER REFERENCES archived (id) ON DELETE CASCADE;
+CREATE INDEX IF NOT EXISTS todos_parent_id_idx ON todos (parent_id);
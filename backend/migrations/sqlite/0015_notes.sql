@@ -0,0 +1,3 @@
+-- Markdown notes, rendered to sanitized HTML on request, see notes.rs.
+ALTER TABLE todos ADD COLUMN notes TEXT;
+ALTER TABLE archived ADD COLUMN notes TEXT;
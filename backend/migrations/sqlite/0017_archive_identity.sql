@@ -0,0 +1,36 @@
+-- Set when a todo is completed and cleared when it is reopened.
+ALTER TABLE todos ADD COLUMN completed_at TIMESTAMP;
+ALTER TABLE archived ADD COLUMN completed_at TIMESTAMP;
+
+-- Archived todos keep the id they had as todos. Until now the archive
+-- handed out ids of its own, so archived rows whose id is also taken by a
+-- todo move to fresh ids above both. Foreign keys are only checked at
+-- commit, once every reference to a moved row points at its new id.
+PRAGMA defer_foreign_keys = ON;
+CREATE TEMPORARY TABLE archived_renumbered AS
+    SELECT id AS old_id,
+           MAX((SELECT COALESCE(MAX(id), 0) FROM todos), (SELECT COALESCE(MAX(id), 0) FROM archived))
+               + ROW_NUMBER() OVER (ORDER BY id) AS new_id
+    FROM archived
+    WHERE id IN (SELECT id FROM todos);
+UPDATE archived_tags
+    SET archived_id = (SELECT new_id FROM archived_renumbered WHERE old_id = archived_tags.archived_id)
+    WHERE archived_id IN (SELECT old_id FROM archived_renumbered);
+UPDATE attachments
+    SET archived_id = (SELECT new_id FROM archived_renumbered WHERE old_id = attachments.archived_id)
+    WHERE archived_id IN (SELECT old_id FROM archived_renumbered);
+UPDATE archived
+    SET parent_id = (SELECT new_id FROM archived_renumbered WHERE old_id = archived.parent_id)
+    WHERE parent_id IN (SELECT old_id FROM archived_renumbered);
+UPDATE archived
+    SET id = (SELECT new_id FROM archived_renumbered WHERE old_id = archived.id)
+    WHERE id IN (SELECT old_id FROM archived_renumbered);
+DROP TABLE archived_renumbered;
+
+-- Todos take their ids above every archived id from now on, so the two
+-- never collide again.
+INSERT INTO sqlite_sequence (name, seq)
+    SELECT 'todos', 0 WHERE NOT EXISTS (SELECT 1 FROM sqlite_sequence WHERE name = 'todos');
+UPDATE sqlite_sequence
+    SET seq = MAX(seq, (SELECT COALESCE(MAX(id), 0) FROM archived))
+    WHERE name = 'todos';
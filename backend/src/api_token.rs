@@ -0,0 +1,159 @@
+use axum::{
+    extract::{Extension, MatchedPath, Request},
+    http::{header, Method},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::auth::{self, User};
+use crate::error::ApiError;
+use crate::todo_store::TodoStore;
+
+/// Prepended to every token so that leaked tokens are easy to recognise.
+pub const TOKEN_PREFIX: &str = "todo_";
+
+pub const DEFAULT_TOKEN_TTL_DAYS: u32 = 30;
+pub const MAX_TOKEN_TTL_DAYS: u32 = 365;
+
+/// What an API token may do. Tokens never reach the account routes under
+/// `/api/auth` and `/api/tokens` or the invite routes, whatever their scopes.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    #[serde(rename = "todos:read")]
+    TodosRead,
+    #[serde(rename = "todos:write")]
+    TodosWrite,
+    #[serde(rename = "archive:write")]
+    ArchiveWrite,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 3] = [Scope::TodosRead, Scope::TodosWrite, Scope::ArchiveWrite];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::TodosRead => "todos:read",
+            Scope::TodosWrite => "todos:write",
+            Scope::ArchiveWrite => "archive:write",
+        }
+    }
+
+    /// The scopes a request needs, given its method and the pattern of the
+    /// route it matched, e.g. `/api/lists/:list_id/todos/:id`, or `None` for
+    /// routes that only a session may use. Deleting a list takes its archive
+    /// along, so it needs `archive:write` as well.
+    pub fn required_for(method: &Method, route: &str) -> Option<&'static [Scope]> {
+        let list_route = route.strip_prefix("/api/lists/:list_id");
+        let todo_route = route.strip_prefix("/api/todos")
+            .or_else(|| list_route.and_then(|route| route.strip_prefix("/todos")));
+        if route.starts_with("/api/auth") || route.starts_with("/api/tokens")
+            || route.starts_with("/api/invites") || list_route == Some("/invites") {
+            None
+        } else if matches!(todo_route, Some("/archive_completed" | "/:id/archive" | "/restore")) {
+            Some(&[Scope::ArchiveWrite])
+        } else if list_route == Some("") && method == Method::DELETE {
+            Some(&[Scope::TodosWrite, Scope::ArchiveWrite])
+        } else if method == Method::GET || method == Method::HEAD {
+            Some(&[Scope::TodosRead])
+        } else {
+            Some(&[Scope::TodosWrite])
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, ()> {
+        Scope::ALL.into_iter().find(|scope| scope.as_str() == value).ok_or(())
+    }
+}
+
+/// Scopes are stored as one space separated column in both databases.
+pub fn join_scopes(scopes: &[Scope]) -> String {
+    scopes.iter().map(|scope| scope.as_str()).collect::<Vec<_>>().join(" ")
+}
+
+pub fn split_scopes(scopes: &str) -> Vec<Scope> {
+    scopes.split_whitespace().filter_map(|scope| scope.parse().ok()).collect()
+}
+
+/// A personal access token as it is listed. The token itself is only shown
+/// once, when it is created; the database keeps its `hash_token` digest.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ApiToken {
+    pub id: u32,
+    pub user_id: u32,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Body of `POST /api/tokens`. Scopes are plain strings so that unknown
+/// ones are reported like any other invalid field.
+#[derive(Deserialize)]
+pub struct CreateApiToken {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_in_days: Option<u32>,
+}
+
+/// Answer to `POST /api/tokens`, the only one that carries the token.
+#[derive(Serialize)]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+    pub token: String,
+}
+
+pub fn new_api_token() -> String {
+    format!("{}{}", TOKEN_PREFIX, auth::new_session_token())
+}
+
+/// Set by `bearer_auth` on requests that came with a valid API token.
+/// `AuthUser` prefers it over the session cookie.
+#[derive(Clone)]
+pub struct TokenUser(pub User);
+
+/// Authenticates requests that carry an `Authorization: Bearer` header.
+/// Unknown, revoked and expired tokens get 401, tokens without the scope
+/// the route needs get 403, as do requests that match no route. Requests
+/// without the header pass untouched and are left to the session cookie.
+pub async fn bearer_auth(
+    Extension(db): Extension<Arc<dyn TodoStore>>,
+    mut request: Request,
+    next: Next)
+    -> Result<Response, ApiError> {
+    let Some(value) = request.headers().get(header::AUTHORIZATION) else {
+        return Ok(next.run(request).await);
+    };
+    let token = value.to_str().ok()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .ok_or_else(|| ApiError::Unauthorized("Expected a bearer token".to_string()))?;
+
+    let (user, api_token) = db.use_api_token(&auth::hash_token(token), Utc::now())
+        .await?
+        .ok_or_else(|| ApiError::Unauthorized("Invalid or expired API token".to_string()))?;
+
+    let route = request.extensions().get::<MatchedPath>().map(|route| route.as_str());
+    let scopes = route.and_then(|route| Scope::required_for(request.method(), route))
+        .ok_or_else(|| ApiError::Forbidden("API tokens cannot be used on this route".to_string()))?;
+    if let Some(scope) = scopes.iter().find(|scope| !api_token.scopes.contains(scope)) {
+        return Err(ApiError::Forbidden(format!("The token lacks the {} scope", scope)));
+    }
+    request.extensions_mut().insert(TokenUser(user));
+    Ok(next.run(request).await)
+}
@@ -0,0 +1,195 @@
+use axum::extract::multipart::{Field, MultipartError};
+use axum::http::HeaderValue;
+use chrono::{DateTime, Utc};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use crate::error::{ApiError, FieldError};
+use crate::todo_store::TodoStore;
+
+pub const DEFAULT_ATTACHMENTS_DIR: &str = "attachments";
+pub const DEFAULT_MAX_ATTACHMENT_BYTES: u64 = 10 * 1024 * 1024;
+pub const MAX_FILE_NAME_LENGTH: usize = 255;
+/// Given to uploads that come without a usable content type.
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// A file attached to a todo. The metadata lives in the store, the content
+/// in a file of the attachments directory named by `storage_key`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub id: u32,
+    /// The todo the file belongs to or, with `archived` set, the archived
+    /// todo. Archiving a todo takes its attachments along.
+    pub todo_id: u32,
+    pub archived: bool,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: u64,
+    /// The SHA-256 digest of the content in lowercase hex.
+    pub sha256: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip)]
+    pub storage_key: String,
+}
+
+/// Path of `GET` and `DELETE /api/todos/:id/attachments/:attachment_id`.
+#[derive(Deserialize)]
+pub struct AttachmentPath {
+    pub id: u32,
+    pub attachment_id: u32,
+}
+
+/// Where attachment files are kept and how large they may get.
+#[derive(Clone, Debug)]
+pub struct AttachmentConfig {
+    pub dir: PathBuf,
+    pub max_bytes: u64,
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        AttachmentConfig {
+            dir: PathBuf::from(DEFAULT_ATTACHMENTS_DIR),
+            max_bytes: DEFAULT_MAX_ATTACHMENT_BYTES,
+        }
+    }
+}
+
+/// An uploaded file that has been written to the attachments directory.
+pub struct StoredFile {
+    pub storage_key: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl AttachmentConfig {
+    /// Reads `ATTACHMENTS_DIR` and `ATTACHMENT_MAX_BYTES`, falling back to
+    /// the defaults for anything that is missing or not a number.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        AttachmentConfig {
+            dir: std::env::var("ATTACHMENTS_DIR").map(PathBuf::from).unwrap_or(defaults.dir),
+            max_bytes: std::env::var("ATTACHMENT_MAX_BYTES")
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(defaults.max_bytes),
+        }
+    }
+
+    /// Streams an uploaded file into the attachments directory, hashing it
+    /// on the way. Uploads that grow beyond `max_bytes` are dropped with
+    /// `ApiError::PayloadTooLarge`. The file only takes its final name once
+    /// it is complete, so readers never see a partial upload.
+    pub async fn store(&self, field: &mut Field<'_>) -> Result<StoredFile, ApiError> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let storage_key = new_storage_key();
+        let partial = self.dir.join(format!("{}.part", storage_key));
+        let result = self.write(field, &partial).await;
+        let (size, sha256) = match result {
+            Ok(written) => written,
+            Err(err) => {
+                let _ = tokio::fs::remove_file(&partial).await;
+                return Err(err);
+            }
+        };
+        tokio::fs::rename(&partial, self.dir.join(&storage_key)).await?;
+        Ok(StoredFile { storage_key, size, sha256 })
+    }
+
+    async fn write(&self, field: &mut Field<'_>, path: &PathBuf) -> Result<(u64, String), ApiError> {
+        let mut file = tokio::fs::File::create(path).await?;
+        let mut hasher = Sha256::new();
+        let mut size = 0u64;
+        while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+            size += chunk.len() as u64;
+            if size > self.max_bytes {
+                return Err(ApiError::PayloadTooLarge(format!("Attachments must be at most {} bytes", self.max_bytes)));
+            }
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        Ok((size, to_hex(&hasher.finalize())))
+    }
+
+    /// Opens the file of an attachment, to be streamed rather than read
+    /// into memory as a whole. A file that went missing while its metadata
+    /// stayed is reported as not found and logged.
+    pub async fn open(&self, attachment: &Attachment) -> Result<tokio::fs::File, ApiError> {
+        match tokio::fs::File::open(self.dir.join(&attachment.storage_key)).await {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                tracing::error!("file {} of attachment {} is missing", attachment.storage_key, attachment.id);
+                Err(ApiError::NotFound(format!("Attachment file of attachment {} not found", attachment.id)))
+            }
+            file => Ok(file?),
+        }
+    }
+
+    /// Deleting todos takes the metadata of their attachments along; this
+    /// removes the files of the attachments in `before` that are gone now.
+    pub async fn remove_dropped(&self, db: &Arc<dyn TodoStore>, list_id: u32, before: Vec<Attachment>)
+        -> Result<(), ApiError> {
+        let after = db.query_list_attachments(list_id).await?;
+        for attachment in before {
+            if !after.iter().any(|kept| kept.storage_key == attachment.storage_key) {
+                self.remove(&attachment.storage_key).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes the file of an attachment whose metadata is gone. A file that
+    /// is already missing is fine; other failures only leave a stray file
+    /// behind, so they are logged rather than reported.
+    pub async fn remove(&self, storage_key: &str) {
+        match tokio::fs::remove_file(self.dir.join(storage_key)).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                tracing::warn!("could not remove attachment file {}: {}", storage_key, err);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Keeps the last path segment of an uploaded file name without control
+/// characters and quotes, so that it fits into a `Content-Disposition`
+/// header as is.
+pub fn file_name(uploaded: Option<&str>) -> String {
+    let name: String = uploaded.unwrap_or_default()
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(MAX_FILE_NAME_LENGTH)
+        .collect();
+    match name.trim() {
+        "" | "." | ".." => "attachment".to_string(),
+        name => name.to_string(),
+    }
+}
+
+/// The content type the client sent, if it can be sent back as a header.
+pub fn content_type(uploaded: Option<&str>) -> String {
+    uploaded
+        .filter(|value| value.contains('/') && HeaderValue::from_str(value).is_ok())
+        .unwrap_or(DEFAULT_CONTENT_TYPE)
+        .to_string()
+}
+
+pub fn multipart_error(err: MultipartError) -> ApiError {
+    ApiError::Validation(vec![FieldError::new("file", err.body_text())])
+}
+
+fn new_storage_key() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,117 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap, HeaderValue},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Duration, Utc};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use crate::api_token::TokenUser;
+use crate::error::ApiError;
+use crate::todo_store::TodoStore;
+
+pub const SESSION_COOKIE: &str = "session";
+
+/// How long a session stays valid after login.
+pub const SESSION_TTL_DAYS: i64 = 14;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct User {
+    pub id: u32,
+    pub email: String,
+}
+
+/// Body of `POST /api/auth/register` and `POST /api/auth/login`.
+#[derive(Deserialize)]
+pub struct Credentials {
+    pub email: String,
+    pub password: String,
+}
+
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("argon2 failed to hash a password")
+        .to_string()
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    PasswordHash::new(password_hash)
+        .map(|hash| Argon2::default().verify_password(password.as_bytes(), &hash).is_ok())
+        .unwrap_or(false)
+}
+
+/// A new random session token. Only its `hash_token` digest is stored, so a
+/// leaked sessions table cannot be replayed.
+pub fn new_session_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+pub fn hash_token(token: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+pub fn session_expiry(now: DateTime<Utc>) -> DateTime<Utc> {
+    now + Duration::days(SESSION_TTL_DAYS)
+}
+
+pub fn session_cookie(token: &str) -> HeaderValue {
+    let max_age = Duration::days(SESSION_TTL_DAYS).num_seconds();
+    HeaderValue::from_str(&format!(
+        "{}={}; HttpOnly; SameSite=Lax; Path=/; Max-Age={}",
+        SESSION_COOKIE, token, max_age,
+    ))
+    .unwrap()
+}
+
+pub fn expired_session_cookie() -> HeaderValue {
+    HeaderValue::from_str(&format!("{}=; HttpOnly; SameSite=Lax; Path=/; Max-Age=0", SESSION_COOKIE)).unwrap()
+}
+
+/// The value of the session cookie, if the request carries one.
+pub fn session_token(headers: &HeaderMap) -> Option<String> {
+    cookie_value(headers, SESSION_COOKIE)
+}
+
+pub fn cookie_value(headers: &HeaderMap, cookie: &str) -> Option<String> {
+    headers.get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == cookie)
+        .map(|(_, value)| value.to_string())
+}
+
+/// The user behind the API token or, failing that, the session cookie of a
+/// request. Requests without a valid, unexpired session are rejected with 401.
+pub struct AuthUser(pub User);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AuthUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, ApiError> {
+        if let Some(TokenUser(user)) = parts.extensions.get::<TokenUser>() {
+            return Ok(AuthUser(user.clone()));
+        }
+        let token = session_token(&parts.headers).ok_or_else(ApiError::unauthenticated)?;
+        let db = parts.extensions.get::<Arc<dyn TodoStore>>()
+            .cloned()
+            .expect("the todo store extension is missing");
+        db.query_session_user(&hash_token(&token), Utc::now())
+            .await?
+            .map(AuthUser)
+            .ok_or_else(ApiError::unauthenticated)
+    }
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::Todo;
+use crate::error::{ApiError, FieldError};
+
+/// Records that `todo_id` is blocked by `blocker_id`: it cannot be
+/// completed while the blocker is open.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dependency {
+    pub todo_id: u32,
+    pub blocker_id: u32,
+}
+
+/// Body of `POST /api/todos/:id/blockers`.
+#[derive(Deserialize)]
+pub struct BlockerPayload {
+    pub blocker_id: u32,
+}
+
+/// Path of `DELETE /api/todos/:id/blockers/:blocker_id`.
+#[derive(Deserialize)]
+pub struct BlockerPath {
+    pub id: u32,
+    pub blocker_id: u32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GraphNode {
+    pub id: u32,
+    pub title: String,
+    pub completed: bool,
+    pub blocked: bool,
+}
+
+/// Body of `GET /api/todos/graph`: every todo of the list, with an edge
+/// from each blocked todo to each of its blockers.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DependencyGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<Dependency>,
+}
+
+impl DependencyGraph {
+    pub fn new(todos: &[Todo], edges: Vec<Dependency>) -> Self {
+        let nodes = todos.iter()
+            .map(|todo| GraphNode {
+                id: todo.id,
+                title: todo.title.clone(),
+                completed: todo.completed,
+                blocked: todo.blocked,
+            })
+            .collect();
+        DependencyGraph { nodes, edges }
+    }
+}
+
+/// Sets `blocked` on each of `todos` that waits on one of them that is
+/// still open.
+pub fn mark_blocked(todos: &mut [Todo], dependencies: &[Dependency]) {
+    let open: Vec<u32> = todos.iter().filter(|todo| !todo.completed).map(|todo| todo.id).collect();
+    for todo in todos.iter_mut() {
+        todo.blocked = dependencies.iter()
+            .any(|dependency| dependency.todo_id == todo.id && open.contains(&dependency.blocker_id));
+    }
+}
+
+/// Fails with `ApiError::Conflict` if one of the todos about to be
+/// completed is open and still blocked.
+pub fn check_unblocked(todos: &[Todo], todo_ids: impl IntoIterator<Item = u32>) -> Result<(), ApiError> {
+    for todo_id in todo_ids {
+        if todos.iter().any(|todo| todo.id == todo_id && todo.blocked && !todo.completed) {
+            return Err(ApiError::todo_blocked(todo_id as u64));
+        }
+    }
+    Ok(())
+}
+
+/// Whether `to` can be reached from `from` by following blockers.
+fn blocked_by(dependencies: &[Dependency], from: u32, to: u32) -> bool {
+    let mut blockers: HashMap<u32, Vec<u32>> = HashMap::new();
+    for dependency in dependencies {
+        blockers.entry(dependency.todo_id).or_default().push(dependency.blocker_id);
+    }
+    let mut seen = vec![from];
+    let mut next = vec![from];
+    while let Some(id) = next.pop() {
+        for blocker in blockers.get(&id).into_iter().flatten() {
+            if *blocker == to {
+                return true;
+            }
+            if !seen.contains(blocker) {
+                seen.push(*blocker);
+                next.push(*blocker);
+            }
+        }
+    }
+    false
+}
+
+/// Checks that `blocker_id` names one of `todos` that may block `todo_id`
+/// given the `dependencies` of the list: a todo that already waits on
+/// `todo_id`, directly or not, would make a cycle that nobody can finish.
+pub fn check_blocker(todos: &[Todo], dependencies: &[Dependency], todo_id: u32, blocker_id: u32) -> Result<(), FieldError> {
+    if !todos.iter().any(|todo| todo.id == blocker_id) {
+        return Err(FieldError::new("blocker_id", "must be a todo of the same list"));
+    }
+    if blocker_id == todo_id || blocked_by(dependencies, blocker_id, todo_id) {
+        return Err(FieldError::new("blocker_id", "must not be the todo itself or a todo it blocks"));
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+use axum::{
+    extract::Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::fmt;
+use crate::validation;
+
+/// The error type shared by the stores and the HTTP handlers. Every variant
+/// is rendered as an `ErrorBody` with a matching status code.
+#[derive(Debug)]
+pub enum ApiError {
+    NotFound(String),
+    Unauthorized(String),
+    Forbidden(String),
+    Validation(Vec<FieldError>),
+    Conflict(String),
+    PayloadTooLarge(String),
+    Database(sqlx::Error),
+    /// Reading or writing attachment files failed.
+    Storage(std::io::Error),
+}
+
+/// One rejected input field, reported in the `details` of a validation error.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError { field: field.to_string(), message: message.into() }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ErrorBody {
+    pub error: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<FieldError>,
+}
+
+impl ApiError {
+    pub fn todo_not_found(todo_id: u64) -> Self {
+        ApiError::NotFound(format!("Todo with id {} not found", todo_id))
+    }
+
+    pub fn archived_todo_not_found(archived_id: u32) -> Self {
+        ApiError::NotFound(format!("Archived todo with id {} not found", archived_id))
+    }
+
+    pub fn list_not_found(list_id: u32) -> Self {
+        ApiError::NotFound(format!("List with id {} not found", list_id))
+    }
+
+    pub fn tag_not_found(todo_id: u64, name: &str) -> Self {
+        ApiError::NotFound(format!("Todo with id {} has no tag '{}'", todo_id, name))
+    }
+
+    pub fn dependency_not_found(todo_id: u64, blocker_id: u64) -> Self {
+        ApiError::NotFound(format!("Todo with id {} is not blocked by todo {}", todo_id, blocker_id))
+    }
+
+    pub fn todo_blocked(todo_id: u64) -> Self {
+        ApiError::Conflict(format!("Todo with id {} is blocked by open todos", todo_id))
+    }
+
+    pub fn attachment_not_found(attachment_id: u32) -> Self {
+        ApiError::NotFound(format!("Attachment with id {} not found", attachment_id))
+    }
+
+    pub fn email_taken() -> Self {
+        ApiError::Conflict("An account with this email already exists".to_string())
+    }
+
+    pub fn api_token_not_found(token_id: u32) -> Self {
+        ApiError::NotFound(format!("API token with id {} not found", token_id))
+    }
+
+    pub fn invite_not_found() -> Self {
+        ApiError::NotFound("Invite not found".to_string())
+    }
+
+    pub fn unauthenticated() -> Self {
+        ApiError::Unauthorized("Authentication required".to_string())
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::Validation(_) => StatusCode::BAD_REQUEST,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Database(_) | ApiError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::Database(_) => "database_error",
+            ApiError::Storage(_) => "storage_error",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::NotFound(message)
+            | ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::Conflict(message)
+            | ApiError::PayloadTooLarge(message) => write!(f, "{}", message),
+            ApiError::Validation(errors) => {
+                let fields: Vec<String> = errors.iter()
+                    .map(|e| format!("{} {}", e.field, e.message))
+                    .collect();
+                write!(f, "Invalid input: {}", fields.join(", "))
+            }
+            ApiError::Database(err) => write!(f, "Database error: {}", err),
+            ApiError::Storage(err) => write!(f, "Storage error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<std::io::Error> for ApiError {
+    fn from(err: std::io::Error) -> Self {
+        ApiError::Storage(err)
+    }
+}
+
+/// What clients are told when a unique constraint rejects a write, by a
+/// part of the constraint name (Postgres) or of the columns that SQLite
+/// names in its message instead.
+const UNIQUE_VIOLATIONS: &[(&[&str], &str)] = &[
+    (&["users_email", "users.email"], "An account with this email already exists"),
+    (&["user_identities"], "The identity is already linked"),
+    (&["tags_list_id_name", "tags.name"], "A tag with this name already exists"),
+    (&["todo_tags", "archived_tags"], "The todo already has this tag"),
+    (&["todo_dependencies"], "The todo is already blocked by that todo"),
+    (&["list_members"], "The user is already a member of the list"),
+    (&["lists_one_default", "lists.owner_id"], "The user already has a default list"),
+];
+
+/// Like `UNIQUE_VIOLATIONS`, for check constraints.
+const CHECK_VIOLATIONS: &[(&str, &str, &str)] = &[
+    ("list_members_role", "role", "must be one of viewer, editor and owner"),
+    ("list_invites_role", "role", "must be one of viewer, editor and owner"),
+];
+
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::RowNotFound => ApiError::NotFound("Row not found".to_string()),
+            // The database's own message names constraints and repeats
+            // values, so it only goes to the log.
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                tracing::warn!("unique violation: {}", db_err.message());
+                let constraint = db_err.constraint().unwrap_or(db_err.message());
+                let message = UNIQUE_VIOLATIONS.iter()
+                    .find(|(names, _)| names.iter().any(|name| constraint.contains(name)))
+                    .map_or("The record already exists", |(_, message)| message);
+                ApiError::Conflict(message.to_string())
+            }
+            sqlx::Error::Database(db_err) if db_err.is_check_violation() => {
+                tracing::warn!("check violation: {}", db_err.message());
+                // SQLite does not report the constraint name separately, but
+                // both databases mention it in the message.
+                let constraint = db_err.constraint().unwrap_or(db_err.message());
+                if constraint.contains("priority_range") {
+                    ApiError::Validation(vec![validation::priority_error("priority")])
+                } else if let Some((_, field, message)) = CHECK_VIOLATIONS.iter().find(|(name, ..)| constraint.contains(name)) {
+                    ApiError::Validation(vec![FieldError::new(field, *message)])
+                } else {
+                    ApiError::Validation(vec![FieldError::new("", "is not allowed")])
+                }
+            }
+            _ => ApiError::Database(err),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        // Database and storage details stay in the log, clients only get a
+        // generic message.
+        let message = match &self {
+            ApiError::Database(err) => {
+                tracing::error!("database error: {}", err);
+                "Internal database error".to_string()
+            }
+            ApiError::Storage(err) => {
+                tracing::error!("storage error: {}", err);
+                "Internal storage error".to_string()
+            }
+            other => other.to_string(),
+        };
+        let details = match &self {
+            ApiError::Validation(errors) => errors.clone(),
+            _ => Vec::new(),
+        };
+        let body = ErrorBody { error: self.code(), message, details };
+        (self.status(), Json(body)).into_response()
+    }
+}
@@ -0,0 +1,821 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use crate::{Todo, TodoList, UpdateTodo, DEFAULT_LIST_ID};
+use crate::api_token::{ApiToken, Scope};
+use crate::attachments::Attachment;
+use crate::auth::User;
+use crate::dependencies::{self, Dependency};
+use crate::error::ApiError;
+use crate::membership::{ListInvite, ListMember, Role};
+use crate::recurrence;
+use crate::subtasks;
+use crate::tags::{Tag, DEFAULT_TAG_COLOR};
+use crate::todo_query::{TodoPage, TodoQuery};
+use crate::todo_store::TodoStore;
+use crate::validation::{self, MAX_PRIORITY, MIN_PRIORITY};
+
+#[derive(Clone)]
+struct StoredTodo {
+    id: u32,
+    list_id: u32,
+    title: String,
+    priority: i32,
+    completed: bool,
+    completed_at: Option<DateTime<Utc>>,
+    due_at: Option<DateTime<Utc>>,
+    recurrence: Option<String>,
+    /// Stands in for the `recurred` column.
+    recurred: bool,
+    parent_id: Option<u32>,
+    notes: Option<String>,
+    tag_ids: Vec<u32>,
+    /// Stands in for the `archived_at` column of archived todos.
+    archived_at: Option<DateTime<Utc>>,
+}
+
+impl StoredTodo {
+    fn to_todo(&self, tags: &[Tag]) -> Todo {
+        let mut tags: Vec<Tag> = tags.iter().filter(|tag| self.tag_ids.contains(&tag.id)).cloned().collect();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Todo {
+            id: self.id,
+            list_id: self.list_id,
+            title: self.title.clone(),
+            priority: self.priority as u8,
+            completed: self.completed,
+            completed_at: self.completed_at,
+            due_at: self.due_at,
+            recurrence: self.recurrence.clone(),
+            parent_id: self.parent_id,
+            notes: self.notes.clone(),
+            tags,
+            blocked: false,
+        }
+    }
+}
+
+/// Stands in for the `todos_priority_range` CHECK constraint.
+fn check_priority_range(priority: i32) -> Result<(), ApiError> {
+    if (MIN_PRIORITY as i32..=MAX_PRIORITY as i32).contains(&priority) {
+        Ok(())
+    } else {
+        Err(ApiError::Validation(vec![validation::priority_error("priority")]))
+    }
+}
+
+struct StoredSession {
+    user_id: u32,
+    expires_at: DateTime<Utc>,
+}
+
+struct StoredInvite {
+    token_hash: String,
+    invite: ListInvite,
+}
+
+struct Tables {
+    users: Vec<(User, String)>,
+    sessions: HashMap<String, StoredSession>,
+    identities: HashMap<(String, String), u32>,
+    next_user_id: u32,
+    api_tokens: Vec<(ApiToken, String)>,
+    next_api_token_id: u32,
+    lists: Vec<TodoList>,
+    members: Vec<(u32, u32, Role)>,
+    invites: Vec<StoredInvite>,
+    todos: Vec<StoredTodo>,
+    archived: Vec<StoredTodo>,
+    tags: Vec<Tag>,
+    dependencies: Vec<Dependency>,
+    attachments: Vec<Attachment>,
+    next_list_id: u32,
+    next_todo_id: u32,
+    next_tag_id: u32,
+    next_attachment_id: u32,
+}
+
+impl Default for Tables {
+    /// Starts out like a freshly migrated database, with only the default list.
+    fn default() -> Self {
+        Tables {
+            users: Vec::new(),
+            sessions: HashMap::new(),
+            identities: HashMap::new(),
+            next_user_id: 0,
+            api_tokens: Vec::new(),
+            next_api_token_id: 0,
+            lists: vec![TodoList {
+                id: DEFAULT_LIST_ID,
+                owner_id: None,
+                name: "Default".to_string(),
+                is_default: true,
+            }],
+            members: Vec::new(),
+            invites: Vec::new(),
+            todos: Vec::new(),
+            archived: Vec::new(),
+            tags: Vec::new(),
+            dependencies: Vec::new(),
+            attachments: Vec::new(),
+            next_list_id: DEFAULT_LIST_ID,
+            next_todo_id: 0,
+            next_tag_id: 0,
+            next_attachment_id: 0,
+        }
+    }
+}
+
+impl Tables {
+    fn list_mut(&mut self, list_id: u32) -> Result<&mut TodoList, ApiError> {
+        self.lists.iter_mut()
+            .find(|l| l.id == list_id)
+            .ok_or_else(|| ApiError::list_not_found(list_id))
+    }
+
+    fn todo_position(&self, list_id: u32, todo_id: u64) -> Result<usize, ApiError> {
+        self.todos.iter()
+            .position(|t| t.list_id == list_id && t.id as u64 == todo_id)
+            .ok_or_else(|| ApiError::todo_not_found(todo_id))
+    }
+
+    /// Turns an active todo into a `Todo`, working out whether it is
+    /// blocked from the dependencies.
+    fn todo(&self, stored: &StoredTodo) -> Todo {
+        let blocked = self.dependencies.iter()
+            .filter(|dependency| dependency.todo_id == stored.id)
+            .any(|dependency| self.todos.iter().any(|todo| todo.id == dependency.blocker_id && !todo.completed));
+        Todo { blocked, ..stored.to_todo(&self.tags) }
+    }
+
+    /// Moves the todos named by `todo_ids`, parents before their subtasks,
+    /// to the archive under the ids they have, as archived at `archived_at`.
+    fn archive(&mut self, todo_ids: &[u32], archived_at: DateTime<Utc>) {
+        let archived_at = Some(archived_at);
+        for todo_id in todo_ids {
+            let index = self.todos.iter().position(|todo| todo.id == *todo_id).unwrap();
+            let todo = self.todos.remove(index);
+            let parent_id = todo.parent_id.filter(|parent_id| todo_ids.contains(parent_id));
+            for attachment in self.attachments.iter_mut().filter(|a| !a.archived && a.todo_id == todo.id) {
+                attachment.archived = true;
+            }
+            self.archived.push(StoredTodo { parent_id, archived_at, ..todo });
+        }
+        self.cascade_deletes();
+    }
+
+    /// Changes the todo with `update`, keeping `completed_at` in step and
+    /// creating the next occurrence of a recurring todo that this completes.
+    fn modify<F>(&mut self, list_id: u32, todo_id: u64, update: F) -> Result<Todo, ApiError>
+    where
+        F: FnOnce(&mut StoredTodo),
+    {
+        let index = self.todo_position(list_id, todo_id)?;
+        let mut updated = self.todos[index].clone();
+        update(&mut updated);
+        updated.completed_at = match (self.todos[index].completed, updated.completed) {
+            (_, false) => None,
+            (false, true) => Some(Utc::now()),
+            (true, true) => updated.completed_at,
+        };
+        check_priority_range(updated.priority)?;
+        let todo = self.todo(&updated);
+        if todo.completed && !updated.recurred
+            && let Some(next) = recurrence::next_occurrence(&todo, Utc::now())
+        {
+            self.next_todo_id += 1;
+            let id = self.next_todo_id;
+            self.todos.push(StoredTodo {
+                id,
+                completed: false,
+                completed_at: None,
+                due_at: next.due_at,
+                recurrence: next.recurrence,
+                recurred: false,
+                ..updated.clone()
+            });
+            updated.recurred = true;
+        }
+        self.todos[index] = updated;
+        Ok(todo)
+    }
+
+    /// Stands in for the `ON DELETE CASCADE` of `todo_dependencies`,
+    /// `attachments` and the `parent_id` of archived todos.
+    fn cascade_deletes(&mut self) {
+        while let Some(index) = self.archived.iter()
+            .position(|todo| todo.parent_id.is_some_and(|parent_id| !self.archived.iter().any(|parent| parent.id == parent_id)))
+        {
+            self.archived.remove(index);
+        }
+        let ids: Vec<u32> = self.todos.iter().map(|todo| todo.id).collect();
+        let archived_ids: Vec<u32> = self.archived.iter().map(|todo| todo.id).collect();
+        self.dependencies.retain(|dependency| ids.contains(&dependency.todo_id) && ids.contains(&dependency.blocker_id));
+        self.attachments.retain(|attachment| match attachment.archived {
+            false => ids.contains(&attachment.todo_id),
+            true => archived_ids.contains(&attachment.todo_id),
+        });
+    }
+}
+
+/// Applies the fields that are set in `update` and leaves the rest alone.
+fn apply_update(todo: &mut StoredTodo, update: &UpdateTodo) {
+    if let Some(title) = &update.title {
+        todo.title = title.clone();
+    }
+    if let Some(priority) = update.priority {
+        todo.priority = priority as i32;
+    }
+    if let Some(completed) = update.completed {
+        todo.completed = completed;
+    }
+    if let Some(due_at) = update.due_at {
+        todo.due_at = due_at;
+    }
+    if let Some(recurrence) = &update.recurrence {
+        todo.recurrence = recurrence.clone();
+    }
+    if let Some(parent_id) = update.parent_id {
+        todo.parent_id = parent_id;
+    }
+    if let Some(notes) = &update.notes {
+        todo.notes = notes.clone();
+    }
+}
+
+/// A `TodoStore` that lives in process memory. Ids are handed out like the
+/// SERIAL columns in Postgres and restored todos go back to their place,
+/// so `todos` stays in creation order and a higher id always means a todo
+/// that was created later.
+#[derive(Default)]
+pub struct InMemoryTodoListDao {
+    tables: Mutex<Tables>,
+}
+
+impl InMemoryTodoListDao {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn modify_todo<F>(&self, list_id: u32, todo_id: u64, update: F) -> Result<Todo, ApiError>
+    where
+        F: FnOnce(&mut StoredTodo),
+    {
+        self.tables.lock().unwrap().modify(list_id, todo_id, update)
+    }
+}
+
+#[async_trait]
+impl TodoStore for InMemoryTodoListDao {
+    async fn initialize(&self) {
+        *self.tables.lock().unwrap() = Tables::default();
+    }
+
+    async fn migrate(&self) -> Result<Vec<i64>, sqlx::Error> {
+        Ok(Vec::new())
+    }
+
+    async fn create_user(&self, email: &str, password_hash: &str) -> Result<User, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        if tables.users.iter().any(|(user, _)| user.email == email) {
+            return Err(ApiError::email_taken());
+        }
+        tables.next_user_id += 1;
+        let user = User { id: tables.next_user_id, email: email.to_string() };
+        tables.users.push((user.clone(), password_hash.to_string()));
+
+        for list in tables.lists.iter_mut().filter(|l| l.owner_id.is_none()) {
+            list.owner_id = Some(user.id);
+        }
+        if !tables.lists.iter().any(|l| l.owner_id == Some(user.id) && l.is_default) {
+            tables.next_list_id += 1;
+            let id = tables.next_list_id;
+            tables.lists.push(TodoList { id, owner_id: Some(user.id), name: "Default".to_string(), is_default: true });
+        }
+        let owned: Vec<u32> = tables.lists.iter().filter(|l| l.owner_id == Some(user.id)).map(|l| l.id).collect();
+        tables.members.extend(owned.into_iter().map(|list_id| (list_id, user.id, Role::Owner)));
+        Ok(user)
+    }
+
+    async fn query_credentials(&self, email: &str) -> Result<Option<(User, String)>, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.users.iter().find(|(user, _)| user.email == email).cloned())
+    }
+
+    async fn query_identity_user(&self, issuer: &str, subject: &str) -> Result<Option<User>, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.identities.get(&(issuer.to_string(), subject.to_string()))
+            .and_then(|user_id| tables.users.iter().find(|(user, _)| user.id == *user_id))
+            .map(|(user, _)| user.clone()))
+    }
+
+    async fn link_identity(&self, issuer: &str, subject: &str, user_id: u32) -> Result<(), ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let key = (issuer.to_string(), subject.to_string());
+        if tables.identities.contains_key(&key) {
+            return Err(ApiError::Conflict("The identity is already linked".to_string()));
+        }
+        tables.identities.insert(key, user_id);
+        Ok(())
+    }
+
+    async fn create_session(&self, token_hash: &str, user_id: u32, expires_at: DateTime<Utc>) -> Result<(), ApiError> {
+        let session = StoredSession { user_id, expires_at };
+        self.tables.lock().unwrap().sessions.insert(token_hash.to_string(), session);
+        Ok(())
+    }
+
+    async fn query_session_user(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<User>, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.sessions.get(token_hash)
+            .filter(|session| session.expires_at > now)
+            .and_then(|session| tables.users.iter().find(|(user, _)| user.id == session.user_id))
+            .map(|(user, _)| user.clone()))
+    }
+
+    async fn delete_session(&self, token_hash: &str) -> Result<(), ApiError> {
+        self.tables.lock().unwrap().sessions.remove(token_hash);
+        Ok(())
+    }
+
+    async fn create_api_token(&self, user_id: u32, name: &str, token_hash: &str, scopes: &[Scope], expires_at: DateTime<Utc>)
+        -> Result<ApiToken, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.next_api_token_id += 1;
+        let api_token = ApiToken {
+            id: tables.next_api_token_id,
+            user_id,
+            name: name.to_string(),
+            scopes: scopes.to_vec(),
+            created_at: Utc::now(),
+            expires_at,
+            last_used_at: None,
+        };
+        tables.api_tokens.push((api_token.clone(), token_hash.to_string()));
+        Ok(api_token)
+    }
+
+    async fn query_api_tokens(&self, user_id: u32) -> Result<Vec<ApiToken>, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.api_tokens.iter()
+            .rev()
+            .filter(|(api_token, _)| api_token.user_id == user_id)
+            .map(|(api_token, _)| api_token.clone())
+            .collect())
+    }
+
+    async fn use_api_token(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<(User, ApiToken)>, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let Some((api_token, _)) = tables.api_tokens.iter_mut()
+            .find(|(api_token, hash)| hash == token_hash && api_token.expires_at > now) else {
+            return Ok(None);
+        };
+        api_token.last_used_at = Some(now);
+        let api_token = api_token.clone();
+        let user = tables.users.iter()
+            .find(|(user, _)| user.id == api_token.user_id)
+            .map(|(user, _)| user.clone());
+        Ok(user.map(|user| (user, api_token)))
+    }
+
+    async fn delete_api_token(&self, user_id: u32, token_id: u32) -> Result<ApiToken, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let index = tables.api_tokens.iter()
+            .position(|(api_token, _)| api_token.id == token_id && api_token.user_id == user_id)
+            .ok_or_else(|| ApiError::api_token_not_found(token_id))?;
+        Ok(tables.api_tokens.remove(index).0)
+    }
+
+    async fn query_lists(&self, user_id: u32) -> Result<Vec<TodoList>, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        let mut lists: Vec<TodoList> = tables.lists.iter()
+            .filter(|l| tables.members.iter().any(|&(list_id, member, _)| list_id == l.id && member == user_id))
+            .cloned()
+            .collect();
+        lists.sort_by_key(|l| (!(l.is_default && l.owner_id == Some(user_id)), l.id));
+        Ok(lists)
+    }
+
+    async fn query_list_ids(&self) -> Result<Vec<u32>, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.lists.iter().map(|l| l.id).collect())
+    }
+
+    async fn query_default_list(&self, owner_id: u32) -> Result<TodoList, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        tables.lists.iter()
+            .find(|l| l.owner_id == Some(owner_id) && l.is_default)
+            .cloned()
+            .ok_or_else(|| ApiError::NotFound("Row not found".to_string()))
+    }
+
+    async fn query_list(&self, list_id: u32) -> Result<TodoList, ApiError> {
+        Ok(self.tables.lock().unwrap().list_mut(list_id)?.clone())
+    }
+
+    async fn create_list(&self, owner_id: u32, name: String) -> Result<TodoList, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.next_list_id += 1;
+        let list = TodoList { id: tables.next_list_id, owner_id: Some(owner_id), name, is_default: false };
+        tables.lists.push(list.clone());
+        tables.members.push((list.id, owner_id, Role::Owner));
+        Ok(list)
+    }
+
+    async fn rename_list(&self, list_id: u32, name: String) -> Result<TodoList, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let list = tables.list_mut(list_id)?;
+        list.name = name;
+        Ok(list.clone())
+    }
+
+    async fn delete_list(&self, list_id: u32) -> Result<TodoList, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let list = tables.list_mut(list_id)?.clone();
+        tables.lists.retain(|l| l.id != list_id);
+        tables.members.retain(|&(id, _, _)| id != list_id);
+        tables.invites.retain(|stored| stored.invite.list_id != list_id);
+        tables.todos.retain(|t| t.list_id != list_id);
+        tables.cascade_deletes();
+        tables.archived.retain(|t| t.list_id != list_id);
+        tables.tags.retain(|tag| tag.list_id != list_id);
+        Ok(list)
+    }
+
+    async fn query_member_role(&self, list_id: u32, user_id: u32) -> Result<Option<Role>, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.members.iter()
+            .find(|&&(id, member, _)| id == list_id && member == user_id)
+            .map(|&(_, _, role)| role))
+    }
+
+    async fn query_members(&self, list_id: u32) -> Result<Vec<ListMember>, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        let mut members: Vec<ListMember> = tables.members.iter()
+            .filter(|&&(id, _, _)| id == list_id)
+            .filter_map(|&(_, user_id, role)| {
+                let (user, _) = tables.users.iter().find(|(user, _)| user.id == user_id)?;
+                Some(ListMember { list_id, user_id, email: user.email.clone(), role })
+            })
+            .collect();
+        members.sort_by_key(|member| member.user_id);
+        Ok(members)
+    }
+
+    async fn create_invite(&self, list_id: u32, email: &str, role: Role, token_hash: &str, expires_at: DateTime<Utc>)
+        -> Result<ListInvite, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.list_mut(list_id)?;
+        let invite = ListInvite { list_id, email: email.to_string(), role, expires_at };
+        tables.invites.push(StoredInvite { token_hash: token_hash.to_string(), invite: invite.clone() });
+        Ok(invite)
+    }
+
+    async fn accept_invite(&self, token_hash: &str, user: &User, now: DateTime<Utc>) -> Result<ListMember, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let index = tables.invites.iter()
+            .position(|stored| stored.token_hash == token_hash && stored.invite.email == user.email && stored.invite.expires_at > now)
+            .ok_or_else(ApiError::invite_not_found)?;
+        let invite = tables.invites.remove(index).invite;
+        let list_id = invite.list_id;
+        let role = match tables.members.iter_mut().find(|(id, member, _)| *id == list_id && *member == user.id) {
+            Some((_, _, role)) => {
+                *role = (*role).max(invite.role);
+                *role
+            }
+            None => {
+                tables.members.push((list_id, user.id, invite.role));
+                invite.role
+            }
+        };
+        Ok(ListMember { list_id, user_id: user.id, email: user.email.clone(), role })
+    }
+
+    async fn truncate_todos_table(&self, list_id: u32) -> Result<&'static str, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.todos.retain(|t| t.list_id != list_id);
+        tables.cascade_deletes();
+        Ok("All tables truncated successfully")
+    }
+
+    async fn query_todo_page(&self, list_id: u32, query: &TodoQuery) -> Result<TodoPage, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        let mut todos: Vec<Todo> = tables.todos.iter()
+            .filter(|t| t.list_id == list_id)
+            .map(|t| tables.todo(t))
+            .filter(|todo| query.matches(todo))
+            .collect();
+        query.sort(&mut todos);
+        if let Some(limit) = query.fetch_limit() {
+            todos.truncate(limit as usize);
+        }
+        Ok(TodoPage::from_rows(todos, query))
+    }
+
+    async fn query_archived_todos(&self, list_id: u32) -> Result<Vec<Todo>, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.archived.iter()
+            .rev()
+            .filter(|t| t.list_id == list_id)
+            .map(|t| t.to_todo(&tables.tags))
+            .collect())
+    }
+
+    async fn query_todo(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        let index = tables.todo_position(list_id, todo_id)?;
+        Ok(tables.todo(&tables.todos[index]))
+    }
+
+    async fn save_todo(&self, todo: &Todo) -> Result<Todo, ApiError> {
+        check_priority_range(todo.priority as i32)?;
+        let mut tables = self.tables.lock().unwrap();
+        tables.list_mut(todo.list_id)?;
+        tables.next_todo_id += 1;
+        let stored = StoredTodo {
+            id: tables.next_todo_id,
+            list_id: todo.list_id,
+            title: todo.title.clone(),
+            priority: todo.priority as i32,
+            completed: todo.completed,
+            completed_at: todo.completed.then(Utc::now),
+            due_at: todo.due_at,
+            recurrence: todo.recurrence.clone(),
+            recurred: false,
+            parent_id: todo.parent_id,
+            notes: todo.notes.clone(),
+            tag_ids: Vec::new(),
+            archived_at: None,
+        };
+        tables.todos.push(stored.clone());
+        Ok(tables.todo(&stored))
+    }
+
+    async fn archive_completed_todos(&self, list_id: u32) -> Result<u64, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let todos: Vec<Todo> = tables.todos.iter()
+            .filter(|todo| todo.list_id == list_id)
+            .map(|todo| tables.todo(todo))
+            .collect();
+        let todo_ids: Vec<u32> = subtasks::archivable(&todos).iter().map(|todo| todo.id).collect();
+        tables.archive(&todo_ids, Utc::now());
+        Ok(todo_ids.len() as u64)
+    }
+
+    async fn archive_todo(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.todo_position(list_id, todo_id)?;
+        let todos: Vec<Todo> = tables.todos.iter()
+            .filter(|todo| todo.list_id == list_id)
+            .map(|todo| tables.todo(todo))
+            .collect();
+        let todo_ids: Vec<u32> = subtasks::subtree(&todos, todo_id as u32).iter().map(|todo| todo.id).collect();
+        tables.archive(&todo_ids, Utc::now());
+        let archived = tables.archived.iter().find(|todo| todo.id as u64 == todo_id).unwrap();
+        Ok(archived.to_todo(&tables.tags))
+    }
+
+    async fn archive_completed_before(&self, list_id: u32, completed_before: DateTime<Utc>, now: DateTime<Utc>)
+        -> Result<u64, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let todos: Vec<Todo> = tables.todos.iter()
+            .filter(|todo| todo.list_id == list_id)
+            .map(|todo| tables.todo(todo))
+            .collect();
+        let todo_ids: Vec<u32> = subtasks::archivable_before(&todos, completed_before).iter().map(|todo| todo.id).collect();
+        tables.archive(&todo_ids, now);
+        Ok(todo_ids.len() as u64)
+    }
+
+    async fn query_archived_before(&self, list_id: u32, archived_before: DateTime<Utc>) -> Result<Vec<Todo>, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.archived.iter()
+            .filter(|t| t.list_id == list_id && t.archived_at.is_some_and(|at| at < archived_before))
+            .map(|t| t.to_todo(&tables.tags))
+            .collect())
+    }
+
+    async fn purge_archived_before(&self, list_id: u32, archived_before: DateTime<Utc>) -> Result<u64, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let before = tables.archived.len();
+        tables.archived.retain(|t| t.list_id != list_id || t.archived_at.is_none_or(|at| at >= archived_before));
+        tables.cascade_deletes();
+        Ok((before - tables.archived.len()) as u64)
+    }
+
+    async fn restore_archived_todos(&self, list_id: u32, archived_ids: &[u32], reset_completed: bool)
+        -> Result<Vec<Todo>, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let archived: Vec<Todo> = tables.archived.iter()
+            .filter(|todo| todo.list_id == list_id)
+            .map(|todo| todo.to_todo(&tables.tags))
+            .collect();
+        let restored_ids: Vec<u32> = subtasks::restorable(&archived, archived_ids)
+            .map_err(ApiError::archived_todo_not_found)?
+            .iter()
+            .map(|todo| todo.id)
+            .collect();
+
+        for (position, archived_id) in restored_ids.iter().enumerate() {
+            let index = tables.archived.iter().position(|todo| todo.id == *archived_id).unwrap();
+            let todo = tables.archived.remove(index);
+            let parent_id = todo.parent_id.filter(|parent_id| restored_ids[..position].contains(parent_id));
+            let completed = todo.completed && !reset_completed;
+            for attachment in tables.attachments.iter_mut().filter(|a| a.archived && a.todo_id == todo.id) {
+                attachment.archived = false;
+            }
+            let completed_at = todo.completed_at.filter(|_| completed);
+            let index = tables.todos.partition_point(|active| active.id < todo.id);
+            tables.todos.insert(index, StoredTodo {
+                parent_id, completed, completed_at, recurred: todo.completed, archived_at: None, ..todo
+            });
+        }
+        tables.cascade_deletes();
+        let restored: Vec<Todo> = tables.todos.iter()
+            .filter(|todo| restored_ids.contains(&todo.id))
+            .map(|todo| tables.todo(todo))
+            .collect();
+        Ok(restored)
+    }
+
+    async fn query_tags(&self, list_id: u32) -> Result<Vec<Tag>, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        let mut tags: Vec<Tag> = tables.tags.iter().filter(|tag| tag.list_id == list_id).cloned().collect();
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(tags)
+    }
+
+    async fn add_todo_tag(&self, list_id: u32, todo_id: u64, name: &str, color: Option<&str>) -> Result<Todo, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let index = tables.todo_position(list_id, todo_id)?;
+        let tag_id = match tables.tags.iter_mut().find(|tag| tag.list_id == list_id && tag.name == name) {
+            Some(tag) => {
+                if let Some(color) = color {
+                    tag.color = color.to_string();
+                }
+                tag.id
+            }
+            None => {
+                tables.next_tag_id += 1;
+                let id = tables.next_tag_id;
+                let color = color.unwrap_or(DEFAULT_TAG_COLOR).to_string();
+                tables.tags.push(Tag { id, list_id, name: name.to_string(), color });
+                id
+            }
+        };
+        let todo = &mut tables.todos[index];
+        if !todo.tag_ids.contains(&tag_id) {
+            todo.tag_ids.push(tag_id);
+        }
+        Ok(tables.todo(&tables.todos[index]))
+    }
+
+    async fn remove_todo_tag(&self, list_id: u32, todo_id: u64, name: &str) -> Result<Todo, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let index = tables.todo_position(list_id, todo_id)?;
+        let tag_id = tables.tags.iter()
+            .find(|tag| tag.list_id == list_id && tag.name == name)
+            .map(|tag| tag.id)
+            .filter(|id| tables.todos[index].tag_ids.contains(id))
+            .ok_or_else(|| ApiError::tag_not_found(todo_id, name))?;
+        tables.todos[index].tag_ids.retain(|id| *id != tag_id);
+        Ok(tables.todo(&tables.todos[index]))
+    }
+
+    async fn query_dependencies(&self, list_id: u32) -> Result<Vec<Dependency>, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        let mut dependencies: Vec<Dependency> = tables.dependencies.iter()
+            .filter(|dependency| tables.todos.iter().any(|todo| todo.id == dependency.todo_id && todo.list_id == list_id))
+            .copied()
+            .collect();
+        dependencies.sort_by_key(|dependency| (dependency.todo_id, dependency.blocker_id));
+        Ok(dependencies)
+    }
+
+    async fn add_dependency(&self, list_id: u32, todo_id: u64, blocker_id: u64) -> Result<Todo, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let index = tables.todo_position(list_id, todo_id)?;
+        let todos: Vec<Todo> = tables.todos.iter()
+            .filter(|todo| todo.list_id == list_id)
+            .map(|todo| tables.todo(todo))
+            .collect();
+        dependencies::check_blocker(&todos, &tables.dependencies, todo_id as u32, blocker_id as u32)
+            .map_err(|error| ApiError::Validation(vec![error]))?;
+        let dependency = Dependency { todo_id: todo_id as u32, blocker_id: blocker_id as u32 };
+        if !tables.dependencies.contains(&dependency) {
+            tables.dependencies.push(dependency);
+        }
+        Ok(tables.todo(&tables.todos[index]))
+    }
+
+    async fn remove_dependency(&self, list_id: u32, todo_id: u64, blocker_id: u64) -> Result<Todo, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let index = tables.todo_position(list_id, todo_id)?;
+        let dependency = Dependency { todo_id: todo_id as u32, blocker_id: blocker_id as u32 };
+        let position = tables.dependencies.iter()
+            .position(|stored| *stored == dependency)
+            .ok_or_else(|| ApiError::dependency_not_found(todo_id, blocker_id))?;
+        tables.dependencies.remove(position);
+        Ok(tables.todo(&tables.todos[index]))
+    }
+
+    async fn query_attachments(&self, list_id: u32, todo_id: u64) -> Result<Vec<Attachment>, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        tables.todo_position(list_id, todo_id)?;
+        Ok(tables.attachments.iter()
+            .filter(|attachment| !attachment.archived && attachment.todo_id as u64 == todo_id)
+            .cloned()
+            .collect())
+    }
+
+    async fn query_list_attachments(&self, list_id: u32) -> Result<Vec<Attachment>, ApiError> {
+        let tables = self.tables.lock().unwrap();
+        let owners = |attachment: &Attachment| match attachment.archived {
+            false => &tables.todos,
+            true => &tables.archived,
+        };
+        Ok(tables.attachments.iter()
+            .filter(|attachment| owners(attachment).iter().any(|todo| todo.id == attachment.todo_id && todo.list_id == list_id))
+            .cloned()
+            .collect())
+    }
+
+    async fn save_attachment(&self, list_id: u32, attachment: &Attachment) -> Result<Attachment, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.todo_position(list_id, attachment.todo_id as u64)?;
+        tables.next_attachment_id += 1;
+        let saved = Attachment { id: tables.next_attachment_id, archived: false, ..attachment.clone() };
+        tables.attachments.push(saved.clone());
+        Ok(saved)
+    }
+
+    async fn query_attachment(&self, list_id: u32, todo_id: u64, attachment_id: u32) -> Result<Attachment, ApiError> {
+        self.query_attachments(list_id, todo_id).await?
+            .into_iter()
+            .find(|attachment| attachment.id == attachment_id)
+            .ok_or_else(|| ApiError::attachment_not_found(attachment_id))
+    }
+
+    async fn delete_attachment(&self, list_id: u32, todo_id: u64, attachment_id: u32) -> Result<Attachment, ApiError> {
+        let attachment = self.query_attachment(list_id, todo_id, attachment_id).await?;
+        self.tables.lock().unwrap().attachments.retain(|stored| stored.id != attachment_id);
+        Ok(attachment)
+    }
+
+    async fn rename_todo(&self, list_id: u32, todo_id: u64, new_title: String) -> Result<Todo, ApiError> {
+        self.modify_todo(list_id, todo_id, |todo| todo.title = new_title)
+    }
+
+    async fn delete_todo(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let index = tables.todo_position(list_id, todo_id)?;
+        let deleted = tables.todo(&tables.todos[index]);
+        tables.todos.remove(index);
+        // Stands in for the `ON DELETE CASCADE` of `parent_id`.
+        let todos: Vec<Todo> = tables.todos.iter().map(|todo| tables.todo(todo)).collect();
+        let subtasks = subtasks::descendants(&todos, deleted.id);
+        tables.todos.retain(|todo| !subtasks.contains(&todo.id));
+        tables.cascade_deletes();
+        Ok(deleted)
+    }
+
+    async fn update_todo(&self, list_id: u32, todo_id: u64, update: &UpdateTodo) -> Result<Todo, ApiError> {
+        self.modify_todo(list_id, todo_id, |todo| apply_update(todo, update))
+    }
+
+    async fn update_todo_checked(&self, list_id: u32, todo_id: u64, update: &UpdateTodo, cascade: bool)
+        -> Result<Todo, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let todos: Vec<Todo> = tables.todos.iter()
+            .filter(|todo| todo.list_id == list_id)
+            .map(|todo| tables.todo(todo))
+            .collect();
+        let subtasks = subtasks::check_update(&todos, todo_id as u32, update, cascade)?;
+        let todo = tables.modify(list_id, todo_id, |todo| apply_update(todo, update))?;
+        for subtask in subtasks {
+            tables.modify(list_id, subtask as u64, |todo| todo.completed = true)?;
+        }
+        Ok(todo)
+    }
+
+    async fn toggle_todo_completion(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError> {
+        let mut tables = self.tables.lock().unwrap();
+        let todos: Vec<Todo> = tables.todos.iter()
+            .filter(|todo| todo.list_id == list_id)
+            .map(|todo| tables.todo(todo))
+            .collect();
+        let completed = todos.iter().any(|todo| todo.id == todo_id as u32 && !todo.completed);
+        let update = UpdateTodo { completed: Some(completed), ..UpdateTodo::default() };
+        subtasks::check_update(&todos, todo_id as u32, &update, false)?;
+        tables.modify(list_id, todo_id, |todo| apply_update(todo, &update))
+    }
+
+    async fn increase_todo_priority(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError> {
+        self.modify_todo(list_id, todo_id, |todo| todo.priority += 1)
+    }
+
+    async fn decrease_todo_priority(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError> {
+        self.modify_todo(list_id, todo_id, |todo| todo.priority -= 1)
+    }
+}
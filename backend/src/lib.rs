@@ -1,15 +1,56 @@
 use axum::{
-    routing::{get, post},
+    async_trait,
+    routing::{delete, get, post, MethodRouter},
     Router,
-    extract::{Json, Extension},
-    http::{StatusCode, Method, header},
+    extract::{DefaultBodyLimit, FromRequest, FromRequestParts, Json, Extension, Multipart, Path, RawPathParams, Request},
+    extract::{multipart::MultipartRejection, path::ErrorKind, rejection::PathRejection},
+    http::{request::Parts, StatusCode, Method, header, HeaderMap, HeaderName, HeaderValue},
+    middleware,
+    response::{IntoResponse, Redirect, Response},
+    body::Body,
 };
-use serde::{Deserialize, Serialize};
-use tower_http::cors::{Any, CorsLayer};
-use sqlx::Row;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use chrono::{DateTime, Utc};
+use tower_http::cors::CorsLayer;
+use tokio_util::io::ReaderStream;
 use std::sync::Arc;
 
+pub mod api_token;
+pub mod attachments;
+pub mod auth;
+pub mod dependencies;
+pub mod error;
+pub mod in_memory_todo_list_dao;
+pub mod membership;
+pub mod migrations;
+pub mod notes;
+pub mod oidc;
+pub mod recurrence;
+pub mod retention;
+pub mod search;
+pub mod sqlite_todo_list_dao;
+pub mod subtasks;
+pub mod tags;
 pub mod todo_list_dao;
+pub mod todo_query;
+pub mod todo_store;
+pub mod validation;
+
+use api_token::{ApiToken, CreateApiToken, CreatedApiToken, DEFAULT_TOKEN_TTL_DAYS};
+use attachments::{Attachment, AttachmentConfig, AttachmentPath};
+use auth::{AuthUser, Credentials, User};
+use dependencies::{BlockerPath, BlockerPayload, DependencyGraph};
+use error::{ApiError, FieldError};
+use membership::{AcceptInvite, CreatedInvite, InvitePayload, ListMember, Role};
+use notes::RenderedNotes;
+use oidc::{OidcClient, OidcConfig, PendingLogin};
+use retention::{Clock, RetentionConfig, RetentionReport, SystemClock};
+use search::{SearchHit, SearchParams, SearchQuery};
+use subtasks::{TodoNode, UpdateParams};
+use tags::{Tag, TagPayload, TodoTagPath};
+use todo_query::{ListTodosParams, TodoPage, TodoQuery};
+use todo_store::TodoStore;
+use validation::{ValidationRules, DEFAULT_PRIORITY};
 
 #[derive(Serialize)]
 pub struct Message {
@@ -20,6 +61,16 @@ pub struct Message {
 pub struct CreateTodo{
     pub title: String,
     pub priority: Option<u8>,
+    pub due_at: Option<DateTime<Utc>>,
+    pub recurrence: Option<String>,
+    pub parent_id: Option<u32>,
+    pub notes: Option<String>,
+}
+
+/// Body of `POST /api/lists` and `PATCH /api/lists/:list_id`.
+#[derive(Deserialize)]
+pub struct ListPayload {
+    pub name: String,
 }
 
 #[derive(Deserialize)]
@@ -27,39 +78,357 @@ pub struct IdPayload {
     pub id: u32,
 }
 
+/// Body of `POST /api/todos/restore`.
+#[derive(Deserialize)]
+pub struct RestorePayload {
+    pub ids: Vec<u32>,
+    /// Brings the todos back as open todos.
+    #[serde(default)]
+    pub reset_completed: bool,
+}
+
 #[derive(Deserialize)]
 pub struct RenamePayload {
     pub id: u32,
     pub new_title: String,
 }
 
-#[derive(Serialize, Debug)]
+/// Body of `PATCH /api/todos/:id`. Fields that are left out keep their
+/// current value.
+#[derive(Deserialize, Default)]
+pub struct UpdateTodo {
+    pub title: Option<String>,
+    pub priority: Option<u8>,
+    pub completed: Option<bool>,
+    /// `null` removes the due date.
+    #[serde(default, deserialize_with = "present")]
+    pub due_at: Option<Option<DateTime<Utc>>>,
+    /// `null` stops the todo from recurring.
+    #[serde(default, deserialize_with = "present")]
+    pub recurrence: Option<Option<String>>,
+    /// `null` turns a subtask into a top-level todo.
+    #[serde(default, deserialize_with = "present")]
+    pub parent_id: Option<Option<u32>>,
+    /// `null` removes the notes.
+    #[serde(default, deserialize_with = "present")]
+    pub notes: Option<Option<String>>,
+}
+
+/// Tells a field that is `null` apart from one that is left out, which
+/// `Option<Option<_>>` alone cannot.
+fn present<'de, T: Deserialize<'de>, D: Deserializer<'de>>(deserializer: D) -> Result<Option<T>, D::Error> {
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Query string of `GET /api/auth/oidc/callback`. Providers send either a
+/// code and the state or an error.
+#[derive(Deserialize)]
+pub struct OidcCallback {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Path of `DELETE /api/tokens/:id`.
+#[derive(Deserialize)]
+pub struct ApiTokenPath {
+    pub id: u32,
+}
+
+/// Path of the routes under `/api/todos/:id`. The list id, if any, is
+/// picked up separately by `ListId`.
+#[derive(Deserialize)]
+pub struct TodoPath {
+    pub id: u32,
+}
+
+#[derive(Serialize, Debug, Clone)]
 pub struct Todo {
     pub id: u32,
+    pub list_id: u32,
     pub title: String,
     pub priority: u8,
     pub completed: bool,
+    /// When the todo was completed, kept in the archive. Set by the store
+    /// and cleared when the todo is reopened.
+    pub completed_at: Option<DateTime<Utc>>,
+    pub due_at: Option<DateTime<Utc>>,
+    /// See `recurrence::Recurrence`. Completing a recurring todo creates
+    /// its next occurrence.
+    pub recurrence: Option<String>,
+    /// The todo this one is a subtask of.
+    pub parent_id: Option<u32>,
+    /// Markdown; `GET /api/todos/:id/notes` renders it to HTML.
+    pub notes: Option<String>,
+    /// Sorted by name.
+    pub tags: Vec<Tag>,
+    /// Whether an open todo blocks this one from being completed, see
+    /// `dependencies::Dependency`. Never set in the archive.
+    pub blocked: bool,
+}
+
+/// The list that migration 0005 creates for the todos that predate lists.
+/// It becomes the default list of the first account that registers.
+pub const DEFAULT_LIST_ID: u32 = 1;
+
+/// A named list of todos. Lists created before accounts existed have no
+/// owner until the first account registers.
+#[derive(Serialize, Debug, Clone)]
+pub struct TodoList {
+    pub id: u32,
+    pub owner_id: Option<u32>,
+    pub name: String,
+    /// Backs the `/api/todos` routes of its owner and cannot be deleted.
+    pub is_default: bool,
 }
-pub fn build_app(db: Arc<todo_list_dao::TodoListDao>) -> Router {
+
+pub fn build_app(db: Arc<dyn TodoStore>) -> Router {
+    build_app_with_rules(db, ValidationRules::default())
+}
+
+pub fn build_app_with_rules(db: Arc<dyn TodoStore>, rules: ValidationRules) -> Router {
+    build_app_with_oidc(db, rules, None)
+}
+
+/// Like `build_app_with_rules`, with login through an OpenID Connect
+/// provider when `oidc` is given.
+pub fn build_app_with_oidc(db: Arc<dyn TodoStore>, rules: ValidationRules, oidc: Option<OidcConfig>) -> Router {
+    build_app_with_attachments(db, rules, oidc, AttachmentConfig::default())
+}
+
+/// Like `build_app_with_oidc`, keeping attachment files as `attachments`
+/// says.
+pub fn build_app_with_attachments(db: Arc<dyn TodoStore>, rules: ValidationRules, oidc: Option<OidcConfig>,
+    attachments: AttachmentConfig) -> Router {
+    build_app_with_retention(db, rules, oidc, attachments, RetentionConfig::default(), Arc::new(SystemClock))
+}
+
+/// Like `build_app_with_attachments`, reporting on the `retention` rules
+/// as of the time `clock` tells. The rules themselves are applied by
+/// `retention::spawn`, not by the router.
+pub fn build_app_with_retention(db: Arc<dyn TodoStore>, rules: ValidationRules, oidc: Option<OidcConfig>,
+    attachments: AttachmentConfig, retention: RetentionConfig, clock: Arc<dyn Clock>) -> Router {
+    let oidc = oidc.map(|config| Arc::new(OidcClient::new(config)));
     let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST])
-        .allow_headers([header::CONTENT_TYPE])
-        .allow_origin(Any);
+        .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
+        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION])
+        .expose_headers([HeaderName::from_static(NEXT_CURSOR_HEADER)])
+        .allow_origin(frontend_origin())
+        .allow_credentials(true);
 
     Router::new()
         .route("/", get(root))
-        .route("/api/todos", get(list_todos).post(create_todo))
-        .route("/api/todos/complete", get(list_completed_todos).post(toggle_todo_completion))
-        .route("/api/todos/delete", post(delete_todo))
-        .route("/api/todos/increase_priority", post(increase_todo_priority))
-        .route("/api/todos/decrease_priority", post(decrease_todo_priority))
-        .route("/api/todos/clear", post(clear_todo_list))
-        .route("/api/todos/archive_completed", post(archive_completed_todos))
-        .route("/api/todos/rename", post(rename_todo))
+        .route("/api/auth/register", post(register))
+        .route("/api/auth/login", post(login))
+        .route("/api/auth/logout", post(logout))
+        .route("/api/auth/me", get(current_user))
+        .route("/api/auth/oidc/login", get(oidc_login))
+        .route("/api/auth/oidc/callback", get(oidc_callback))
+        .route("/api/tokens", get(list_api_tokens).post(create_api_token))
+        .route("/api/tokens/:id", delete(revoke_api_token))
+        .nest("/api/todos", todo_routes())
+        .route("/api/search", get(search_todos))
+        .route("/api/lists", get(list_lists).post(create_list))
+        .route("/api/lists/:list_id", get(get_list).patch(rename_list).delete(delete_list))
+        .route("/api/lists/:list_id/members", get(list_members))
+        .route("/api/lists/:list_id/invites", post(invite_member))
+        .route("/api/invites/accept", post(accept_invite))
+        .nest("/api/lists/:list_id/todos", todo_routes())
+        .route("/api/lists/:list_id/search", get(search_todos))
+        .route("/api/tags", get(list_tags))
+        .route("/api/lists/:list_id/tags", get(list_tags))
+        .layer(middleware::from_fn(api_token::bearer_auth))
         .layer(Extension(db))
+        .layer(Extension(rules))
+        .layer(Extension(oidc))
+        .layer(Extension(attachments))
+        .layer(Extension(retention))
+        .layer(Extension(clock))
         .layer(cors)
 }
 
+/// Session cookies are only sent along with cross-origin requests from an
+/// origin that CORS names explicitly, which is `FRONTEND_ORIGIN`.
+fn frontend_origin() -> HeaderValue {
+    let origin = std::env::var("FRONTEND_ORIGIN").unwrap_or_else(|_| "http://localhost:3000".to_string());
+    HeaderValue::from_str(&origin).expect("FRONTEND_ORIGIN is not a valid header value")
+}
+
+/// The todo routes of a single list. They are mounted at `/api/todos` for
+/// the default list and at `/api/lists/:list_id/todos` for every list.
+fn todo_routes() -> Router {
+    Router::new()
+        .route("/", get(list_todos).post(create_todo))
+        .route("/:id", get(get_todo).patch(update_todo).delete(delete_todo_by_id))
+        .route("/:id/notes", get(get_todo_notes))
+        .route("/:id/archive", post(archive_todo))
+        .route("/:id/tags", post(add_todo_tag))
+        .route("/:id/tags/:name", delete(remove_todo_tag))
+        .route("/:id/blockers", post(add_blocker))
+        .route("/:id/blockers/:blocker_id", delete(remove_blocker))
+        .route("/:id/attachments", get(list_attachments).post(upload_attachment).layer(DefaultBodyLimit::disable()))
+        .route("/:id/attachments/:attachment_id", get(download_attachment).delete(delete_attachment))
+        .route("/overdue", get(list_overdue_todos))
+        .route("/tree", get(todo_tree))
+        .route("/graph", get(dependency_graph))
+        .route("/complete", get(list_completed_todos).merge(deprecated(post(toggle_todo_completion))))
+        .route("/delete", deprecated(post(delete_todo)))
+        .route("/increase_priority", deprecated(post(increase_todo_priority)))
+        .route("/decrease_priority", deprecated(post(decrease_todo_priority)))
+        .route("/clear", post(clear_todo_list))
+        .route("/archive_completed", post(archive_completed_todos))
+        .route("/archived", get(list_archived_todos))
+        .route("/archived/:id", get(get_archived_todo))
+        .route("/restore", post(restore_archived_todos))
+        .route("/retention", get(retention_report))
+        .route("/rename", deprecated(post(rename_todo)))
+}
+
+/// The list a request works on: the `:list_id` of the routes under
+/// `/api/lists/:list_id`, or the caller's default list everywhere else.
+/// Lists that do not exist or that the caller is no member of are rejected
+/// with 404. `ListId` lets any member in; `EditableListId` and
+/// `OwnedListId` answer 403 to members whose role is too low.
+pub struct ListId(pub u32);
+
+pub struct EditableListId(pub u32);
+
+pub struct OwnedListId(pub u32);
+
+async fn list_with_role<S: Send + Sync>(parts: &mut Parts, state: &S, needed: Role) -> Result<u32, ApiError> {
+    let AuthUser(user) = AuthUser::from_request_parts(parts, state).await?;
+    let db = parts.extensions.get::<Arc<dyn TodoStore>>()
+        .cloned()
+        .expect("the todo store extension is missing");
+
+    let params = RawPathParams::from_request_parts(parts, state).await.ok();
+    let raw = params.iter()
+        .flat_map(|params| params.iter())
+        .find(|(name, _)| *name == "list_id")
+        .map(|(_, value)| value.to_string());
+    let list_id = match raw {
+        None => db.query_default_list(user.id).await?.id,
+        Some(raw) => raw.parse::<u32>()
+            .map_err(|_| ApiError::Validation(vec![FieldError::new("list_id", "must be a list id")]))?,
+    };
+
+    match db.query_member_role(list_id, user.id).await? {
+        None => Err(ApiError::list_not_found(list_id)),
+        Some(role) if role < needed => {
+            Err(ApiError::Forbidden(format!("This needs the {} role on list {}", needed, list_id)))
+        }
+        Some(_) => Ok(list_id),
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ListId {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, ApiError> {
+        list_with_role(parts, state, Role::Viewer).await.map(ListId)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for EditableListId {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, ApiError> {
+        list_with_role(parts, state, Role::Editor).await.map(EditableListId)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for OwnedListId {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, ApiError> {
+        list_with_role(parts, state, Role::Owner).await.map(OwnedListId)
+    }
+}
+
+/// `Json` for request bodies, rejecting them with an `ApiError` that names
+/// the field that did not deserialize.
+pub struct ApiJson<T>(pub T);
+
+/// `Query` with the rejections of `ApiJson`.
+pub struct ApiQuery<T>(pub T);
+
+/// `Path` with its rejections answered as `ApiError`s, naming the segment
+/// that did not parse.
+pub struct ApiPath<T>(pub T);
+
+#[async_trait]
+impl<S: Send + Sync, T: DeserializeOwned> FromRequest<S> for ApiJson<T> {
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, ApiError> {
+        let Json(value) = Json::<serde_json::Value>::from_request(request, state).await
+            .map_err(|rejection| ApiError::Validation(vec![FieldError::new("body", rejection.body_text())]))?;
+        serde_path_to_error::deserialize(value).map(ApiJson).map_err(field_error)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync, T: DeserializeOwned> FromRequestParts<S> for ApiQuery<T> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, ApiError> {
+        let query = parts.uri.query().unwrap_or_default();
+        let deserializer = serde_urlencoded::Deserializer::new(form_urlencoded::parse(query.as_bytes()));
+        serde_path_to_error::deserialize(deserializer).map(ApiQuery).map_err(field_error)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync, T: DeserializeOwned + Send> FromRequestParts<S> for ApiPath<T> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, ApiError> {
+        Path::<T>::from_request_parts(parts, state).await.map(|Path(value)| ApiPath(value)).map_err(path_error)
+    }
+}
+
+fn path_error(rejection: PathRejection) -> ApiError {
+    let field = match &rejection {
+        PathRejection::FailedToDeserializePathParams(error) => match error.kind() {
+            ErrorKind::ParseErrorAtKey { key, .. }
+            | ErrorKind::InvalidUtf8InPathParam { key } => key.clone(),
+            _ => String::new(),
+        },
+        _ => String::new(),
+    };
+    ApiError::Validation(vec![FieldError::new(&field, rejection.body_text())])
+}
+
+/// The field serde stopped at, or the one it found missing, with its reason.
+fn field_error<E: std::fmt::Display>(error: serde_path_to_error::Error<E>) -> ApiError {
+    let path = match error.path().to_string() {
+        root if root == "." => String::new(),
+        path => path,
+    };
+    let message = error.inner().to_string();
+    let missing = message.strip_prefix("missing field `").and_then(|rest| rest.strip_suffix('`'));
+    let error = match missing {
+        Some(field) if path.is_empty() => FieldError::new(field, "is required"),
+        Some(field) => FieldError::new(&format!("{}.{}", path, field), "is required"),
+        None => FieldError::new(&path, message),
+    };
+    ApiError::Validation(vec![error])
+}
+
+/// Marks the verb-style routes that predate `/api/todos/:id`. They keep
+/// working for the current frontend but answer with a `Deprecation` header.
+fn deprecated(route: MethodRouter) -> MethodRouter {
+    route.layer(middleware::map_response(mark_deprecated))
+}
+
+async fn mark_deprecated(mut response: Response) -> Response {
+    response.headers_mut().insert("deprecation", HeaderValue::from_static("true"));
+    response
+}
+
 pub async fn root() -> Json<Message> {
     Json(Message {
         text: "Hello from backend!".to_string(),
@@ -67,176 +436,680 @@ pub async fn root() -> Json<Message> {
 }
 
 pub async fn create_todo(Extension(
-    db): Extension<Arc<todo_list_dao::TodoListDao>>,
-    Json(payload): Json<CreateTodo>) 
-    -> (StatusCode, Json<Todo>) {
-    let priority = payload.priority.unwrap_or(1u8);
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(rules): Extension<ValidationRules>,
+    EditableListId(list_id): EditableListId,
+    ApiJson(payload): ApiJson<CreateTodo>) 
+    -> Result<(StatusCode, Json<Todo>), ApiError> {
+    let mut validator = rules.validator();
+    let title = validator.title("title", &payload.title);
+    let priority = validator.priority("priority", payload.priority.unwrap_or(DEFAULT_PRIORITY));
+    let recurrence = payload.recurrence.as_deref().map(|rule| validator.recurrence("recurrence", rule));
+    let notes = payload.notes.as_deref().and_then(|notes| validator.notes("notes", notes));
+    validator.finish()?;
+    if let Some(parent_id) = payload.parent_id {
+        check_parent(&db, list_id, None, parent_id).await?;
+    }
+
     let new = Todo {
         id: 0,
-        title: payload.title,
+        list_id,
+        title,
         priority,
         completed: false,
+        completed_at: None,
+        due_at: payload.due_at,
+        recurrence,
+        parent_id: payload.parent_id,
+        notes,
+        tags: Vec::new(),
+        blocked: false,
     };
 
-    match db.save_todo(&new).await {
-        Ok(id) => {
-            let todo = Todo { id, title: new.title, priority: new.priority, completed: new.completed };
-            (StatusCode::CREATED, Json(todo))
+    let todo = db.save_todo(&new).await?;
+    Ok((StatusCode::CREATED, Json(todo)))
+}
+
+pub async fn get_todo(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    ListId(list_id): ListId,
+    ApiPath(TodoPath { id }): ApiPath<TodoPath>)
+    -> Result<Json<Todo>, ApiError> {
+    Ok(Json(db.query_todo(list_id, id as u64).await?))
+}
+
+/// The notes of the todo as sanitized HTML, so that clients never have to
+/// render Markdown or trust raw HTML themselves.
+pub async fn get_todo_notes(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    ListId(list_id): ListId,
+    ApiPath(TodoPath { id }): ApiPath<TodoPath>)
+    -> Result<Json<RenderedNotes>, ApiError> {
+    let todo = db.query_todo(list_id, id as u64).await?;
+    Ok(Json(RenderedNotes { html: todo.notes.as_deref().map(notes::render).unwrap_or_default() }))
+}
+
+pub async fn update_todo(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(rules): Extension<ValidationRules>,
+    EditableListId(list_id): EditableListId,
+    ApiPath(TodoPath { id }): ApiPath<TodoPath>,
+    ApiQuery(params): ApiQuery<UpdateParams>,
+    ApiJson(payload): ApiJson<UpdateTodo>)
+    -> Result<Json<Todo>, ApiError> {
+    let mut validator = rules.validator();
+    let update = UpdateTodo {
+        title: payload.title.as_deref().map(|title| validator.title("title", title)),
+        priority: payload.priority.map(|priority| validator.priority("priority", priority)),
+        completed: payload.completed,
+        due_at: payload.due_at,
+        recurrence: payload.recurrence.map(|rule| rule.map(|rule| validator.recurrence("recurrence", &rule))),
+        parent_id: payload.parent_id,
+        notes: payload.notes.map(|notes| notes.and_then(|notes| validator.notes("notes", &notes))),
+    };
+    validator.finish()?;
+
+    Ok(Json(db.update_todo_checked(list_id, id as u64, &update, params.cascade == Some(true)).await?))
+}
+
+async fn check_parent(db: &Arc<dyn TodoStore>, list_id: u32, todo_id: Option<u32>, parent_id: u32) -> Result<(), ApiError> {
+    let todos = db.query_todos(list_id).await?;
+    subtasks::check_parent(&todos, todo_id, parent_id).map_err(|error| ApiError::Validation(vec![error]))
+}
+
+/// The todos of the list as a tree, each with the progress of its subtasks.
+/// Siblings come in the default priority order.
+pub async fn todo_tree(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    ListId(list_id): ListId)
+    -> Result<Json<Vec<TodoNode>>, ApiError> {
+    let todos = db.query_todos(list_id).await?;
+    Ok(Json(subtasks::build_tree(&todos)))
+}
+
+pub async fn delete_todo_by_id(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(attachments): Extension<AttachmentConfig>,
+    EditableListId(list_id): EditableListId,
+    ApiPath(TodoPath { id }): ApiPath<TodoPath>)
+    -> Result<StatusCode, ApiError> {
+    let before = db.query_list_attachments(list_id).await?;
+    db.delete_todo(list_id, id as u64).await?;
+    attachments.remove_dropped(&db, list_id, before).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_attachments(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    ListId(list_id): ListId,
+    ApiPath(TodoPath { id }): ApiPath<TodoPath>)
+    -> Result<Json<Vec<Attachment>>, ApiError> {
+    Ok(Json(db.query_attachments(list_id, id as u64).await?))
+}
+
+/// Takes the `file` part of a multipart upload and attaches it to the todo.
+/// The file is streamed to disk, so the request body limit does not apply;
+/// `AttachmentConfig::max_bytes` does instead.
+pub async fn upload_attachment(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(attachments): Extension<AttachmentConfig>,
+    EditableListId(list_id): EditableListId,
+    ApiPath(TodoPath { id }): ApiPath<TodoPath>,
+    multipart: Result<Multipart, MultipartRejection>)
+    -> Result<(StatusCode, Json<Attachment>), ApiError> {
+    let mut multipart = multipart
+        .map_err(|rejection| ApiError::Validation(vec![FieldError::new("file", rejection.body_text())]))?;
+    db.query_todo(list_id, id as u64).await?;
+    while let Some(mut field) = multipart.next_field().await.map_err(attachments::multipart_error)? {
+        if field.name() != Some("file") {
+            continue;
         }
-        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, Json(new)),
+        let file_name = attachments::file_name(field.file_name());
+        let content_type = attachments::content_type(field.content_type());
+        let stored = attachments.store(&mut field).await?;
+        let attachment = Attachment {
+            id: 0,
+            todo_id: id,
+            archived: false,
+            file_name,
+            content_type,
+            size: stored.size,
+            sha256: stored.sha256,
+            created_at: Utc::now(),
+            storage_key: stored.storage_key,
+        };
+        return match db.save_attachment(list_id, &attachment).await {
+            Ok(saved) => Ok((StatusCode::CREATED, Json(saved))),
+            Err(err) => {
+                attachments.remove(&attachment.storage_key).await;
+                Err(err)
+            }
+        };
     }
+    Err(ApiError::Validation(vec![FieldError::new("file", "is required")]))
+}
+
+/// Streams the file back as a download with the name and type it was
+/// uploaded with. `nosniff` keeps browsers from treating it as anything
+/// else.
+pub async fn download_attachment(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(attachments): Extension<AttachmentConfig>,
+    ListId(list_id): ListId,
+    ApiPath(AttachmentPath { id, attachment_id }): ApiPath<AttachmentPath>)
+    -> Result<Response, ApiError> {
+    let attachment = db.query_attachment(list_id, id as u64, attachment_id).await?;
+    let file = attachments.open(&attachment).await?;
+    let disposition = format!("attachment; filename=\"{}\"", attachment.file_name);
+    let headers = [
+        (header::CONTENT_TYPE, attachment.content_type),
+        (header::CONTENT_LENGTH, attachment.size.to_string()),
+        (header::CONTENT_DISPOSITION, disposition),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+    ];
+    Ok((headers, Body::from_stream(ReaderStream::new(file))).into_response())
+}
+
+pub async fn delete_attachment(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(attachments): Extension<AttachmentConfig>,
+    EditableListId(list_id): EditableListId,
+    ApiPath(AttachmentPath { id, attachment_id }): ApiPath<AttachmentPath>)
+    -> Result<StatusCode, ApiError> {
+    let attachment = db.delete_attachment(list_id, id as u64, attachment_id).await?;
+    attachments.remove(&attachment.storage_key).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Puts a tag on the todo, creating the tag on first use. Tagging a todo
+/// twice is harmless.
+pub async fn add_todo_tag(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(rules): Extension<ValidationRules>,
+    EditableListId(list_id): EditableListId,
+    ApiPath(TodoPath { id }): ApiPath<TodoPath>,
+    ApiJson(payload): ApiJson<TagPayload>)
+    -> Result<Json<Todo>, ApiError> {
+    let mut validator = rules.validator();
+    let name = validator.tag_name("name", &payload.name);
+    let color = payload.color.as_deref().map(|color| validator.color("color", color));
+    validator.finish()?;
+
+    Ok(Json(db.add_todo_tag(list_id, id as u64, &name, color.as_deref()).await?))
+}
+
+/// Records that the todo is blocked by another todo of the list. Blockers
+/// that would close a cycle are rejected.
+pub async fn add_blocker(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    EditableListId(list_id): EditableListId,
+    ApiPath(TodoPath { id }): ApiPath<TodoPath>,
+    ApiJson(payload): ApiJson<BlockerPayload>)
+    -> Result<Json<Todo>, ApiError> {
+    Ok(Json(db.add_dependency(list_id, id as u64, payload.blocker_id as u64).await?))
+}
+
+pub async fn remove_blocker(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    EditableListId(list_id): EditableListId,
+    ApiPath(BlockerPath { id, blocker_id }): ApiPath<BlockerPath>)
+    -> Result<Json<Todo>, ApiError> {
+    Ok(Json(db.remove_dependency(list_id, id as u64, blocker_id as u64).await?))
+}
+
+/// Every todo of the list with the dependencies between them.
+pub async fn dependency_graph(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    ListId(list_id): ListId)
+    -> Result<Json<DependencyGraph>, ApiError> {
+    let todos = db.query_todos(list_id).await?;
+    let dependencies = db.query_dependencies(list_id).await?;
+    Ok(Json(DependencyGraph::new(&todos, dependencies)))
+}
+
+/// Takes a tag off the todo. The tag itself stays available in the list.
+pub async fn remove_todo_tag(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    EditableListId(list_id): EditableListId,
+    ApiPath(TodoTagPath { id, name }): ApiPath<TodoTagPath>)
+    -> Result<Json<Todo>, ApiError> {
+    Ok(Json(db.remove_todo_tag(list_id, id as u64, &name).await?))
+}
+
+pub async fn list_tags(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    ListId(list_id): ListId)
+    -> Result<Json<Vec<Tag>>, ApiError> {
+    Ok(Json(db.query_tags(list_id).await?))
 }
 
 pub async fn archive_completed_todos(Extension(
-    db): Extension<Arc<todo_list_dao::TodoListDao>>) 
-    -> (StatusCode, Json<Message>) {
+    db): Extension<Arc<dyn TodoStore>>,
+    EditableListId(list_id): EditableListId)
+    -> Result<(StatusCode, Json<Message>), ApiError> {
+    let count = db.archive_completed_todos(list_id).await?;
+    let msg = Message { text: format!("Archived {} completed todo(s)", count) };
+    Ok((StatusCode::OK, Json(msg)))
+}
 
-    match db.archive_completed_todos().await {
-        Ok(count) => {
-            let msg = Message { text: format!("Archived {} completed todo(s)", count) };
-            (StatusCode::OK, Json(msg))
-        }
-        Err(_) => {
-            let msg = Message { text: "Failed to archive completed todos".to_string() };
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(msg))
-        }
+/// A dry run of the retention rules on the list.
+pub async fn retention_report(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(retention): Extension<RetentionConfig>,
+    Extension(clock): Extension<Arc<dyn Clock>>,
+    ListId(list_id): ListId)
+    -> Result<Json<RetentionReport>, ApiError> {
+    Ok(Json(retention::report(&db, &retention, list_id, clock.now()).await?))
+}
+
+pub async fn list_archived_todos(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    ListId(list_id): ListId)
+    -> Result<Json<Vec<Todo>>, ApiError> {
+    Ok(Json(db.query_archived_todos(list_id).await?))
+}
+
+/// An archived todo under the id it had before, so that links to a todo
+/// keep working once it is archived.
+pub async fn get_archived_todo(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    ListId(list_id): ListId,
+    ApiPath(TodoPath { id }): ApiPath<TodoPath>)
+    -> Result<Json<Todo>, ApiError> {
+    db.query_archived_todos(list_id).await?
+        .into_iter()
+        .find(|todo| todo.id == id)
+        .map(Json)
+        .ok_or_else(|| ApiError::archived_todo_not_found(id))
+}
+
+/// Archives one todo with its subtasks, whether it is completed or not.
+pub async fn archive_todo(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    EditableListId(list_id): EditableListId,
+    ApiPath(TodoPath { id }): ApiPath<TodoPath>)
+    -> Result<Json<Todo>, ApiError> {
+    Ok(Json(db.archive_todo(list_id, id as u64).await?))
+}
+
+/// Moves archived todos back to the list under the ids they had in the
+/// archive.
+pub async fn restore_archived_todos(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    EditableListId(list_id): EditableListId,
+    ApiJson(payload): ApiJson<RestorePayload>)
+    -> Result<Json<Vec<Todo>>, ApiError> {
+    if payload.ids.is_empty() {
+        return Err(ApiError::Validation(vec![FieldError::new("ids", "must name at least one archived todo")]));
     }
+    Ok(Json(db.restore_archived_todos(list_id, &payload.ids, payload.reset_completed).await?))
 }
 
 pub async fn rename_todo(Extension(
-    db): Extension<Arc<todo_list_dao::TodoListDao>>, 
-    Json(payload): Json<RenamePayload>) 
-    -> (StatusCode, Json<Todo>) {
-    let id = payload.id as u64;
-    let new_title = payload.new_title;
-
-    match db.rename_todo(id, new_title.clone()).await {
-        Ok(id_u32) => {
-            let todo = Todo { id: id_u32, title: new_title.to_string(), priority: 0, completed: false };
-            (StatusCode::ACCEPTED, Json(todo))
-        }
-        Err(_) => {
-            let fallback = Todo { id: payload.id, title: new_title.to_string(), priority: 0, completed: false };
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(fallback))
-        }
-    }
+    db): Extension<Arc<dyn TodoStore>>, 
+    Extension(rules): Extension<ValidationRules>,
+    EditableListId(list_id): EditableListId,
+    ApiJson(payload): ApiJson<RenamePayload>) 
+    -> Result<(StatusCode, Json<Todo>), ApiError> {
+    let mut validator = rules.validator();
+    let new_title = validator.title("new_title", &payload.new_title);
+    validator.finish()?;
+
+    let todo = db.rename_todo(list_id, payload.id as u64, new_title).await?;
+    Ok((StatusCode::ACCEPTED, Json(todo)))
 }
 
 pub async fn toggle_todo_completion(Extension(
-    db): Extension<Arc<todo_list_dao::TodoListDao>>, 
-    Json(payload): Json<IdPayload>) -> (StatusCode, Json<Todo>) {
+    db): Extension<Arc<dyn TodoStore>>,
+    EditableListId(list_id): EditableListId,
+    ApiJson(payload): ApiJson<IdPayload>) -> Result<(StatusCode, Json<Todo>), ApiError> {
+    let todo = db.toggle_todo_completion(list_id, payload.id as u64).await?;
+    Ok((StatusCode::ACCEPTED, Json(todo)))
+}
 
-    let id = payload.id as u64;
+pub async fn delete_todo(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(attachments): Extension<AttachmentConfig>,
+    EditableListId(list_id): EditableListId,
+    ApiJson(payload): ApiJson<IdPayload>) -> Result<(StatusCode, Json<Message>), ApiError> {
+    let before = db.query_list_attachments(list_id).await?;
+    db.delete_todo(list_id, payload.id as u64).await?;
+    attachments.remove_dropped(&db, list_id, before).await?;
+    let msg = Message { text: format!("Todo with id {} deleted successfully", payload.id) };
+    Ok((StatusCode::OK, Json(msg)))
+}
 
-    match db.toggle_todo_completion(id).await {
-        Ok(id_u32) => {
-            let todo = Todo { id: id_u32, title: String::new(), priority: 0, completed: true };
-            (StatusCode::ACCEPTED, Json(todo))
-        }
-        Err(_) => {
-            let fallback = Todo { id: payload.id, title: String::new(), priority: 0, completed: true };
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(fallback))
-        }
-    }
+pub async fn increase_todo_priority(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    EditableListId(list_id): EditableListId,
+    ApiJson(payload): ApiJson<IdPayload>) -> Result<(StatusCode, Json<Todo>), ApiError> {
+    let todo = db.increase_todo_priority(list_id, payload.id as u64).await?;
+    Ok((StatusCode::ACCEPTED, Json(todo)))
 }
 
-pub async fn delete_todo(Extension(
-    db): Extension<Arc<todo_list_dao::TodoListDao>>, 
-    Json(payload): Json<IdPayload>) -> (StatusCode, Json<Message>) {
+pub async fn decrease_todo_priority(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    EditableListId(list_id): EditableListId,
+    ApiJson(payload): ApiJson<IdPayload>) -> Result<(StatusCode, Json<Todo>), ApiError> {
+    let todo = db.decrease_todo_priority(list_id, payload.id as u64).await?;
+    Ok((StatusCode::ACCEPTED, Json(todo)))
+}
 
-    let id = payload.id as u64;
+pub async fn clear_todo_list(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(attachments): Extension<AttachmentConfig>,
+    EditableListId(list_id): EditableListId)
+    -> Result<(StatusCode, Json<Message>), ApiError> {
+    let before = db.query_list_attachments(list_id).await?;
+    db.truncate_todos_table(list_id).await?;
+    attachments.remove_dropped(&db, list_id, before).await?;
+    Ok((StatusCode::OK, Json(Message { text: "All todos have been deleted".to_string() })))
+}
 
-    match db.delete_todo(id).await {
-        Ok(_) => {
-            let msg = Message { text: format!("Todo with id {} deleted successfully", payload.id) };
-            (StatusCode::OK, Json(msg))
-        }
-        Err(_) => {
-            let msg = Message { text: format!("Failed to delete todo with id {}", payload.id) };
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(msg))
-        }
+/// Carries the cursor of the next page of `GET /api/todos`. The body stays
+/// a plain array so that existing clients keep working.
+pub const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+pub async fn list_todos(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    ListId(list_id): ListId,
+    ApiQuery(params): ApiQuery<ListTodosParams>)
+    -> Result<Response, ApiError> {
+    let query = TodoQuery::try_from(params)?;
+    let page = db.query_todo_page(list_id, &query).await?;
+    Ok(page_response(page))
+}
+
+/// The open todos whose due date has passed, in the order of
+/// `GET /api/todos`, which also lends its paging parameters.
+pub async fn list_overdue_todos(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(clock): Extension<Arc<dyn Clock>>,
+    ListId(list_id): ListId,
+    ApiQuery(params): ApiQuery<ListTodosParams>)
+    -> Result<Response, ApiError> {
+    let mut query = TodoQuery::try_from(params)?;
+    let now = clock.now();
+    query.completed = Some(false);
+    query.due_before = Some(query.due_before.map_or(now, |before| before.min(now)));
+    let page = db.query_todo_page(list_id, &query).await?;
+    Ok(page_response(page))
+}
+
+fn page_response(page: TodoPage) -> Response {
+    let mut response = Json(page.todos).into_response();
+    if let Some(cursor) = page.next_cursor {
+        let value = HeaderValue::from_str(&cursor.encode()).unwrap();
+        response.headers_mut().insert(NEXT_CURSOR_HEADER, value);
     }
+    response
 }
 
-pub async fn increase_todo_priority(Extension(
-    db): Extension<Arc<todo_list_dao::TodoListDao>>, 
-    Json(payload): Json<IdPayload>) -> (StatusCode, Json<Message>) {
+pub async fn search_todos(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    ListId(list_id): ListId,
+    ApiQuery(params): ApiQuery<SearchParams>)
+    -> Result<Json<Vec<SearchHit>>, ApiError> {
+    let query = SearchQuery::try_from(params)?;
+    Ok(Json(db.search_todos(list_id, &query).await?))
+}
 
-    let id = payload.id as u64;
+pub async fn list_completed_todos(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    ListId(list_id): ListId)
+    -> Result<Json<Vec<Todo>>, ApiError> {
+    Ok(Json(db.query_archived_todos(list_id).await?))
+}
 
-    match db.increase_todo_priority(id).await {
-        Ok(id_u32) => {
-            (StatusCode::ACCEPTED, Json(Message { text: format!("Todo with id {} priority increased", id_u32) }))
-        }
-        Err(_) => {
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(Message { text: "Failed to increase todo priority".into() }))
-        }
-    }
+pub async fn list_lists(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    AuthUser(user): AuthUser)
+    -> Result<Json<Vec<TodoList>>, ApiError> {
+    Ok(Json(db.query_lists(user.id).await?))
 }
 
-pub async fn decrease_todo_priority(Extension(
-    db): Extension<Arc<todo_list_dao::TodoListDao>>, 
-    Json(payload): Json<IdPayload>) -> (StatusCode, Json<Message>) {
+pub async fn create_list(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(rules): Extension<ValidationRules>,
+    AuthUser(user): AuthUser,
+    ApiJson(payload): ApiJson<ListPayload>)
+    -> Result<(StatusCode, Json<TodoList>), ApiError> {
+    let mut validator = rules.validator();
+    let name = validator.title("name", &payload.name);
+    validator.finish()?;
 
-    let id = payload.id as u64;
+    Ok((StatusCode::CREATED, Json(db.create_list(user.id, name).await?)))
+}
 
-    match db.decrease_todo_priority(id).await {
-        Ok(id_u32) => {
-            (StatusCode::ACCEPTED, Json(Message { text: format!("Todo with id {} priority decreased", id_u32) }))
-        }
-        Err(_) => {
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(Message { text: "Failed to decrease todo priority".into() }))
-        }
+pub async fn get_list(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    ListId(list_id): ListId)
+    -> Result<Json<TodoList>, ApiError> {
+    Ok(Json(db.query_list(list_id).await?))
+}
+
+pub async fn rename_list(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(rules): Extension<ValidationRules>,
+    OwnedListId(list_id): OwnedListId,
+    ApiJson(payload): ApiJson<ListPayload>)
+    -> Result<Json<TodoList>, ApiError> {
+    let mut validator = rules.validator();
+    let name = validator.title("name", &payload.name);
+    validator.finish()?;
+
+    Ok(Json(db.rename_list(list_id, name).await?))
+}
+
+/// Deletes a list together with its active and archived todos. The default
+/// list backs the `/api/todos` routes and cannot be deleted.
+pub async fn delete_list(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(attachments): Extension<AttachmentConfig>,
+    OwnedListId(list_id): OwnedListId)
+    -> Result<StatusCode, ApiError> {
+    if db.query_list(list_id).await?.is_default {
+        return Err(ApiError::Conflict("The default list cannot be deleted".to_string()));
+    }
+    let dropped = db.query_list_attachments(list_id).await?;
+    db.delete_list(list_id).await?;
+    for attachment in dropped {
+        attachments.remove(&attachment.storage_key).await;
     }
+    Ok(StatusCode::NO_CONTENT)
 }
 
-pub async fn clear_todo_list(Extension(
-    db): Extension<Arc<todo_list_dao::TodoListDao>>) 
-    -> (StatusCode, Json<Message>) {
-    match db.truncate_todos_table().await {
-        Ok(_) => (StatusCode::OK, Json(Message { text: "All todos have been deleted".to_string() })),
-        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, Json(Message { text: "Failed to clear todo list".to_string() })),
+/// Any member may see who else is on a list.
+pub async fn list_members(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    ListId(list_id): ListId)
+    -> Result<Json<Vec<ListMember>>, ApiError> {
+    Ok(Json(db.query_members(list_id).await?))
+}
+
+/// Invites the account registered under `email` to the list. The invitee
+/// does not need to exist yet; they accept with the returned token once
+/// they have signed up.
+pub async fn invite_member(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(rules): Extension<ValidationRules>,
+    OwnedListId(list_id): OwnedListId,
+    ApiJson(payload): ApiJson<InvitePayload>)
+    -> Result<(StatusCode, Json<CreatedInvite>), ApiError> {
+    let mut validator = rules.validator();
+    let email = validator.email("email", &payload.email);
+    let role = validator.role("role", &payload.role);
+    validator.finish()?;
+
+    let token = auth::new_session_token();
+    let expires_at = membership::invite_expiry(Utc::now());
+    let invite = db.create_invite(list_id, &email, role, &auth::hash_token(&token), expires_at).await?;
+    Ok((StatusCode::CREATED, Json(CreatedInvite { invite, token })))
+}
+
+pub async fn accept_invite(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    AuthUser(user): AuthUser,
+    ApiJson(payload): ApiJson<AcceptInvite>)
+    -> Result<Json<ListMember>, ApiError> {
+    Ok(Json(db.accept_invite(&auth::hash_token(&payload.token), &user, Utc::now()).await?))
+}
+
+/// Starts a session for `user` and sets its cookie on `response`.
+async fn sign_in(db: &Arc<dyn TodoStore>, user: &User, mut response: Response) -> Result<Response, ApiError> {
+    let token = auth::new_session_token();
+    db.create_session(&auth::hash_token(&token), user.id, auth::session_expiry(Utc::now())).await?;
+    response.headers_mut().append(header::SET_COOKIE, auth::session_cookie(&token));
+    Ok(response)
+}
+
+pub async fn register(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(rules): Extension<ValidationRules>,
+    ApiJson(payload): ApiJson<Credentials>)
+    -> Result<Response, ApiError> {
+    let mut validator = rules.validator();
+    let email = validator.email("email", &payload.email);
+    validator.password("password", &payload.password);
+    validator.finish()?;
+
+    let user = db.create_user(&email, &auth::hash_password(&payload.password)).await?;
+    let response = (StatusCode::CREATED, Json(user.clone())).into_response();
+    sign_in(&db, &user, response).await
+}
+
+/// Unknown emails and wrong passwords get the same answer, so that the
+/// endpoint does not reveal which addresses have an account.
+pub async fn login(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    ApiJson(payload): ApiJson<Credentials>)
+    -> Result<Response, ApiError> {
+    let email = payload.email.trim().to_lowercase();
+    let user = match db.query_credentials(&email).await? {
+        Some((user, password_hash)) if auth::verify_password(&payload.password, &password_hash) => user,
+        _ => return Err(ApiError::Unauthorized("Invalid email or password".to_string())),
+    };
+
+    let response = Json(user.clone()).into_response();
+    sign_in(&db, &user, response).await
+}
+
+pub async fn logout(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    headers: HeaderMap)
+    -> Result<Response, ApiError> {
+    if let Some(token) = auth::session_token(&headers) {
+        db.delete_session(&auth::hash_token(&token)).await?;
     }
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    response.headers_mut().insert(header::SET_COOKIE, auth::expired_session_cookie());
+    Ok(response)
 }
 
-pub async fn list_todos(Extension(
-    db): Extension<Arc<todo_list_dao::TodoListDao>>) 
-    -> Json<Vec<Todo>> {
-    let mut todos: Vec<Todo> = Vec::new();
-    if let Ok(rows) = db.query_todos().await {
-        for row in rows {
-            let id: i32 = row.get("id");
-            let title: String = row.get("title");
-            let priority: i32 = row.get("priority");
-            let completed: bool = row.get("completed");
-            todos.push(Todo {
-                id: id as u32,
-                title,
-                priority: priority as u8,
-                completed,
-            });
-        }
+fn oidc_disabled() -> ApiError {
+    ApiError::NotFound("OIDC login is not configured".to_string())
+}
+
+/// Starts an OIDC login by sending the browser to the provider. The state,
+/// PKCE verifier and nonce wait in a short-lived cookie for the callback.
+pub async fn oidc_login(Extension(
+    oidc): Extension<Option<Arc<OidcClient>>>)
+    -> Result<Response, ApiError> {
+    let oidc = oidc.ok_or_else(oidc_disabled)?;
+    let pending = PendingLogin::new();
+    let url = oidc.authorization_url(&pending).await?;
+
+    let mut response = Redirect::to(url.as_str()).into_response();
+    response.headers_mut().insert(header::SET_COOKIE, pending.cookie());
+    Ok(response)
+}
+
+/// Finishes an OIDC login: checks that the callback belongs to the login
+/// this browser started, redeems the code and signs the user in before
+/// sending them back to the frontend. A user who is already signed in may
+/// link the identity to their account this way.
+pub async fn oidc_callback(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(oidc): Extension<Option<Arc<OidcClient>>>,
+    signed_in: Option<AuthUser>,
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<OidcCallback>)
+    -> Result<Response, ApiError> {
+    let oidc = oidc.ok_or_else(oidc_disabled)?;
+    if let Some(error) = params.error {
+        return Err(oidc::login_failed(format!("the provider answered {}", error)));
+    }
+    let pending = PendingLogin::from_headers(&headers).ok_or_else(|| oidc::login_failed("no login is pending"))?;
+    if params.state.as_deref() != Some(pending.state.as_str()) {
+        return Err(oidc::login_failed("the state does not match"));
     }
-    Json(todos)
+    let code = params.code.ok_or_else(|| oidc::login_failed("the callback has no code"))?;
+    let claims = oidc.exchange_code(&code, &pending).await?;
+    let user = oidc_user(&db, &claims, signed_in.map(|AuthUser(user)| user)).await?;
+
+    let mut response = Redirect::to(frontend_origin().to_str().unwrap()).into_response();
+    response.headers_mut().insert(header::SET_COOKIE, PendingLogin::expired_cookie());
+    sign_in(&db, &user, response).await
 }
 
-pub async fn list_completed_todos(Extension(
-    db): Extension<Arc<todo_list_dao::TodoListDao>>) 
-    -> Json<Vec<Todo>> {
-    let mut todos: Vec<Todo> = Vec::new();
-    if let Ok(rows) = db.query_archived_todos().await {
-        for row in rows {
-            let id: i32 = row.get("id");
-            let title: String = row.get("title");
-            let priority: i32 = row.get("priority");
-            let completed: bool = row.get("completed");
-            todos.push(Todo {
-                id: id as u32,
-                title,
-                priority: priority as u8,
-                completed,
-            });
-        }
+/// The account behind an OIDC identity. On its first login an identity is
+/// linked to the account with the same verified email, or to a new account
+/// without a password. An account with a password is only linked while its
+/// owner is `signed_in` to it, since the provider vouching for an email does
+/// not prove who set that password.
+async fn oidc_user(db: &Arc<dyn TodoStore>, claims: &oidc::IdTokenClaims, signed_in: Option<User>)
+    -> Result<User, ApiError> {
+    if let Some(user) = db.query_identity_user(&claims.iss, &claims.sub).await? {
+        return Ok(user);
     }
-    Json(todos)
+    let email = claims.verified_email()
+        .ok_or_else(|| ApiError::Unauthorized("The identity provider did not share a verified email".to_string()))?;
+    let user = match db.query_credentials(&email).await? {
+        Some((user, password_hash)) if password_hash.is_empty() || signed_in.is_some_and(|current| current.id == user.id) => user,
+        Some(_) => return Err(ApiError::Conflict(
+            "An account with this email already exists; sign in with its password to link this identity".to_string())),
+        None => db.create_user(&email, "").await?,
+    };
+    db.link_identity(&claims.iss, &claims.sub, user.id).await?;
+    Ok(user)
+}
+
+pub async fn current_user(AuthUser(user): AuthUser) -> Json<User> {
+    Json(user)
+}
+
+/// Creates a personal access token. The response is the only place where
+/// the token itself ever appears.
+pub async fn create_api_token(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    Extension(rules): Extension<ValidationRules>,
+    AuthUser(user): AuthUser,
+    ApiJson(payload): ApiJson<CreateApiToken>)
+    -> Result<(StatusCode, Json<CreatedApiToken>), ApiError> {
+    let mut validator = rules.validator();
+    let name = validator.title("name", &payload.name);
+    let scopes = validator.scopes("scopes", &payload.scopes);
+    let days = validator.token_lifetime("expires_in_days", payload.expires_in_days.unwrap_or(DEFAULT_TOKEN_TTL_DAYS));
+    validator.finish()?;
+
+    let token = api_token::new_api_token();
+    let expires_at = Utc::now() + chrono::Duration::days(days as i64);
+    let api_token = db.create_api_token(user.id, &name, &auth::hash_token(&token), &scopes, expires_at).await?;
+    Ok((StatusCode::CREATED, Json(CreatedApiToken { api_token, token })))
+}
+
+pub async fn list_api_tokens(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    AuthUser(user): AuthUser)
+    -> Result<Json<Vec<ApiToken>>, ApiError> {
+    Ok(Json(db.query_api_tokens(user.id).await?))
+}
+
+pub async fn revoke_api_token(Extension(
+    db): Extension<Arc<dyn TodoStore>>,
+    AuthUser(user): AuthUser,
+    ApiPath(ApiTokenPath { id }): ApiPath<ApiTokenPath>)
+    -> Result<StatusCode, ApiError> {
+    db.delete_api_token(user.id, id).await?;
+    Ok(StatusCode::NO_CONTENT)
 }
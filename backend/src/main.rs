@@ -1,20 +1,46 @@
 use dotenvy::dotenv;
 use std::net::SocketAddr;
-
-use backend::{build_app};
-use backend::todo_list_dao::TodoListDao;
 use std::sync::Arc;
 
+use backend::build_app_with_retention;
+use backend::attachments::AttachmentConfig;
+use backend::oidc::OidcConfig;
+use backend::retention::{self, RetentionConfig, SystemClock};
+use backend::validation::ValidationRules;
+use backend::todo_store;
+
 #[tokio::main]
 async fn main() {
 
     dotenv().ok();
+    tracing_subscriber::fmt::init();
+
+    let database_url = std::env::var("DATABASE_URL")
+        .expect("DATABASE_URL must be set");
+    let db = todo_store::connect(&database_url).await.unwrap();
 
-    let database = TodoListDao::new().await.unwrap();
-    database.initialize().await;
-    let db = Arc::new(database);
+    if std::env::args().any(|arg| arg == "--reset") {
+        println!("Resetting database, all data will be lost");
+        db.initialize().await;
+    } else {
+        let applied = db.migrate().await.unwrap();
+        for version in applied {
+            println!("Applied migration {:04}", version);
+        }
+    }
 
-    let app = build_app(db.clone());
+    let oidc = OidcConfig::from_env();
+    if let Some(config) = &oidc {
+        println!("OIDC login through {}", config.issuer);
+    }
+    let attachments = AttachmentConfig::from_env();
+    let retention = RetentionConfig::from_env();
+    if retention.is_enabled() {
+        println!("Applying retention rules every {} minute(s)", retention.interval.as_secs() / 60);
+        retention::spawn(db.clone(), attachments.clone(), retention.clone(), Arc::new(SystemClock));
+    }
+    let app = build_app_with_retention(db.clone(), ValidationRules::from_env(), oidc, attachments, retention,
+        Arc::new(SystemClock));
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 3001));
     let msg = format!("Server listening on http://{}", addr);
@@ -0,0 +1,89 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// How long an invite can be accepted.
+pub const INVITE_TTL_DAYS: i64 = 7;
+
+/// What a member may do with a list. Each role includes the ones before
+/// it: viewers read, editors also change todos, owners also rename, delete
+/// and share the list.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Viewer, Role::Editor, Role::Owner];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, ()> {
+        Role::ALL.into_iter().find(|role| role.as_str() == value).ok_or(())
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ListMember {
+    pub list_id: u32,
+    pub user_id: u32,
+    pub email: String,
+    pub role: Role,
+}
+
+/// Body of `POST /api/lists/:list_id/invites`. The role is a plain string
+/// so that unknown roles are reported like any other invalid field.
+#[derive(Deserialize)]
+pub struct InvitePayload {
+    pub email: String,
+    pub role: String,
+}
+
+/// A pending invite. It can only be accepted by the account registered
+/// under `email`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ListInvite {
+    pub list_id: u32,
+    pub email: String,
+    pub role: Role,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Answer to `POST /api/lists/:list_id/invites`. The token is handed to the
+/// invitee out of band; only its hash is stored.
+#[derive(Serialize)]
+pub struct CreatedInvite {
+    #[serde(flatten)]
+    pub invite: ListInvite,
+    pub token: String,
+}
+
+/// Body of `POST /api/invites/accept`.
+#[derive(Deserialize)]
+pub struct AcceptInvite {
+    pub token: String,
+}
+
+pub fn invite_expiry(now: DateTime<Utc>) -> DateTime<Utc> {
+    now + Duration::days(INVITE_TTL_DAYS)
+}
@@ -0,0 +1,267 @@
+use sqlx::{Acquire, Executor, PgPool, SqlitePool};
+
+/// A numbered schema change. Migrations are applied in `version` order and
+/// each one is recorded in `schema_migrations` once it has run.
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+pub const POSTGRES_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_todos_and_archived",
+        sql: include_str!("../migrations/postgres/0001_create_todos_and_archived.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "priority_range",
+        sql: include_str!("../migrations/postgres/0002_priority_range.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "todo_list_indexes",
+        sql: include_str!("../migrations/postgres/0003_todo_list_indexes.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "title_search",
+        sql: include_str!("../migrations/postgres/0004_title_search.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "lists",
+        sql: include_str!("../migrations/postgres/0005_lists.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "users",
+        sql: include_str!("../migrations/postgres/0006_users.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "api_tokens",
+        sql: include_str!("../migrations/postgres/0007_api_tokens.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "list_members",
+        sql: include_str!("../migrations/postgres/0008_list_members.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "user_identities",
+        sql: include_str!("../migrations/postgres/0009_user_identities.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "tags",
+        sql: include_str!("../migrations/postgres/0010_tags.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "due_dates",
+        sql: include_str!("../migrations/postgres/0011_due_dates.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "recurrence",
+        sql: include_str!("../migrations/postgres/0012_recurrence.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "subtasks",
+        sql: include_str!("../migrations/postgres/0013_subtasks.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "dependencies",
+        sql: include_str!("../migrations/postgres/0014_dependencies.sql"),
+    },
+    Migration {
+        version: 15,
+        name: "notes",
+        sql: include_str!("../migrations/postgres/0015_notes.sql"),
+    },
+    Migration {
+        version: 16,
+        name: "attachments",
+        sql: include_str!("../migrations/postgres/0016_attachments.sql"),
+    },
+    Migration {
+        version: 17,
+        name: "archive_identity",
+        sql: include_str!("../migrations/postgres/0017_archive_identity.sql"),
+    },
+];
+
+pub const SQLITE_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_todos_and_archived",
+        sql: include_str!("../migrations/sqlite/0001_create_todos_and_archived.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "priority_range",
+        sql: include_str!("../migrations/sqlite/0002_priority_range.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "todo_list_indexes",
+        sql: include_str!("../migrations/sqlite/0003_todo_list_indexes.sql"),
+    },
+    // Version 4 added Postgres full-text search, which SQLite does without.
+    Migration {
+        version: 5,
+        name: "lists",
+        sql: include_str!("../migrations/sqlite/0005_lists.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "users",
+        sql: include_str!("../migrations/sqlite/0006_users.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "api_tokens",
+        sql: include_str!("../migrations/sqlite/0007_api_tokens.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "list_members",
+        sql: include_str!("../migrations/sqlite/0008_list_members.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "user_identities",
+        sql: include_str!("../migrations/sqlite/0009_user_identities.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "tags",
+        sql: include_str!("../migrations/sqlite/0010_tags.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "due_dates",
+        sql: include_str!("../migrations/sqlite/0011_due_dates.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "recurrence",
+        sql: include_str!("../migrations/sqlite/0012_recurrence.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "subtasks",
+        sql: include_str!("../migrations/sqlite/0013_subtasks.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "dependencies",
+        sql: include_str!("../migrations/sqlite/0014_dependencies.sql"),
+    },
+    Migration {
+        version: 15,
+        name: "notes",
+        sql: include_str!("../migrations/sqlite/0015_notes.sql"),
+    },
+    Migration {
+        version: 16,
+        name: "attachments",
+        sql: include_str!("../migrations/sqlite/0016_attachments.sql"),
+    },
+    Migration {
+        version: 17,
+        name: "archive_identity",
+        sql: include_str!("../migrations/sqlite/0017_archive_identity.sql"),
+    },
+];
+
+const CREATE_SCHEMA_MIGRATIONS_TABLE: &str =
+    "CREATE TABLE IF NOT EXISTS schema_migrations (
+        version BIGINT PRIMARY KEY,
+        name TEXT NOT NULL,
+        applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+    )";
+
+/// Key of the advisory lock that `run_postgres` holds, so that instances
+/// starting at the same time migrate one after the other.
+const POSTGRES_MIGRATION_LOCK: i64 = 0x746f_646f_5f6d_6967;
+
+/// Applies every migration that is not yet listed in `schema_migrations` and
+/// returns the versions that were applied. Each migration runs in its own
+/// transaction together with its bookkeeping row, and the whole run holds
+/// `POSTGRES_MIGRATION_LOCK`; the applied versions are read only once it is
+/// taken, so a second instance finds the first one's work done.
+pub async fn run_postgres(pool: &PgPool) -> Result<Vec<i64>, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    // The lock belongs to the session. Closing the connection afterwards
+    // releases it even when a migration fails halfway.
+    conn.close_on_drop();
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(POSTGRES_MIGRATION_LOCK)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query(CREATE_SCHEMA_MIGRATIONS_TABLE)
+        .execute(&mut *conn)
+        .await?;
+
+    let applied: Vec<i64> = sqlx::query_scalar("SELECT version FROM schema_migrations")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut newly_applied = Vec::new();
+    for migration in pending(POSTGRES_MIGRATIONS, &applied) {
+        let mut tx = conn.begin().await?;
+        tx.execute(migration.sql).await?;
+        sqlx::query("INSERT INTO schema_migrations (version, name) VALUES ($1, $2)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        newly_applied.push(migration.version);
+    }
+
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(POSTGRES_MIGRATION_LOCK)
+        .execute(&mut *conn)
+        .await?;
+    Ok(newly_applied)
+}
+
+/// The SQLite counterpart of `run_postgres`. Everything runs on a single
+/// connection: SQLite connections cache the schema, and a connection that
+/// read it before a migration expands `SELECT *` to the old columns.
+pub async fn run_sqlite(pool: &SqlitePool) -> Result<Vec<i64>, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    sqlx::query(CREATE_SCHEMA_MIGRATIONS_TABLE)
+        .execute(&mut *conn)
+        .await?;
+
+    let applied: Vec<i64> = sqlx::query_scalar("SELECT version FROM schema_migrations")
+        .fetch_all(&mut *conn)
+        .await?;
+
+    let mut newly_applied = Vec::new();
+    for migration in pending(SQLITE_MIGRATIONS, &applied) {
+        let mut tx = conn.begin().await?;
+        tx.execute(migration.sql).await?;
+        sqlx::query("INSERT INTO schema_migrations (version, name) VALUES ($1, $2)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        newly_applied.push(migration.version);
+    }
+    Ok(newly_applied)
+}
+
+fn pending<'a>(migrations: &'a [Migration], applied: &'a [i64]) -> impl Iterator<Item = &'a Migration> {
+    migrations.iter().filter(move |m| !applied.contains(&m.version))
+}
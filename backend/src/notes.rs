@@ -0,0 +1,23 @@
+use pulldown_cmark::{html, Options, Parser};
+use serde::Serialize;
+
+/// Notes are Markdown; anything longer is rejected.
+pub const MAX_NOTES_LENGTH: usize = 10_000;
+
+/// Body of `GET /api/todos/:id/notes`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RenderedNotes {
+    /// Empty for a todo without notes.
+    pub html: String,
+}
+
+/// Renders Markdown to HTML that is safe to insert into a page as is: raw
+/// HTML in the notes goes through the same sanitizer as everything else,
+/// which keeps formatting but drops scripts, event handlers and
+/// `javascript:` links.
+pub fn render(markdown: &str) -> String {
+    let parser = Parser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH);
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, parser);
+    ammonia::clean(&unsafe_html)
+}
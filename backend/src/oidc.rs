@@ -0,0 +1,241 @@
+use axum::http::{HeaderMap, HeaderValue};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use tokio::sync::OnceCell;
+use url::Url;
+use crate::auth;
+use crate::error::ApiError;
+
+pub const LOGIN_COOKIE: &str = "oidc_login";
+
+/// How long the provider may take to send the browser back.
+pub const LOGIN_TTL_SECONDS: i64 = 600;
+
+/// Where to find the identity provider and how this backend is registered
+/// with it. Read from the environment, so `.env` works as for `DATABASE_URL`.
+#[derive(Clone, Debug)]
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    /// Left out for public clients, which rely on PKCE alone.
+    pub client_secret: Option<String>,
+    /// The `/api/auth/oidc/callback` URL as the provider knows it.
+    pub redirect_url: String,
+}
+
+impl OidcConfig {
+    /// `None` unless both `OIDC_ISSUER` and `OIDC_CLIENT_ID` are set, which
+    /// leaves OIDC login switched off.
+    pub fn from_env() -> Option<Self> {
+        Some(OidcConfig {
+            issuer: std::env::var("OIDC_ISSUER").ok()?,
+            client_id: std::env::var("OIDC_CLIENT_ID").ok()?,
+            client_secret: std::env::var("OIDC_CLIENT_SECRET").ok(),
+            redirect_url: std::env::var("OIDC_REDIRECT_URL")
+                .unwrap_or_else(|_| "http://localhost:3001/api/auth/oidc/callback".to_string()),
+        })
+    }
+}
+
+/// The parts of the provider's discovery document that the code flow needs.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Discovery {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// The claims of a validated ID token that login cares about.
+#[derive(Deserialize, Debug)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub nonce: Option<String>,
+}
+
+impl IdTokenClaims {
+    /// The email, if the provider vouches for it. Providers that leave out
+    /// `email_verified` are trusted to only hand out addresses they checked.
+    pub fn verified_email(&self) -> Option<String> {
+        match (&self.email, self.email_verified) {
+            (Some(email), None | Some(true)) => Some(email.trim().to_lowercase()),
+            _ => None,
+        }
+    }
+}
+
+/// What the login route hands to the callback through `LOGIN_COOKIE`: the
+/// `state` that ties the callback to this browser, the PKCE verifier and
+/// the nonce that the ID token has to repeat.
+#[derive(Debug, PartialEq)]
+pub struct PendingLogin {
+    pub state: String,
+    pub code_verifier: String,
+    pub nonce: String,
+}
+
+impl PendingLogin {
+    pub fn new() -> Self {
+        PendingLogin {
+            state: auth::new_session_token(),
+            code_verifier: auth::new_session_token(),
+            nonce: auth::new_session_token(),
+        }
+    }
+
+    /// The S256 PKCE challenge for `code_verifier`.
+    pub fn code_challenge(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Sha256::digest(self.code_verifier.as_bytes()))
+    }
+
+    /// All three values are base64url, so a dot separates them safely.
+    pub fn cookie(&self) -> HeaderValue {
+        HeaderValue::from_str(&format!(
+            "{}={}.{}.{}; HttpOnly; SameSite=Lax; Path=/api/auth/oidc; Max-Age={}",
+            LOGIN_COOKIE, self.state, self.code_verifier, self.nonce, LOGIN_TTL_SECONDS,
+        ))
+        .unwrap()
+    }
+
+    pub fn expired_cookie() -> HeaderValue {
+        HeaderValue::from_str(&format!("{}=; HttpOnly; SameSite=Lax; Path=/api/auth/oidc; Max-Age=0", LOGIN_COOKIE)).unwrap()
+    }
+
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let value = auth::cookie_value(headers, LOGIN_COOKIE)?;
+        let mut parts = value.split('.');
+        let pending = PendingLogin {
+            state: parts.next()?.to_string(),
+            code_verifier: parts.next()?.to_string(),
+            nonce: parts.next()?.to_string(),
+        };
+        parts.next().is_none().then_some(pending)
+    }
+}
+
+impl Default for PendingLogin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Logs the details of a failed login and answers with a generic 401, as
+/// none of them help the user.
+pub fn login_failed(reason: impl fmt::Display) -> ApiError {
+    tracing::warn!("OIDC login failed: {}", reason);
+    ApiError::Unauthorized("Login with the identity provider failed".to_string())
+}
+
+/// Runs the authorization code flow against one provider. The discovery
+/// document is fetched on first use; the signing keys are fetched for every
+/// login so that key rotation needs no restart.
+pub struct OidcClient {
+    config: OidcConfig,
+    http: reqwest::Client,
+    discovery: OnceCell<Discovery>,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig) -> Self {
+        OidcClient { config, http: reqwest::Client::new(), discovery: OnceCell::new() }
+    }
+
+    pub async fn discovery(&self) -> Result<&Discovery, ApiError> {
+        self.discovery.get_or_try_init(|| async {
+            let url = format!("{}/.well-known/openid-configuration", self.config.issuer.trim_end_matches('/'));
+            let discovery: Discovery = self.http.get(&url).send().await
+                .and_then(|response| response.error_for_status())
+                .map_err(login_failed)?
+                .json().await
+                .map_err(login_failed)?;
+            if discovery.issuer.trim_end_matches('/') != self.config.issuer.trim_end_matches('/') {
+                return Err(login_failed(format!("discovery names issuer {}", discovery.issuer)));
+            }
+            Ok(discovery)
+        })
+        .await
+    }
+
+    /// Where to send the browser to start logging in.
+    pub async fn authorization_url(&self, pending: &PendingLogin) -> Result<Url, ApiError> {
+        let discovery = self.discovery().await?;
+        let mut url = Url::parse(&discovery.authorization_endpoint).map_err(login_failed)?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_url)
+            .append_pair("scope", "openid email")
+            .append_pair("state", &pending.state)
+            .append_pair("nonce", &pending.nonce)
+            .append_pair("code_challenge", &pending.code_challenge())
+            .append_pair("code_challenge_method", "S256");
+        Ok(url)
+    }
+
+    /// Trades the authorization code for an ID token and validates it.
+    pub async fn exchange_code(&self, code: &str, pending: &PendingLogin) -> Result<IdTokenClaims, ApiError> {
+        let discovery = self.discovery().await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_url.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", pending.code_verifier.as_str()),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+        let tokens: TokenResponse = self.http.post(&discovery.token_endpoint).form(&form).send().await
+            .and_then(|response| response.error_for_status())
+            .map_err(login_failed)?
+            .json().await
+            .map_err(login_failed)?;
+        self.validate_id_token(&tokens.id_token, &pending.nonce).await
+    }
+
+    /// Checks the signature against the provider's JWKS, then the issuer,
+    /// audience, expiry and nonce. Only asymmetric algorithms are accepted,
+    /// so the keys cannot be abused as shared secrets.
+    pub async fn validate_id_token(&self, id_token: &str, nonce: &str) -> Result<IdTokenClaims, ApiError> {
+        let discovery = self.discovery().await?;
+        let header = jsonwebtoken::decode_header(id_token).map_err(login_failed)?;
+        if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+            return Err(login_failed(format!("ID token signed with {:?}", header.alg)));
+        }
+
+        let jwks: JwkSet = self.http.get(&discovery.jwks_uri).send().await
+            .and_then(|response| response.error_for_status())
+            .map_err(login_failed)?
+            .json().await
+            .map_err(login_failed)?;
+        let jwk = match &header.kid {
+            Some(kid) => jwks.find(kid),
+            None => jwks.keys.first(),
+        }
+        .ok_or_else(|| login_failed("no matching signing key"))?;
+        let key = DecodingKey::from_jwk(jwk).map_err(login_failed)?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_issuer(&[&discovery.issuer]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map_err(login_failed)?
+            .claims;
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(login_failed("nonce mismatch"));
+        }
+        Ok(claims)
+    }
+}
@@ -0,0 +1,203 @@
+use chrono::{DateTime, Datelike, Duration, Months, Utc, Weekday};
+use std::fmt;
+use std::str::FromStr;
+use crate::Todo;
+
+pub const MAX_INTERVAL: u32 = 365;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl Frequency {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+        }
+    }
+}
+
+const WEEKDAYS: [(&str, Weekday); 7] = [
+    ("MO", Weekday::Mon),
+    ("TU", Weekday::Tue),
+    ("WE", Weekday::Wed),
+    ("TH", Weekday::Thu),
+    ("FR", Weekday::Fri),
+    ("SA", Weekday::Sat),
+    ("SU", Weekday::Sun),
+];
+
+/// How often a todo comes back once it is completed. Rules are written as
+/// the subset of iCalendar RRULEs with `FREQ` set to `DAILY`, `WEEKLY` or
+/// `MONTHLY`, an optional `INTERVAL`, for weekly rules `BYDAY`, e.g.
+/// `FREQ=WEEKLY;BYDAY=MO,TH`, and for monthly rules a single `BYMONTHDAY`.
+/// `daily`, `weekly` and `monthly` are accepted as shorthands. Weekdays
+/// and days of the month are those of the due date in UTC.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub interval: u32,
+    /// Monday first, without duplicates. Empty means the weekday of the
+    /// due date.
+    pub by_day: Vec<Weekday>,
+    /// The day of the month monthly occurrences fall on. `None` means the
+    /// day of the due date.
+    pub by_month_day: Option<u32>,
+}
+
+impl Recurrence {
+    /// The occurrence that follows one due at `due_at`. Monthly rules keep
+    /// their day of the month where they can and fall back to the month's
+    /// last day otherwise. Unlike in iCalendar, months without that day are
+    /// not skipped.
+    pub fn after(&self, due_at: DateTime<Utc>) -> DateTime<Utc> {
+        match self.frequency {
+            Frequency::Daily => due_at + Duration::days(self.interval as i64),
+            Frequency::Weekly if self.by_day.is_empty() => due_at + Duration::weeks(self.interval as i64),
+            Frequency::Weekly => {
+                let from_monday = due_at.weekday().num_days_from_monday();
+                if let Some(day) = self.by_day.iter().find(|day| day.num_days_from_monday() > from_monday) {
+                    return due_at + Duration::days((day.num_days_from_monday() - from_monday) as i64);
+                }
+                let monday = due_at - Duration::days(from_monday as i64);
+                monday + Duration::weeks(self.interval as i64) + Duration::days(self.by_day[0].num_days_from_monday() as i64)
+            }
+            Frequency::Monthly => {
+                let day = self.by_month_day.unwrap_or_else(|| due_at.day());
+                let month = (due_at - Duration::days(due_at.day0() as i64))
+                    .checked_add_months(Months::new(self.interval))
+                    .expect("due dates stay far from the end of time");
+                let last_day = (month + Months::new(1) - Duration::days(1)).day();
+                month + Duration::days((day.min(last_day) - 1) as i64)
+            }
+        }
+    }
+
+    /// This rule pinned to the day of the month of `due_at` if it is a
+    /// monthly rule without a `BYMONTHDAY`, so that a todo due on the 31st
+    /// comes back on the 31st after a short month. Days every month has
+    /// need no pinning.
+    pub fn anchored(&self, due_at: DateTime<Utc>) -> Recurrence {
+        let mut rule = self.clone();
+        if rule.frequency == Frequency::Monthly && rule.by_month_day.is_none() && due_at.day() > 28 {
+            rule.by_month_day = Some(due_at.day());
+        }
+        rule
+    }
+
+    /// The first occurrence after both `due_at` and `completed_at`, so that
+    /// a todo completed late does not come back already overdue. Todos
+    /// without a due date recur from the moment they were completed.
+    pub fn next_due(&self, due_at: Option<DateTime<Utc>>, completed_at: DateTime<Utc>) -> DateTime<Utc> {
+        let from = due_at.unwrap_or(completed_at);
+        let rule = self.anchored(from);
+        let mut next = rule.after(from);
+        while next <= completed_at {
+            next = rule.after(next);
+        }
+        next
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FREQ={}", self.frequency.as_str())?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if !self.by_day.is_empty() {
+            let days: Vec<&str> = self.by_day.iter()
+                .map(|day| WEEKDAYS.iter().find(|(_, weekday)| weekday == day).unwrap().0)
+                .collect();
+            write!(f, ";BYDAY={}", days.join(","))?;
+        }
+        if let Some(day) = self.by_month_day {
+            write!(f, ";BYMONTHDAY={}", day)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        let shorthand = |frequency| Ok(Recurrence { frequency, interval: 1, by_day: Vec::new(), by_month_day: None });
+        match value.to_lowercase().as_str() {
+            "daily" => return shorthand(Frequency::Daily),
+            "weekly" => return shorthand(Frequency::Weekly),
+            "monthly" => return shorthand(Frequency::Monthly),
+            _ => {}
+        }
+
+        let rule = value.to_uppercase();
+        let rule = rule.strip_prefix("RRULE:").unwrap_or(&rule);
+        let (mut frequency, mut interval, mut by_day, mut by_month_day) = (None, 1, Vec::new(), None);
+        for part in rule.split(';').filter(|part| !part.is_empty()) {
+            let (key, value) = part.split_once('=').ok_or_else(|| format!("has a malformed part '{}'", part))?;
+            match key {
+                "FREQ" => {
+                    frequency = Some(match value {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        _ => return Err("must have a FREQ of DAILY, WEEKLY or MONTHLY".to_string()),
+                    });
+                }
+                "INTERVAL" => {
+                    interval = value.parse().ok()
+                        .filter(|interval| (1..=MAX_INTERVAL).contains(interval))
+                        .ok_or_else(|| format!("must have an INTERVAL between 1 and {}", MAX_INTERVAL))?;
+                }
+                "BYDAY" => {
+                    for code in value.split(',') {
+                        let (_, day) = WEEKDAYS.iter()
+                            .find(|(name, _)| *name == code)
+                            .ok_or_else(|| format!("has an unknown weekday '{}'", code))?;
+                        by_day.push(*day);
+                    }
+                }
+                "BYMONTHDAY" => {
+                    by_month_day = Some(value.parse().ok()
+                        .filter(|day| (1..=31).contains(day))
+                        .ok_or_else(|| "must have a BYMONTHDAY between 1 and 31".to_string())?);
+                }
+                _ => return Err(format!("has an unsupported part '{}'", key)),
+            }
+        }
+
+        let frequency = frequency.ok_or_else(|| "must have a FREQ of DAILY, WEEKLY or MONTHLY".to_string())?;
+        if !by_day.is_empty() && frequency != Frequency::Weekly {
+            return Err("may only have BYDAY with a WEEKLY FREQ".to_string());
+        }
+        if by_month_day.is_some() && frequency != Frequency::Monthly {
+            return Err("may only have BYMONTHDAY with a MONTHLY FREQ".to_string());
+        }
+        by_day.sort_by_key(|day| day.num_days_from_monday());
+        by_day.dedup();
+        Ok(Recurrence { frequency, interval, by_day, by_month_day })
+    }
+}
+
+/// The open todo that takes over from `completed`, a recurring todo that
+/// was completed at `completed_at`. It keeps the title, priority, rule and
+/// tags and is due at the next occurrence. A monthly rule is pinned to the
+/// day it was first due on, which the next occurrence may not fall on.
+pub fn next_occurrence(completed: &Todo, completed_at: DateTime<Utc>) -> Option<Todo> {
+    let rule: Recurrence = completed.recurrence.as_deref()?.parse().ok()?;
+    let rule = rule.anchored(completed.due_at.unwrap_or(completed_at));
+    Some(Todo {
+        id: 0,
+        completed: false,
+        completed_at: None,
+        due_at: Some(rule.next_due(completed.due_at, completed_at)),
+        recurrence: Some(rule.to_string()),
+        ..completed.clone()
+    })
+}
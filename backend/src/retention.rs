@@ -0,0 +1,163 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use crate::Todo;
+use crate::attachments::AttachmentConfig;
+use crate::error::ApiError;
+use crate::subtasks;
+use crate::todo_store::TodoStore;
+
+pub const DEFAULT_RETENTION_INTERVAL_MINUTES: u64 = 60;
+
+/// Where the retention rules get the current time from. The server uses
+/// `SystemClock`; tests put in a clock of their own to move time forward.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// When completed todos move to the archive on their own and when archived
+/// todos are deleted for good. Either rule is off while its number of days
+/// is `None`, which is the default.
+#[derive(Clone, Debug)]
+pub struct RetentionConfig {
+    /// Days a todo stays completed before it is archived.
+    pub archive_after_days: Option<u32>,
+    /// Days a todo stays in the archive before it is purged.
+    pub purge_after_days: Option<u32>,
+    /// How often the background task applies the rules.
+    pub interval: std::time::Duration,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            archive_after_days: None,
+            purge_after_days: None,
+            interval: std::time::Duration::from_secs(DEFAULT_RETENTION_INTERVAL_MINUTES * 60),
+        }
+    }
+}
+
+impl RetentionConfig {
+    /// Reads `ARCHIVE_COMPLETED_AFTER_DAYS`, `PURGE_ARCHIVED_AFTER_DAYS`
+    /// and `RETENTION_INTERVAL_MINUTES`. Rules that are missing or not a
+    /// number stay off; an unusable interval falls back to the default.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let days = |name: &str| std::env::var(name).ok().and_then(|value| value.parse().ok());
+        RetentionConfig {
+            archive_after_days: days("ARCHIVE_COMPLETED_AFTER_DAYS"),
+            purge_after_days: days("PURGE_ARCHIVED_AFTER_DAYS"),
+            interval: std::env::var("RETENTION_INTERVAL_MINUTES")
+                .ok()
+                .and_then(|value| value.parse::<u64>().ok())
+                .filter(|minutes| *minutes > 0)
+                .map(|minutes| std::time::Duration::from_secs(minutes * 60))
+                .unwrap_or(defaults.interval),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.archive_after_days.is_some() || self.purge_after_days.is_some()
+    }
+
+    /// Todos completed before this are archived at `now`.
+    pub fn archive_cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.archive_after_days.map(|days| now - Duration::days(days as i64))
+    }
+
+    /// Todos archived before this are purged at `now`.
+    pub fn purge_cutoff(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.purge_after_days.map(|days| now - Duration::days(days as i64))
+    }
+}
+
+/// Response of `GET /api/todos/retention`: what the rules would do to the
+/// list if they were applied now, without doing it.
+#[derive(Serialize, Debug)]
+pub struct RetentionReport {
+    pub archive_after_days: Option<u32>,
+    pub purge_after_days: Option<u32>,
+    /// Completed todos that would move to the archive, parents before
+    /// their subtasks.
+    pub archive: Vec<Todo>,
+    /// Archived todos that would be deleted for good, oldest first.
+    pub purge: Vec<Todo>,
+}
+
+/// What a run of the rules did across all lists.
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct RetentionRun {
+    pub archived: u64,
+    pub purged: u64,
+}
+
+pub async fn report(db: &Arc<dyn TodoStore>, config: &RetentionConfig, list_id: u32, now: DateTime<Utc>)
+    -> Result<RetentionReport, ApiError> {
+    let archive = match config.archive_cutoff(now) {
+        Some(cutoff) => {
+            let todos = db.query_todos(list_id).await?;
+            subtasks::archivable_before(&todos, cutoff).into_iter().cloned().collect()
+        }
+        None => Vec::new(),
+    };
+    let purge = match config.purge_cutoff(now) {
+        Some(cutoff) => db.query_archived_before(list_id, cutoff).await?,
+        None => Vec::new(),
+    };
+    Ok(RetentionReport {
+        archive_after_days: config.archive_after_days,
+        purge_after_days: config.purge_after_days,
+        archive,
+        purge,
+    })
+}
+
+/// Applies the rules to every list as of `now`: archives first, then
+/// purges, removing the files of purged attachments as well.
+pub async fn run(db: &Arc<dyn TodoStore>, attachments: &AttachmentConfig, config: &RetentionConfig,
+    now: DateTime<Utc>) -> Result<RetentionRun, ApiError> {
+    let mut done = RetentionRun::default();
+    for list_id in db.query_list_ids().await? {
+        if let Some(cutoff) = config.archive_cutoff(now) {
+            done.archived += db.archive_completed_before(list_id, cutoff, now).await?;
+        }
+        if let Some(cutoff) = config.purge_cutoff(now) {
+            let before = db.query_list_attachments(list_id).await?;
+            let purged = db.purge_archived_before(list_id, cutoff).await?;
+            if purged > 0 {
+                attachments.remove_dropped(db, list_id, before).await?;
+            }
+            done.purged += purged;
+        }
+    }
+    Ok(done)
+}
+
+/// Starts the background task that calls `run` right away and then every
+/// `config.interval`. A failed run is logged and tried again next time.
+pub fn spawn(db: Arc<dyn TodoStore>, attachments: AttachmentConfig, config: RetentionConfig, clock: Arc<dyn Clock>)
+    -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticks = tokio::time::interval(config.interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            match run(&db, &attachments, &config, clock.now()).await {
+                Ok(RetentionRun { archived: 0, purged: 0 }) => {}
+                Ok(done) => tracing::info!("retention archived {} and purged {} todos", done.archived, done.purged),
+                Err(err) => tracing::warn!("retention run failed: {:?}", err),
+            }
+        }
+    })
+}
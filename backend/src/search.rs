@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+use crate::Todo;
+use crate::error::{ApiError, FieldError};
+
+pub const DEFAULT_SEARCH_LIMIT: u32 = 20;
+pub const MAX_SEARCH_LIMIT: u32 = 100;
+/// How many words of the notes `search_text` shows around the first match.
+pub const NOTES_SNIPPET_WORDS: usize = 20;
+
+/// Marks the start and end of a match in a raw snippet. They cannot appear
+/// in a title that came in as JSON text, so they survive HTML escaping and
+/// are swapped for `<mark>` tags afterwards.
+pub const MATCH_START: &str = "\u{1}";
+pub const MATCH_END: &str = "\u{2}";
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchScope {
+    Active,
+    Archived,
+    #[default]
+    Both,
+}
+
+impl SearchScope {
+    pub fn includes_active(self) -> bool {
+        self != SearchScope::Archived
+    }
+
+    pub fn includes_archived(self) -> bool {
+        self != SearchScope::Active
+    }
+}
+
+/// Query string of `GET /api/search`.
+#[derive(Deserialize, Default)]
+pub struct SearchParams {
+    pub q: Option<String>,
+    pub scope: Option<SearchScope>,
+    pub limit: Option<u32>,
+}
+
+/// A validated `SearchParams`. Every term has to match the start of a word
+/// in the title or the notes.
+#[derive(Clone, Debug)]
+pub struct SearchQuery {
+    pub terms: Vec<String>,
+    pub scope: SearchScope,
+    pub limit: u32,
+}
+
+impl TryFrom<SearchParams> for SearchQuery {
+    type Error = ApiError;
+
+    fn try_from(params: SearchParams) -> Result<Self, ApiError> {
+        let mut errors = Vec::new();
+        let terms: Vec<String> = params.q.as_deref().unwrap_or_default()
+            .split_whitespace()
+            .map(normalize)
+            .filter(|word| !word.is_empty())
+            .collect();
+        if terms.is_empty() {
+            errors.push(FieldError::new("q", "must contain at least one word"));
+        }
+        let limit = params.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+        if limit == 0 || limit > MAX_SEARCH_LIMIT {
+            errors.push(FieldError::new("limit", format!("must be between 1 and {}", MAX_SEARCH_LIMIT)));
+        }
+
+        if !errors.is_empty() {
+            return Err(ApiError::Validation(errors));
+        }
+        Ok(SearchQuery { terms, scope: params.scope.unwrap_or_default(), limit })
+    }
+}
+
+impl SearchQuery {
+    /// The terms as a Postgres `tsquery` where each term matches as a prefix.
+    /// Terms only contain alphanumeric characters, so nothing needs quoting.
+    pub fn to_prefix_tsquery(&self) -> String {
+        self.terms.iter()
+            .map(|term| format!("{}:*", term))
+            .collect::<Vec<_>>()
+            .join(" & ")
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchSource {
+    Active,
+    Archived,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SearchHit {
+    pub id: u32,
+    pub title: String,
+    pub priority: u8,
+    pub completed: bool,
+    pub source: SearchSource,
+    pub rank: f32,
+    /// The title as HTML with every match wrapped in `<mark>`.
+    pub snippet: String,
+    /// Like `snippet`, for the part of the notes around the matches. Only
+    /// set when the notes match.
+    pub notes_snippet: Option<String>,
+}
+
+impl SearchHit {
+    pub fn new(todo: Todo, source: SearchSource, rank: f32, raw_snippet: &str, raw_notes_snippet: Option<&str>) -> Self {
+        SearchHit {
+            id: todo.id,
+            title: todo.title,
+            priority: todo.priority,
+            completed: todo.completed,
+            source,
+            rank,
+            snippet: highlight(raw_snippet),
+            notes_snippet: raw_notes_snippet.map(highlight),
+        }
+    }
+}
+
+/// Lowercases a word and drops everything but letters and digits.
+fn normalize(word: &str) -> String {
+    word.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase()
+}
+
+/// Escapes a snippet for HTML and turns the match markers into `<mark>` tags.
+pub fn highlight(raw_snippet: &str) -> String {
+    raw_snippet
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+        .replace(MATCH_START, "<mark>")
+        .replace(MATCH_END, "</mark>")
+}
+
+/// Searches titles and notes without database support, for the stores
+/// that lack it. A todo matches when every term starts one of the words of
+/// its title or notes; the rank is the share of title words that matched
+/// plus half the share of notes words.
+pub fn search_text(candidates: Vec<(Todo, SearchSource)>, query: &SearchQuery) -> Vec<SearchHit> {
+    let is_match = |word: &str| {
+        let word = normalize(word);
+        query.terms.iter().any(|term| word.starts_with(term.as_str()))
+    };
+    let share = |words: &[&str]| match words.len() {
+        0 => 0.0,
+        total => words.iter().filter(|word| is_match(word)).count() as f32 / total as f32,
+    };
+    let mark = |words: &[&str]| words.iter()
+        .map(|word| if is_match(word) { format!("{}{}{}", MATCH_START, word, MATCH_END) } else { word.to_string() })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let mut hits: Vec<SearchHit> = candidates.into_iter()
+        .filter_map(|(todo, source)| {
+            let title: Vec<&str> = todo.title.split_whitespace().collect();
+            let notes: Vec<&str> = todo.notes.as_deref().unwrap_or_default().split_whitespace().collect();
+            let every_term_found = query.terms.iter().all(|term| {
+                title.iter().chain(&notes).any(|word| normalize(word).starts_with(term.as_str()))
+            });
+            if !every_term_found {
+                return None;
+            }
+            let rank = share(&title) + share(&notes) / 2.0;
+            let notes_snippet = notes.iter().position(|word| is_match(word)).map(|first| {
+                let start = first.saturating_sub(NOTES_SNIPPET_WORDS / 4);
+                let end = (start + NOTES_SNIPPET_WORDS).min(notes.len());
+                let mut snippet = mark(&notes[start..end]);
+                if start > 0 {
+                    snippet.insert_str(0, "… ");
+                }
+                if end < notes.len() {
+                    snippet.push_str(" …");
+                }
+                snippet
+            });
+            let snippet = mark(&title);
+            Some(SearchHit::new(todo, source, rank, &snippet, notes_snippet.as_deref()))
+        })
+        .collect();
+    hits.sort_by(|a, b| b.rank.total_cmp(&a.rank).then(a.id.cmp(&b.id)));
+    hits.truncate(query.limit as usize);
+    hits
+}
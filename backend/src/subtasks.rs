@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::{Todo, UpdateTodo};
+use crate::dependencies;
+use crate::error::{ApiError, FieldError};
+
+/// How many of a todo's direct subtasks are completed.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Progress {
+    pub completed: u32,
+    pub total: u32,
+}
+
+/// A todo of `GET /api/todos/tree` with its subtasks nested inside.
+#[derive(Serialize, Debug, Clone)]
+pub struct TodoNode {
+    #[serde(flatten)]
+    pub todo: Todo,
+    pub progress: Progress,
+    pub subtasks: Vec<TodoNode>,
+}
+
+/// Query string of `PATCH /api/todos/:id`.
+#[derive(Deserialize, Default)]
+pub struct UpdateParams {
+    /// Completing a todo with `cascade=true` also completes its subtasks.
+    pub cascade: Option<bool>,
+}
+
+fn children_by_parent(todos: &[Todo]) -> HashMap<Option<u32>, Vec<&Todo>> {
+    let mut children: HashMap<Option<u32>, Vec<&Todo>> = HashMap::new();
+    for todo in todos {
+        children.entry(todo.parent_id).or_default().push(todo);
+    }
+    children
+}
+
+/// Nests `todos` under their parents, keeping the order of `todos` among
+/// siblings.
+pub fn build_tree(todos: &[Todo]) -> Vec<TodoNode> {
+    fn node(todo: &Todo, children: &HashMap<Option<u32>, Vec<&Todo>>) -> TodoNode {
+        let subtasks: Vec<TodoNode> = children.get(&Some(todo.id))
+            .map(|subtasks| subtasks.iter().map(|subtask| node(subtask, children)).collect())
+            .unwrap_or_default();
+        let progress = Progress {
+            completed: subtasks.iter().filter(|subtask| subtask.todo.completed).count() as u32,
+            total: subtasks.len() as u32,
+        };
+        TodoNode { todo: todo.clone(), progress, subtasks }
+    }
+
+    let children = children_by_parent(todos);
+    children.get(&None)
+        .map(|roots| roots.iter().map(|root| node(root, &children)).collect())
+        .unwrap_or_default()
+}
+
+/// The ids of every todo below `todo_id`, parents before their subtasks.
+pub fn descendants(todos: &[Todo], todo_id: u32) -> Vec<u32> {
+    let children = children_by_parent(todos);
+    let mut found = Vec::new();
+    let mut next = vec![todo_id];
+    while let Some(id) = next.pop() {
+        for child in children.get(&Some(id)).into_iter().flatten() {
+            found.push(child.id);
+            next.push(child.id);
+        }
+    }
+    found
+}
+
+/// Checks that `parent_id` names one of `todos` that may become the parent
+/// of `todo_id`, a new todo if `None`: moving a todo below itself or below
+/// one of its own subtasks would make a cycle.
+pub fn check_parent(todos: &[Todo], todo_id: Option<u32>, parent_id: u32) -> Result<(), FieldError> {
+    if !todos.iter().any(|todo| todo.id == parent_id) {
+        return Err(FieldError::new("parent_id", "must be a todo of the same list"));
+    }
+    if let Some(todo_id) = todo_id
+        && (parent_id == todo_id || descendants(todos, todo_id).contains(&parent_id))
+    {
+        return Err(FieldError::new("parent_id", "must not be the todo itself or one of its subtasks"));
+    }
+    Ok(())
+}
+
+/// Checks `update`, a `PATCH /api/todos/:id` of `todo_id`, against `todos`,
+/// the todos of the list with `blocked` set: a new `parent_id` has to pass
+/// `check_parent`, and neither the todo nor, with `cascade`, its subtasks
+/// may be completed while blocked. Returns the open subtasks that are
+/// completed along with the todo.
+pub fn check_update(todos: &[Todo], todo_id: u32, update: &UpdateTodo, cascade: bool) -> Result<Vec<u32>, ApiError> {
+    if !todos.iter().any(|todo| todo.id == todo_id) {
+        return Err(ApiError::todo_not_found(todo_id as u64));
+    }
+    if let Some(Some(parent_id)) = update.parent_id {
+        check_parent(todos, Some(todo_id), parent_id).map_err(|error| ApiError::Validation(vec![error]))?;
+    }
+    if update.completed != Some(true) {
+        return Ok(Vec::new());
+    }
+    let mut subtasks = Vec::new();
+    if cascade {
+        subtasks = descendants(todos, todo_id).into_iter()
+            .filter(|subtask| todos.iter().any(|todo| todo.id == *subtask && !todo.completed))
+            .collect();
+    }
+    dependencies::check_unblocked(todos, std::iter::once(todo_id).chain(subtasks.iter().copied()))?;
+    Ok(subtasks)
+}
+
+/// The todo named `todo_id` with all of its subtasks, parents before their
+/// subtasks.
+pub fn subtree(todos: &[Todo], todo_id: u32) -> Vec<&Todo> {
+    let by_id: HashMap<u32, &Todo> = todos.iter().map(|todo| (todo.id, todo)).collect();
+    by_id.get(&todo_id).into_iter().copied()
+        .chain(descendants(todos, todo_id).iter().map(|id| by_id[id]))
+        .collect()
+}
+
+/// The todos that `archive_completed_todos` moves, parents before their
+/// subtasks. Trees are archived whole: a top-level todo goes together with
+/// all of its subtasks once every one of them is completed.
+pub fn archivable(todos: &[Todo]) -> Vec<&Todo> {
+    archivable_where(todos, |todo| todo.completed)
+}
+
+/// Like `archivable`, for trees whose todos were all completed before
+/// `completed_before`. Todos completed before completion times were kept
+/// have no `completed_at` and are left alone.
+pub fn archivable_before(todos: &[Todo], completed_before: DateTime<Utc>) -> Vec<&Todo> {
+    archivable_where(todos, |todo| todo.completed && todo.completed_at.is_some_and(|at| at < completed_before))
+}
+
+fn archivable_where(todos: &[Todo], done: impl Fn(&Todo) -> bool) -> Vec<&Todo> {
+    let mut archived = Vec::new();
+    for root in todos.iter().filter(|todo| todo.parent_id.is_none()) {
+        let subtree = subtree(todos, root.id);
+        if subtree.iter().all(|todo| done(todo)) {
+            archived.extend(subtree);
+        }
+    }
+    archived
+}
+
+/// The archived todos that restoring `ids` brings back, parents before
+/// their subtasks: each named todo comes with all of its archived subtasks,
+/// just like trees are archived whole. Fails with the first id that is not
+/// one of `archived`.
+pub fn restorable<'a>(archived: &'a [Todo], ids: &[u32]) -> Result<Vec<&'a Todo>, u32> {
+    let by_id: HashMap<u32, &Todo> = archived.iter().map(|todo| (todo.id, todo)).collect();
+    let mut selected: Vec<u32> = Vec::new();
+    for id in ids {
+        if !by_id.contains_key(id) {
+            return Err(*id);
+        }
+        selected.push(*id);
+        selected.extend(descendants(archived, *id));
+    }
+    let roots = archived.iter()
+        .filter(|todo| selected.contains(&todo.id))
+        .filter(|todo| !todo.parent_id.is_some_and(|parent_id| selected.contains(&parent_id)));
+    let mut restored = Vec::new();
+    for root in roots {
+        restored.push(root);
+        restored.extend(descendants(archived, root.id).iter().map(|id| by_id[id]));
+    }
+    Ok(restored)
+}
@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+/// Given to tags that are created without a color.
+pub const DEFAULT_TAG_COLOR: &str = "#808080";
+pub const MAX_TAG_NAME_LENGTH: usize = 50;
+
+/// A label that can be put on any number of todos of its list. Names are
+/// unique within a list.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Tag {
+    pub id: u32,
+    pub list_id: u32,
+    pub name: String,
+    /// A `#rrggbb` color in lowercase.
+    pub color: String,
+}
+
+/// Body of `POST /api/todos/:id/tags`. The tag is created on first use; a
+/// color given for an existing tag recolors it everywhere.
+#[derive(Deserialize)]
+pub struct TagPayload {
+    pub name: String,
+    pub color: Option<String>,
+}
+
+/// Path of `DELETE /api/todos/:id/tags/:name`.
+#[derive(Deserialize)]
+pub struct TodoTagPath {
+    pub id: u32,
+    pub name: String,
+}
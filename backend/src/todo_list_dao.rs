@@ -1,6 +1,21 @@
-use sqlx::{postgres::PgPoolOptions, Row};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::{PgPoolOptions, PgRow}, Row};
 use dotenvy::dotenv;
-use crate::Todo;
+use crate::{Todo, TodoList, UpdateTodo};
+use crate::api_token::{self, ApiToken, Scope};
+use crate::attachments::Attachment;
+use crate::auth::User;
+use crate::dependencies::{self, Dependency};
+use crate::error::ApiError;
+use crate::membership::{ListInvite, ListMember, Role};
+use crate::migrations;
+use crate::recurrence;
+use crate::subtasks;
+use crate::search::{self, SearchHit, SearchQuery, SearchSource};
+use crate::tags::{Tag, DEFAULT_TAG_COLOR};
+use crate::todo_query::{SqlValue, TodoPage, TodoQuery};
+use crate::todo_store::TodoStore;
 
 pub struct TodoListDao {
     database: sqlx::Pool<sqlx::Postgres>,
@@ -11,10 +26,13 @@ impl TodoListDao {
         dotenv().ok();
         let database_url = std::env::var("DATABASE_URL")
             .expect("DATABASE_URL must be set");
+        Self::connect(&database_url).await
+    }
 
+    pub async fn connect(database_url: &str) -> Result<Self, sqlx::Error> {
         let pool = PgPoolOptions::new()
             .max_connections(5)
-            .connect(&database_url)
+            .connect(database_url)
             .await?;
 
         Ok(Self { database: pool })
@@ -29,152 +47,1105 @@ impl TodoListDao {
         self.database.size() == 0
     }
 
-    pub async fn initialize(&self) {
-        self.drop_todos_table().await.ok().unwrap(); 
-        self.drop_archived_table().await.ok().unwrap();
-        self.create_todos_table().await.ok().unwrap();
-        self.create_archived_table().await.ok().unwrap();
+    /// Drops every table in the database, including `schema_migrations`, so
+    /// that the next `migrate` starts from an empty schema.
+    pub async fn drop_all_tables(&self) -> Result<&'static str, sqlx::Error> {
+        let tables: Vec<String> = sqlx::query_scalar(
+            "SELECT tablename FROM pg_tables WHERE schemaname = current_schema()"
+        )
+        .fetch_all(&self.database)
+        .await?;
+
+        let mut tx = self.database.begin().await?;
+        for table in tables {
+            sqlx::query(&format!("DROP TABLE IF EXISTS \"{}\" CASCADE", table))
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok("All tables dropped successfully")
+    }
+
+    /// Fills in the tags of `todos`, which are rows of `todos` or, with
+    /// `archived` set, of `archived`, and the `blocked` flag of active todos.
+    async fn with_details(&self, todos: Vec<Todo>, archived: bool) -> Result<Vec<Todo>, ApiError> {
+        let mut connection = self.database.acquire().await?;
+        todo_details(&mut connection, todos, archived).await
+    }
+
+    async fn with_details_or_not_found(&self, row: Option<PgRow>, todo_id: u64) -> Result<Todo, ApiError> {
+        let todo = todo_or_not_found(row, todo_id)?;
+        Ok(self.with_details(vec![todo], false).await?.remove(0))
+    }
+}
+
+/// `with_details` on `connection`, which may be a transaction that has
+/// not been committed yet.
+async fn todo_details(connection: &mut sqlx::PgConnection, mut todos: Vec<Todo>, archived: bool) -> Result<Vec<Todo>, ApiError> {
+    if todos.is_empty() {
+        return Ok(todos);
+    }
+    let sql = if archived {
+        "SELECT archived_tags.archived_id AS todo_id, tags.*
+         FROM archived_tags JOIN tags ON tags.id = archived_tags.tag_id
+         WHERE archived_tags.archived_id = ANY($1) ORDER BY tags.name"
+    } else {
+        "SELECT todo_tags.todo_id, tags.*
+         FROM todo_tags JOIN tags ON tags.id = todo_tags.tag_id
+         WHERE todo_tags.todo_id = ANY($1) ORDER BY tags.name"
+    };
+    let ids: Vec<i32> = todos.iter().map(|todo| todo.id as i32).collect();
+    let rows: Vec<PgRow> = sqlx::query(sql)
+        .bind(&ids)
+        .fetch_all(&mut *connection)
+        .await?;
+    for row in &rows {
+        let todo_id: i32 = row.get("todo_id");
+        if let Some(todo) = todos.iter_mut().find(|todo| todo.id == todo_id as u32) {
+            todo.tags.push(tag_from_row(row));
+        }
+    }
+    if archived {
+        return Ok(todos);
+    }
+
+    let blocked: Vec<i32> = sqlx::query_scalar(
+        "SELECT DISTINCT todo_dependencies.todo_id
+         FROM todo_dependencies JOIN todos ON todos.id = todo_dependencies.blocker_id
+         WHERE todo_dependencies.todo_id = ANY($1) AND NOT todos.completed"
+    )
+    .bind(&ids)
+    .fetch_all(&mut *connection)
+    .await?;
+    for todo in &mut todos {
+        todo.blocked = blocked.contains(&(todo.id as i32));
     }
+    Ok(todos)
+}
+
+/// The todos of the list, locked until the transaction ends so that nobody
+/// completes, reopens or moves them while they are being archived.
+async fn list_todos(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, list_id: u32) -> Result<Vec<Todo>, ApiError> {
+    let rows: Vec<PgRow> = sqlx::query("SELECT * FROM todos WHERE list_id = $1 ORDER BY id FOR UPDATE")
+        .bind(list_id as i32)
+        .fetch_all(&mut **tx)
+        .await?;
+    Ok(rows.iter().map(todo_from_row).collect())
+}
 
-    pub async fn create_todos_table(&self) -> Result<&'static str, sqlx::Error> {
+/// Locks the todo until the transaction ends, failing if the list has no
+/// such todo.
+async fn lock_todo(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, list_id: u32, todo_id: u64) -> Result<(), ApiError> {
+    let row: Option<PgRow> = sqlx::query("SELECT id FROM todos WHERE id = $1 AND list_id = $2 FOR UPDATE")
+        .bind(todo_id as i32)
+        .bind(list_id as i32)
+        .fetch_optional(&mut **tx)
+        .await?;
+    row.map(|_| ()).ok_or_else(|| ApiError::todo_not_found(todo_id))
+}
+
+/// Applies the fields that are set in `update`, creating the next
+/// occurrence of a recurring todo that this completes. `None` if the list
+/// has no such todo.
+async fn apply_update(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, list_id: u32, todo_id: u64, update: &UpdateTodo)
+    -> Result<Option<PgRow>, ApiError> {
+    let row: Option<PgRow> = sqlx::query(
+        "UPDATE todos SET
+            title = COALESCE($1, title),
+            priority = COALESCE($2, priority),
+            completed = COALESCE($3, completed),
+            completed_at = CASE WHEN NOT COALESCE($3, completed) THEN NULL WHEN completed THEN completed_at ELSE $14 END,
+            due_at = CASE WHEN $6 THEN $7 ELSE due_at END,
+            recurrence = CASE WHEN $8 THEN $9 ELSE recurrence END,
+            parent_id = CASE WHEN $10 THEN $11 ELSE parent_id END,
+            notes = CASE WHEN $12 THEN $13 ELSE notes END
+         WHERE id = $4 AND list_id = $5
+         RETURNING *"
+    )
+    .bind(update.title.as_deref())
+    .bind(update.priority.map(|p| p as i32))
+    .bind(update.completed)
+    .bind(todo_id as i32)
+    .bind(list_id as i32)
+    .bind(update.due_at.is_some())
+    .bind(update.due_at.flatten())
+    .bind(update.recurrence.is_some())
+    .bind(update.recurrence.clone().flatten())
+    .bind(update.parent_id.is_some())
+    .bind(update.parent_id.flatten().map(|id| id as i32))
+    .bind(update.notes.is_some())
+    .bind(update.notes.clone().flatten())
+    .bind(Utc::now())
+    .fetch_optional(&mut **tx)
+    .await?;
+    if let Some(row) = &row {
+        recur(tx, row).await?;
+    }
+    Ok(row)
+}
+
+/// The dependencies between the todos of the list, by todo and blocker.
+async fn list_dependencies<'e>(executor: impl sqlx::PgExecutor<'e>, list_id: u32) -> Result<Vec<Dependency>, ApiError> {
+    let rows: Vec<(i32, i32)> = sqlx::query_as(
+        "SELECT todo_dependencies.todo_id, todo_dependencies.blocker_id
+         FROM todo_dependencies JOIN todos ON todos.id = todo_dependencies.todo_id
+         WHERE todos.list_id = $1 ORDER BY todo_dependencies.todo_id, todo_dependencies.blocker_id"
+    )
+    .bind(list_id as i32)
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter()
+        .map(|(todo_id, blocker_id)| Dependency { todo_id: todo_id as u32, blocker_id: blocker_id as u32 })
+        .collect())
+}
+
+/// The todos of the list as `list_todos` locks them, marked blocked for
+/// `subtasks::check_update`.
+async fn checked_todos(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, list_id: u32) -> Result<Vec<Todo>, ApiError> {
+    let mut todos = list_todos(tx, list_id).await?;
+    let dependencies = list_dependencies(&mut **tx, list_id).await?;
+    dependencies::mark_blocked(&mut todos, &dependencies);
+    Ok(todos)
+}
+
+/// Moves `todos`, parents before their subtasks, to the archive under the
+/// ids they have, tags and attachments included, as archived at
+/// `archived_at`. A subtask whose parent stays behind becomes a top-level
+/// archived todo.
+async fn archive(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, todos: &[&Todo], archived_at: DateTime<Utc>) -> Result<(), ApiError> {
+    let todo_ids: Vec<i32> = todos.iter().map(|todo| todo.id as i32).collect();
+    for todo in todos {
+        let parent_id = todo.parent_id.map(|parent_id| parent_id as i32).filter(|parent_id| todo_ids.contains(parent_id));
         sqlx::query(
-            "CREATE TABLE IF NOT EXISTS todos (
-                id SERIAL PRIMARY KEY,
-                title TEXT NOT NULL,
-                priority INT NOT NULL,
-                completed BOOLEAN DEFAULT FALSE,
-                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-            )"
+            "INSERT INTO archived (id, list_id, title, priority, completed, completed_at, due_at, recurrence, parent_id, notes, created_at, archived_at)
+             SELECT id, list_id, title, priority, completed, completed_at, due_at, recurrence, $2, notes, created_at, $3
+             FROM todos WHERE id = $1"
         )
-        .execute(&self.database)
+        .bind(todo.id as i32)
+        .bind(parent_id)
+        .bind(archived_at)
+        .execute(&mut **tx)
+        .await?;
+        sqlx::query("INSERT INTO archived_tags (archived_id, tag_id) SELECT todo_id, tag_id FROM todo_tags WHERE todo_id = $1")
+            .bind(todo.id as i32)
+            .execute(&mut **tx)
+            .await?;
+        sqlx::query("UPDATE attachments SET todo_id = NULL, archived_id = $1 WHERE todo_id = $1")
+            .bind(todo.id as i32)
+            .execute(&mut **tx)
+            .await?;
+    }
+    sqlx::query("DELETE FROM todos WHERE id = ANY($1)")
+        .bind(&todo_ids)
+        .execute(&mut **tx)
         .await?;
-        Ok("Database table created successfully")
+    Ok(())
+}
+
+/// Creates the next occurrence of `row`, a todo that was just updated,
+/// if it is a completed recurring todo that has not recurred yet.
+async fn recur(tx: &mut sqlx::Transaction<'_, sqlx::Postgres>, row: &PgRow) -> Result<(), ApiError> {
+    let todo = todo_from_row(row);
+    if !todo.completed || row.get::<bool, _>("recurred") {
+        return Ok(());
     }
+    let Some(next) = recurrence::next_occurrence(&todo, Utc::now()) else {
+        return Ok(());
+    };
+    let next_id: i32 = sqlx::query_scalar(
+        "INSERT INTO todos (list_id, title, priority, completed, due_at, recurrence, parent_id, notes)
+         VALUES ($1, $2, $3, FALSE, $4, $5, $6, $7) RETURNING id"
+    )
+    .bind(next.list_id as i32)
+    .bind(&next.title)
+    .bind(next.priority as i32)
+    .bind(next.due_at)
+    .bind(&next.recurrence)
+    .bind(next.parent_id.map(|id| id as i32))
+    .bind(&next.notes)
+    .fetch_one(&mut **tx)
+    .await?;
+    sqlx::query("INSERT INTO todo_tags (todo_id, tag_id) SELECT $1, tag_id FROM todo_tags WHERE todo_id = $2")
+        .bind(next_id)
+        .bind(todo.id as i32)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("UPDATE todos SET recurred = TRUE WHERE id = $1")
+        .bind(todo.id as i32)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+fn todo_or_not_found(row: Option<PgRow>, todo_id: u64) -> Result<Todo, ApiError> {
+    row.as_ref()
+        .map(todo_from_row)
+        .ok_or_else(|| ApiError::todo_not_found(todo_id))
+}
+
+fn todo_from_row(row: &PgRow) -> Todo {
+    let id: i32 = row.get("id");
+    let list_id: i32 = row.get("list_id");
+    let priority: i32 = row.get("priority");
+    Todo {
+        id: id as u32,
+        list_id: list_id as u32,
+        title: row.get("title"),
+        priority: priority as u8,
+        completed: row.get("completed"),
+        completed_at: row.get("completed_at"),
+        due_at: row.get("due_at"),
+        recurrence: row.get("recurrence"),
+        parent_id: row.get::<Option<i32>, _>("parent_id").map(|id| id as u32),
+        notes: row.get("notes"),
+        tags: Vec::new(),
+        blocked: false,
+    }
+}
+
+fn attachment_from_row(row: &PgRow) -> Attachment {
+    let id: i32 = row.get("id");
+    let todo_id: Option<i32> = row.get("todo_id");
+    let archived_id: Option<i32> = row.get("archived_id");
+    let size: i64 = row.get("size");
+    Attachment {
+        id: id as u32,
+        todo_id: todo_id.or(archived_id).unwrap_or_default() as u32,
+        archived: todo_id.is_none(),
+        file_name: row.get("file_name"),
+        content_type: row.get("content_type"),
+        size: size as u64,
+        sha256: row.get("sha256"),
+        created_at: row.get("created_at"),
+        storage_key: row.get("storage_key"),
+    }
+}
+
+fn tag_from_row(row: &PgRow) -> Tag {
+    let id: i32 = row.get("id");
+    let list_id: i32 = row.get("list_id");
+    Tag {
+        id: id as u32,
+        list_id: list_id as u32,
+        name: row.get("name"),
+        color: row.get("color"),
+    }
+}
+
+fn list_or_not_found(row: Option<PgRow>, list_id: u32) -> Result<TodoList, ApiError> {
+    row.as_ref()
+        .map(list_from_row)
+        .ok_or_else(|| ApiError::list_not_found(list_id))
+}
+
+fn list_from_row(row: &PgRow) -> TodoList {
+    let id: i32 = row.get("id");
+    let owner_id: Option<i32> = row.get("owner_id");
+    TodoList {
+        id: id as u32,
+        owner_id: owner_id.map(|id| id as u32),
+        name: row.get("name"),
+        is_default: row.get("is_default"),
+    }
+}
+
+fn user_from_row(row: &PgRow) -> User {
+    let id: i32 = row.get("id");
+    User {
+        id: id as u32,
+        email: row.get("email"),
+    }
+}
 
-    pub async fn create_archived_table(&self) -> Result<&'static str, sqlx::Error> {
+fn api_token_from_row(row: &PgRow) -> ApiToken {
+    let id: i32 = row.get("id");
+    let user_id: i32 = row.get("user_id");
+    let scopes: String = row.get("scopes");
+    ApiToken {
+        id: id as u32,
+        user_id: user_id as u32,
+        name: row.get("name"),
+        scopes: api_token::split_scopes(&scopes),
+        created_at: row.get("created_at"),
+        expires_at: row.get("expires_at"),
+        last_used_at: row.get("last_used_at"),
+    }
+}
+
+/// Roles are kept in check by the `*_role` CHECK constraints.
+fn role_from_row(row: &PgRow) -> Role {
+    let role: String = row.get("role");
+    role.parse().expect("the role constraint only allows known roles")
+}
+
+fn member_from_row(row: &PgRow) -> ListMember {
+    let list_id: i32 = row.get("list_id");
+    let user_id: i32 = row.get("user_id");
+    ListMember {
+        list_id: list_id as u32,
+        user_id: user_id as u32,
+        email: row.get("email"),
+        role: role_from_row(row),
+    }
+}
+
+#[async_trait]
+impl TodoStore for TodoListDao {
+    async fn initialize(&self) {
+        self.drop_all_tables().await.unwrap();
+        self.migrate().await.unwrap();
+    }
+
+    async fn migrate(&self) -> Result<Vec<i64>, sqlx::Error> {
+        migrations::run_postgres(&self.database).await
+    }
+
+    async fn create_user(&self, email: &str, password_hash: &str) -> Result<User, ApiError> {
+        let mut tx = self.database.begin().await?;
+        let row: PgRow = sqlx::query("INSERT INTO users (email, password_hash) VALUES ($1, $2) RETURNING id, email")
+            .bind(email)
+            .bind(password_hash)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|err| match ApiError::from(err) {
+                ApiError::Conflict(_) => ApiError::email_taken(),
+                other => other,
+            })?;
+        let user = user_from_row(&row);
+
+        sqlx::query("UPDATE lists SET owner_id = $1 WHERE owner_id IS NULL")
+            .bind(user.id as i32)
+            .execute(&mut *tx)
+            .await?;
         sqlx::query(
-            "CREATE TABLE IF NOT EXISTS archived (
-                id SERIAL PRIMARY KEY,
-                title TEXT NOT NULL,
-                priority INT NOT NULL,
-                completed BOOLEAN DEFAULT FALSE,
-                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-                archived_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-            )"
+            "INSERT INTO lists (owner_id, name, is_default)
+             SELECT $1, 'Default', TRUE
+             WHERE NOT EXISTS (SELECT 1 FROM lists WHERE owner_id = $1 AND is_default)"
         )
-        .execute(&self.database)
+        .bind(user.id as i32)
+        .execute(&mut *tx)
         .await?;
-        Ok("Archived table created successfully")
+        sqlx::query("INSERT INTO list_members (list_id, user_id, role) SELECT id, $1, 'owner' FROM lists WHERE owner_id = $1")
+            .bind(user.id as i32)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(user)
     }
 
-     pub async fn drop_todos_table(&self) -> Result<&'static str, sqlx::Error> {
-        sqlx::query("DROP TABLE IF EXISTS todos")
+    async fn query_credentials(&self, email: &str) -> Result<Option<(User, String)>, ApiError> {
+        let row: Option<PgRow> = sqlx::query("SELECT id, email, password_hash FROM users WHERE email = $1")
+            .bind(email)
+            .fetch_optional(&self.database)
+            .await?;
+        Ok(row.map(|row| (user_from_row(&row), row.get("password_hash"))))
+    }
+
+    async fn query_identity_user(&self, issuer: &str, subject: &str) -> Result<Option<User>, ApiError> {
+        let row: Option<PgRow> = sqlx::query(
+            "SELECT users.id, users.email
+             FROM user_identities JOIN users ON users.id = user_identities.user_id
+             WHERE user_identities.issuer = $1 AND user_identities.subject = $2"
+        )
+        .bind(issuer)
+        .bind(subject)
+        .fetch_optional(&self.database)
+        .await?;
+        Ok(row.as_ref().map(user_from_row))
+    }
+
+    async fn link_identity(&self, issuer: &str, subject: &str, user_id: u32) -> Result<(), ApiError> {
+        sqlx::query("INSERT INTO user_identities (issuer, subject, user_id) VALUES ($1, $2, $3)")
+            .bind(issuer)
+            .bind(subject)
+            .bind(user_id as i32)
             .execute(&self.database)
             .await?;
-        Ok("All tables dropped successfully")
+        Ok(())
+    }
+
+    async fn create_session(&self, token_hash: &str, user_id: u32, expires_at: DateTime<Utc>) -> Result<(), ApiError> {
+        sqlx::query("INSERT INTO sessions (token_hash, user_id, expires_at) VALUES ($1, $2, $3)")
+            .bind(token_hash)
+            .bind(user_id as i32)
+            .bind(expires_at)
+            .execute(&self.database)
+            .await?;
+        Ok(())
+    }
+
+    async fn query_session_user(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<User>, ApiError> {
+        let row: Option<PgRow> = sqlx::query(
+            "SELECT users.id, users.email
+             FROM sessions JOIN users ON users.id = sessions.user_id
+             WHERE sessions.token_hash = $1 AND sessions.expires_at > $2"
+        )
+        .bind(token_hash)
+        .bind(now)
+        .fetch_optional(&self.database)
+        .await?;
+        Ok(row.as_ref().map(user_from_row))
+    }
+
+    async fn delete_session(&self, token_hash: &str) -> Result<(), ApiError> {
+        sqlx::query("DELETE FROM sessions WHERE token_hash = $1")
+            .bind(token_hash)
+            .execute(&self.database)
+            .await?;
+        Ok(())
+    }
+
+    async fn create_api_token(&self, user_id: u32, name: &str, token_hash: &str, scopes: &[Scope], expires_at: DateTime<Utc>)
+        -> Result<ApiToken, ApiError> {
+        let row: PgRow = sqlx::query(
+            "INSERT INTO api_tokens (user_id, name, token_hash, scopes, created_at, expires_at)
+             VALUES ($1, $2, $3, $4, $5, $6) RETURNING *"
+        )
+        .bind(user_id as i32)
+        .bind(name)
+        .bind(token_hash)
+        .bind(api_token::join_scopes(scopes))
+        .bind(Utc::now())
+        .bind(expires_at)
+        .fetch_one(&self.database)
+        .await?;
+        Ok(api_token_from_row(&row))
+    }
+
+    async fn query_api_tokens(&self, user_id: u32) -> Result<Vec<ApiToken>, ApiError> {
+        let rows: Vec<PgRow> = sqlx::query("SELECT * FROM api_tokens WHERE user_id = $1 ORDER BY id DESC")
+            .bind(user_id as i32)
+            .fetch_all(&self.database)
+            .await?;
+        Ok(rows.iter().map(api_token_from_row).collect())
+    }
+
+    async fn use_api_token(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<(User, ApiToken)>, ApiError> {
+        let mut tx = self.database.begin().await?;
+        let row: Option<PgRow> = sqlx::query(
+            "UPDATE api_tokens SET last_used_at = $2 WHERE token_hash = $1 AND expires_at > $2 RETURNING *"
+        )
+        .bind(token_hash)
+        .bind(now)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(api_token) = row.as_ref().map(api_token_from_row) else {
+            return Ok(None);
+        };
+        let user: PgRow = sqlx::query("SELECT id, email FROM users WHERE id = $1")
+            .bind(api_token.user_id as i32)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(Some((user_from_row(&user), api_token)))
+    }
+
+    async fn delete_api_token(&self, user_id: u32, token_id: u32) -> Result<ApiToken, ApiError> {
+        let row: Option<PgRow> = sqlx::query("DELETE FROM api_tokens WHERE id = $1 AND user_id = $2 RETURNING *")
+            .bind(token_id as i32)
+            .bind(user_id as i32)
+            .fetch_optional(&self.database)
+            .await?;
+        row.as_ref().map(api_token_from_row).ok_or_else(|| ApiError::api_token_not_found(token_id))
+    }
+
+    async fn query_lists(&self, user_id: u32) -> Result<Vec<TodoList>, ApiError> {
+        let rows: Vec<PgRow> = sqlx::query(
+            "SELECT lists.* FROM lists JOIN list_members ON list_members.list_id = lists.id
+             WHERE list_members.user_id = $1
+             ORDER BY (lists.owner_id = $1 AND lists.is_default) DESC, lists.id"
+        )
+        .bind(user_id as i32)
+        .fetch_all(&self.database)
+        .await?;
+        Ok(rows.iter().map(list_from_row).collect())
+    }
+
+    async fn query_list_ids(&self) -> Result<Vec<u32>, ApiError> {
+        let ids: Vec<i32> = sqlx::query_scalar("SELECT id FROM lists ORDER BY id")
+            .fetch_all(&self.database)
+            .await?;
+        Ok(ids.into_iter().map(|id| id as u32).collect())
+    }
+
+    async fn query_default_list(&self, owner_id: u32) -> Result<TodoList, ApiError> {
+        let row: PgRow = sqlx::query("SELECT * FROM lists WHERE owner_id = $1 AND is_default")
+            .bind(owner_id as i32)
+            .fetch_one(&self.database)
+            .await?;
+        Ok(list_from_row(&row))
+    }
+
+    async fn query_list(&self, list_id: u32) -> Result<TodoList, ApiError> {
+        let row: Option<PgRow> = sqlx::query("SELECT * FROM lists WHERE id = $1")
+            .bind(list_id as i32)
+            .fetch_optional(&self.database)
+            .await?;
+        list_or_not_found(row, list_id)
+    }
+
+    async fn create_list(&self, owner_id: u32, name: String) -> Result<TodoList, ApiError> {
+        let mut tx = self.database.begin().await?;
+        let row: PgRow = sqlx::query("INSERT INTO lists (owner_id, name) VALUES ($1, $2) RETURNING *")
+            .bind(owner_id as i32)
+            .bind(name)
+            .fetch_one(&mut *tx)
+            .await?;
+        let list = list_from_row(&row);
+        sqlx::query("INSERT INTO list_members (list_id, user_id, role) VALUES ($1, $2, 'owner')")
+            .bind(list.id as i32)
+            .bind(owner_id as i32)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(list)
+    }
+
+    async fn rename_list(&self, list_id: u32, name: String) -> Result<TodoList, ApiError> {
+        let row: Option<PgRow> = sqlx::query("UPDATE lists SET name = $1 WHERE id = $2 RETURNING *")
+            .bind(name)
+            .bind(list_id as i32)
+            .fetch_optional(&self.database)
+            .await?;
+        list_or_not_found(row, list_id)
     }
 
-    pub async fn drop_archived_table(&self) -> Result<&'static str, sqlx::Error> {
-        sqlx::query("DROP TABLE IF EXISTS archived")
+    async fn delete_list(&self, list_id: u32) -> Result<TodoList, ApiError> {
+        let row: Option<PgRow> = sqlx::query("DELETE FROM lists WHERE id = $1 RETURNING *")
+            .bind(list_id as i32)
+            .fetch_optional(&self.database)
+            .await?;
+        list_or_not_found(row, list_id)
+    }
+
+    async fn query_member_role(&self, list_id: u32, user_id: u32) -> Result<Option<Role>, ApiError> {
+        let row: Option<PgRow> = sqlx::query("SELECT role FROM list_members WHERE list_id = $1 AND user_id = $2")
+            .bind(list_id as i32)
+            .bind(user_id as i32)
+            .fetch_optional(&self.database)
+            .await?;
+        Ok(row.as_ref().map(role_from_row))
+    }
+
+    async fn query_members(&self, list_id: u32) -> Result<Vec<ListMember>, ApiError> {
+        let rows: Vec<PgRow> = sqlx::query(
+            "SELECT list_members.list_id, list_members.user_id, users.email, list_members.role
+             FROM list_members JOIN users ON users.id = list_members.user_id
+             WHERE list_members.list_id = $1
+             ORDER BY list_members.user_id"
+        )
+        .bind(list_id as i32)
+        .fetch_all(&self.database)
+        .await?;
+        Ok(rows.iter().map(member_from_row).collect())
+    }
+
+    async fn create_invite(&self, list_id: u32, email: &str, role: Role, token_hash: &str, expires_at: DateTime<Utc>)
+        -> Result<ListInvite, ApiError> {
+        sqlx::query("INSERT INTO list_invites (token_hash, list_id, email, role, expires_at) VALUES ($1, $2, $3, $4, $5)")
+            .bind(token_hash)
+            .bind(list_id as i32)
+            .bind(email)
+            .bind(role.as_str())
+            .bind(expires_at)
             .execute(&self.database)
             .await?;
-        Ok("Archived table dropped successfully")
+        Ok(ListInvite { list_id, email: email.to_string(), role, expires_at })
     }
 
-    pub async fn truncate_todos_table(&self) -> Result<&'static str, sqlx::Error> {
-        sqlx::query("TRUNCATE TABLE todos")
+    async fn accept_invite(&self, token_hash: &str, user: &User, now: DateTime<Utc>) -> Result<ListMember, ApiError> {
+        let mut tx = self.database.begin().await?;
+        let invite: PgRow = sqlx::query(
+            "DELETE FROM list_invites WHERE token_hash = $1 AND email = $2 AND expires_at > $3 RETURNING list_id, role"
+        )
+        .bind(token_hash)
+        .bind(&user.email)
+        .bind(now)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(ApiError::invite_not_found)?;
+        let list_id: i32 = invite.get("list_id");
+        let current: Option<PgRow> = sqlx::query("SELECT role FROM list_members WHERE list_id = $1 AND user_id = $2")
+            .bind(list_id)
+            .bind(user.id as i32)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let role = role_from_row(&invite).max(current.as_ref().map_or(Role::Viewer, role_from_row));
+        sqlx::query(
+            "INSERT INTO list_members (list_id, user_id, role) VALUES ($1, $2, $3)
+             ON CONFLICT (list_id, user_id) DO UPDATE SET role = excluded.role"
+        )
+        .bind(list_id)
+        .bind(user.id as i32)
+        .bind(role.as_str())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(ListMember { list_id: list_id as u32, user_id: user.id, email: user.email.clone(), role })
+    }
+
+    async fn truncate_todos_table(&self, list_id: u32) -> Result<&'static str, ApiError> {
+        sqlx::query("DELETE FROM todos WHERE list_id = $1")
+            .bind(list_id as i32)
             .execute(&self.database)
             .await?;
         Ok("All tables truncated successfully")
     }
    
-    pub async fn query_todos(&self) -> Result<Vec<sqlx::postgres::PgRow>, sqlx::Error> {
-        let todos: Vec<sqlx::postgres::PgRow> = sqlx::query("
-            SELECT id, title, priority, completed
-            FROM todos
-            ORDER BY priority DESC, created_at ASC")
+    async fn query_todo_page(&self, list_id: u32, query: &TodoQuery) -> Result<TodoPage, ApiError> {
+        let (sql, values) = query.to_sql(list_id);
+        let mut statement = sqlx::query(&sql);
+        for value in values {
+            statement = match value {
+                SqlValue::Int(value) => statement.bind(value),
+                SqlValue::Text(value) => statement.bind(value),
+                SqlValue::Bool(value) => statement.bind(value),
+                SqlValue::Timestamp(value) => statement.bind(value),
+            };
+        }
+        let rows: Vec<PgRow> = statement
             .fetch_all(&self.database)
             .await?;
-        Ok(todos)
+        let todos = self.with_details(rows.iter().map(todo_from_row).collect(), false).await?;
+        Ok(TodoPage::from_rows(todos, query))
     }
 
-    pub async fn query_archived_todos(&self) -> Result<Vec<sqlx::postgres::PgRow>, sqlx::Error> {
-        let archived_todos: Vec<sqlx::postgres::PgRow> = sqlx::query("
-            SELECT id, title, priority, completed, archived_at
+    async fn query_archived_todos(&self, list_id: u32) -> Result<Vec<Todo>, ApiError> {
+        let rows: Vec<PgRow> = sqlx::query("
+            SELECT id, list_id, title, priority, completed, completed_at, due_at, recurrence, parent_id, notes, archived_at
             FROM archived
+            WHERE list_id = $1
             ORDER BY archived_at DESC")
+            .bind(list_id as i32)
+            .fetch_all(&self.database)
+            .await?;
+        self.with_details(rows.iter().map(todo_from_row).collect(), true).await
+    }
+
+    async fn search_todos(&self, list_id: u32, query: &SearchQuery) -> Result<Vec<SearchHit>, ApiError> {
+        let headline_options = format!(
+            "StartSel={}, StopSel={}, HighlightAll=true",
+            search::MATCH_START, search::MATCH_END,
+        );
+        let notes_headline_options = format!(
+            "StartSel={}, StopSel={}, MaxWords={}, MinWords={}",
+            search::MATCH_START, search::MATCH_END, search::NOTES_SNIPPET_WORDS, search::NOTES_SNIPPET_WORDS / 2,
+        );
+        let rows: Vec<PgRow> = sqlx::query("
+            SELECT id, list_id, title, priority, completed, completed_at, due_at, recurrence, parent_id, notes,
+                   source, rank, snippet,
+                   CASE WHEN to_tsvector('simple', coalesce(notes, '')) @@ query
+                        THEN ts_headline('simple', notes, query, $7) END AS notes_snippet
+            FROM (
+                SELECT id, list_id, title, priority, completed, completed_at, due_at, recurrence, parent_id, notes, query,
+                       'active' AS source,
+                       ts_rank(search_vector, query) AS rank,
+                       ts_headline('simple', title, query, $2) AS snippet
+                FROM todos, to_tsquery('simple', $1) AS query
+                WHERE $3 AND list_id = $6 AND search_vector @@ query
+                UNION ALL
+                SELECT id, list_id, title, priority, completed, completed_at, due_at, recurrence, parent_id, notes, query,
+                       'archived' AS source,
+                       ts_rank(search_vector, query) AS rank,
+                       ts_headline('simple', title, query, $2) AS snippet
+                FROM archived, to_tsquery('simple', $1) AS query
+                WHERE $4 AND list_id = $6 AND search_vector @@ query
+            ) AS hits
+            ORDER BY rank DESC, id ASC
+            LIMIT $5")
+            .bind(query.to_prefix_tsquery())
+            .bind(headline_options)
+            .bind(query.scope.includes_active())
+            .bind(query.scope.includes_archived())
+            .bind(query.limit as i64)
+            .bind(list_id as i32)
+            .bind(notes_headline_options)
             .fetch_all(&self.database)
             .await?;
-        Ok(archived_todos)
+        Ok(rows.iter()
+            .map(|row| {
+                let source = match row.get::<&str, _>("source") {
+                    "archived" => SearchSource::Archived,
+                    _ => SearchSource::Active,
+                };
+                SearchHit::new(todo_from_row(row), source, row.get("rank"), row.get("snippet"), row.get("notes_snippet"))
+            })
+            .collect())
     }
 
-    pub async fn save_todo(&self, todo: &Todo) -> Result<u32, sqlx::Error> {
-        let row = sqlx::query(
-            "INSERT INTO todos (title, priority, completed) VALUES ($1, $2, $3) RETURNING id"
+    async fn query_todo(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError> {
+        let row: Option<PgRow> = sqlx::query("
+            SELECT id, list_id, title, priority, completed, completed_at, due_at, recurrence, parent_id, notes
+            FROM todos
+            WHERE id = $1 AND list_id = $2")
+            .bind(todo_id as i32)
+            .bind(list_id as i32)
+            .fetch_optional(&self.database)
+            .await?;
+        self.with_details_or_not_found(row, todo_id).await
+    }
+
+    async fn save_todo(&self, todo: &Todo) -> Result<Todo, ApiError> {
+        let row: PgRow = sqlx::query(
+            "INSERT INTO todos (list_id, title, priority, completed, completed_at, due_at, recurrence, parent_id, notes)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING *"
         )
+        .bind(todo.list_id as i32)
         .bind(&todo.title)
         .bind(todo.priority as i32)
         .bind(todo.completed)
+        .bind(todo.completed.then(Utc::now))
+        .bind(todo.due_at)
+        .bind(&todo.recurrence)
+        .bind(todo.parent_id.map(|id| id as i32))
+        .bind(&todo.notes)
         .fetch_one(&self.database)
         .await?;
+        Ok(todo_from_row(&row))
+    }
 
-        let id: i32 = row.get("id");
-        Ok(id as u32)
+    async fn archive_completed_todos(&self, list_id: u32) -> Result<u64, ApiError> {
+        let mut tx = self.database.begin().await?;
+        let todos = list_todos(&mut tx, list_id).await?;
+        let archivable = subtasks::archivable(&todos);
+        archive(&mut tx, &archivable, Utc::now()).await?;
+        tx.commit().await?;
+        Ok(archivable.len() as u64)
     }
 
-    pub async fn archive_completed_todos(&self) -> Result<u64, sqlx::Error> {
+    async fn archive_todo(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError> {
+        let mut tx = self.database.begin().await?;
+        let todos = list_todos(&mut tx, list_id).await?;
+        if !todos.iter().any(|todo| todo.id as u64 == todo_id) {
+            return Err(ApiError::todo_not_found(todo_id));
+        }
+        archive(&mut tx, &subtasks::subtree(&todos, todo_id as u32), Utc::now()).await?;
+        let row: PgRow = sqlx::query("SELECT * FROM archived WHERE id = $1")
+            .bind(todo_id as i32)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        let archived = self.with_details(vec![todo_from_row(&row)], true).await?;
+        Ok(archived.into_iter().next().unwrap())
+    }
+
+    async fn archive_completed_before(&self, list_id: u32, completed_before: DateTime<Utc>, now: DateTime<Utc>)
+        -> Result<u64, ApiError> {
+        let mut tx = self.database.begin().await?;
+        let todos = list_todos(&mut tx, list_id).await?;
+        let archivable = subtasks::archivable_before(&todos, completed_before);
+        archive(&mut tx, &archivable, now).await?;
+        tx.commit().await?;
+        Ok(archivable.len() as u64)
+    }
+
+    async fn query_archived_before(&self, list_id: u32, archived_before: DateTime<Utc>) -> Result<Vec<Todo>, ApiError> {
+        let rows: Vec<PgRow> = sqlx::query("
+            SELECT id, list_id, title, priority, completed, completed_at, due_at, recurrence, parent_id, notes, archived_at
+            FROM archived
+            WHERE list_id = $1 AND archived_at < $2
+            ORDER BY archived_at, id")
+            .bind(list_id as i32)
+            .bind(archived_before)
+            .fetch_all(&self.database)
+            .await?;
+        self.with_details(rows.iter().map(todo_from_row).collect(), true).await
+    }
+
+    async fn purge_archived_before(&self, list_id: u32, archived_before: DateTime<Utc>) -> Result<u64, ApiError> {
+        let purged = sqlx::query("DELETE FROM archived WHERE list_id = $1 AND archived_at < $2")
+            .bind(list_id as i32)
+            .bind(archived_before)
+            .execute(&self.database)
+            .await?;
+        Ok(purged.rows_affected())
+    }
+
+    async fn restore_archived_todos(&self, list_id: u32, archived_ids: &[u32], reset_completed: bool)
+        -> Result<Vec<Todo>, ApiError> {
+        let mut tx = self.database.begin().await?;
+        let rows: Vec<PgRow> = sqlx::query("SELECT * FROM archived WHERE list_id = $1 ORDER BY id FOR UPDATE")
+            .bind(list_id as i32)
+            .fetch_all(&mut *tx)
+            .await?;
+        let archived: Vec<Todo> = rows.iter().map(todo_from_row).collect();
+        let restorable = subtasks::restorable(&archived, archived_ids)
+            .map_err(ApiError::archived_todo_not_found)?;
+
+        // Archived ids come from the same sequence as todo ids, so a todo
+        // can come back under the id it was archived with. A completed
+        // recurring todo made its next occurrence before it was archived,
+        // so it comes back as recurred even when it is reopened.
+        let mut todo_ids: Vec<i32> = Vec::new();
+        for todo in &restorable {
+            let todo_id = todo.id as i32;
+            let parent_id = todo.parent_id.map(|parent_id| parent_id as i32).filter(|parent_id| todo_ids.contains(parent_id));
+            sqlx::query(
+                "INSERT INTO todos (id, list_id, title, priority, completed, completed_at, recurred, due_at, recurrence, parent_id, notes, created_at)
+                 SELECT id, list_id, title, priority, completed AND NOT $3, CASE WHEN $3 THEN NULL ELSE completed_at END,
+                        completed, due_at, recurrence, $2, notes, created_at
+                 FROM archived WHERE id = $1"
+            )
+            .bind(todo_id)
+            .bind(parent_id)
+            .bind(reset_completed)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query("INSERT INTO todo_tags (todo_id, tag_id) SELECT $1, tag_id FROM archived_tags WHERE archived_id = $1")
+                .bind(todo_id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("UPDATE attachments SET archived_id = NULL, todo_id = $1 WHERE archived_id = $1")
+                .bind(todo_id)
+                .execute(&mut *tx)
+                .await?;
+            todo_ids.push(todo_id);
+        }
+
+        sqlx::query("DELETE FROM archived WHERE id = ANY($1)")
+            .bind(&todo_ids)
+            .execute(&mut *tx)
+            .await?;
+        let rows: Vec<PgRow> = sqlx::query("SELECT * FROM todos WHERE id = ANY($1) ORDER BY id")
+            .bind(&todo_ids)
+            .fetch_all(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        self.with_details(rows.iter().map(todo_from_row).collect(), false).await
+    }
+
+    async fn query_tags(&self, list_id: u32) -> Result<Vec<Tag>, ApiError> {
+        let rows: Vec<PgRow> = sqlx::query("SELECT * FROM tags WHERE list_id = $1 ORDER BY name")
+            .bind(list_id as i32)
+            .fetch_all(&self.database)
+            .await?;
+        Ok(rows.iter().map(tag_from_row).collect())
+    }
+
+    async fn add_todo_tag(&self, list_id: u32, todo_id: u64, name: &str, color: Option<&str>) -> Result<Todo, ApiError> {
+        let mut tx = self.database.begin().await?;
+        lock_todo(&mut tx, list_id, todo_id).await?;
+        let tag_id: i32 = sqlx::query_scalar(
+            "INSERT INTO tags (list_id, name, color) VALUES ($1, $2, COALESCE($3, $4))
+             ON CONFLICT (list_id, name) DO UPDATE SET color = COALESCE($3, tags.color)
+             RETURNING id"
+        )
+        .bind(list_id as i32)
+        .bind(name)
+        .bind(color)
+        .bind(DEFAULT_TAG_COLOR)
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query("INSERT INTO todo_tags (todo_id, tag_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(todo_id as i32)
+            .bind(tag_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        self.query_todo(list_id, todo_id).await
+    }
+
+    async fn remove_todo_tag(&self, list_id: u32, todo_id: u64, name: &str) -> Result<Todo, ApiError> {
+        let mut tx = self.database.begin().await?;
+        lock_todo(&mut tx, list_id, todo_id).await?;
         let result = sqlx::query(
-            "INSERT INTO archived (title, priority, completed)
-             SELECT title, priority, completed FROM todos WHERE completed = TRUE"
+            "DELETE FROM todo_tags
+             WHERE todo_id = $1 AND tag_id = (SELECT id FROM tags WHERE list_id = $2 AND name = $3)"
         )
-        .execute(&self.database)
+        .bind(todo_id as i32)
+        .bind(list_id as i32)
+        .bind(name)
+        .execute(&mut *tx)
         .await?;
+        if result.rows_affected() == 0 {
+            return Err(ApiError::tag_not_found(todo_id, name));
+        }
+        tx.commit().await?;
+        self.query_todo(list_id, todo_id).await
+    }
 
-        sqlx::query("DELETE FROM todos WHERE completed = TRUE")
-            .execute(&self.database)
+    async fn query_dependencies(&self, list_id: u32) -> Result<Vec<Dependency>, ApiError> {
+        list_dependencies(&self.database, list_id).await
+    }
+
+    async fn add_dependency(&self, list_id: u32, todo_id: u64, blocker_id: u64) -> Result<Todo, ApiError> {
+        let mut tx = self.database.begin().await?;
+        // Locking every todo of the list, rather than the two being linked,
+        // keeps two requests from each adding half of a cycle.
+        let todos = list_todos(&mut tx, list_id).await?;
+        if !todos.iter().any(|todo| todo.id as u64 == todo_id) {
+            return Err(ApiError::todo_not_found(todo_id));
+        }
+        let dependencies = list_dependencies(&mut *tx, list_id).await?;
+        dependencies::check_blocker(&todos, &dependencies, todo_id as u32, blocker_id as u32)
+            .map_err(|error| ApiError::Validation(vec![error]))?;
+        sqlx::query("INSERT INTO todo_dependencies (todo_id, blocker_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(todo_id as i32)
+            .bind(blocker_id as i32)
+            .execute(&mut *tx)
             .await?;
+        tx.commit().await?;
+        self.query_todo(list_id, todo_id).await
+    }
 
-        Ok(result.rows_affected())
+    async fn remove_dependency(&self, list_id: u32, todo_id: u64, blocker_id: u64) -> Result<Todo, ApiError> {
+        let mut tx = self.database.begin().await?;
+        lock_todo(&mut tx, list_id, todo_id).await?;
+        let result = sqlx::query("DELETE FROM todo_dependencies WHERE todo_id = $1 AND blocker_id = $2")
+            .bind(todo_id as i32)
+            .bind(blocker_id as i32)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(ApiError::dependency_not_found(todo_id, blocker_id));
+        }
+        tx.commit().await?;
+        self.query_todo(list_id, todo_id).await
     }
 
-    pub async fn rename_todo(&self, todo_id: u64, new_title: String) -> Result<u32, sqlx::Error> {
-        sqlx::query("UPDATE todos SET title = $1 WHERE id = $2")
-            .bind(new_title)
+    async fn query_attachments(&self, list_id: u32, todo_id: u64) -> Result<Vec<Attachment>, ApiError> {
+        self.query_todo(list_id, todo_id).await?;
+        let rows: Vec<PgRow> = sqlx::query("SELECT * FROM attachments WHERE todo_id = $1 ORDER BY id")
             .bind(todo_id as i32)
-            .execute(&self.database)
+            .fetch_all(&self.database)
             .await?;
-        Ok(todo_id as u32)
+        Ok(rows.iter().map(attachment_from_row).collect())
     }
 
-    pub async fn delete_todo(&self, todo_id: u64) -> Result<u64, sqlx::Error> {
-        let result = sqlx::query("DELETE FROM todos WHERE id = $1")
+    async fn query_list_attachments(&self, list_id: u32) -> Result<Vec<Attachment>, ApiError> {
+        let rows: Vec<PgRow> = sqlx::query(
+            "SELECT attachments.* FROM attachments
+             LEFT JOIN todos ON todos.id = attachments.todo_id
+             LEFT JOIN archived ON archived.id = attachments.archived_id
+             WHERE todos.list_id = $1 OR archived.list_id = $1
+             ORDER BY attachments.id"
+        )
+        .bind(list_id as i32)
+        .fetch_all(&self.database)
+        .await?;
+        Ok(rows.iter().map(attachment_from_row).collect())
+    }
+
+    async fn save_attachment(&self, list_id: u32, attachment: &Attachment) -> Result<Attachment, ApiError> {
+        let mut tx = self.database.begin().await?;
+        lock_todo(&mut tx, list_id, attachment.todo_id as u64).await?;
+        let row: PgRow = sqlx::query(
+            "INSERT INTO attachments (todo_id, file_name, content_type, size, sha256, storage_key, created_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *"
+        )
+        .bind(attachment.todo_id as i32)
+        .bind(&attachment.file_name)
+        .bind(&attachment.content_type)
+        .bind(attachment.size as i64)
+        .bind(&attachment.sha256)
+        .bind(&attachment.storage_key)
+        .bind(attachment.created_at)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(attachment_from_row(&row))
+    }
+
+    async fn query_attachment(&self, list_id: u32, todo_id: u64, attachment_id: u32) -> Result<Attachment, ApiError> {
+        let mut tx = self.database.begin().await?;
+        lock_todo(&mut tx, list_id, todo_id).await?;
+        let row: Option<PgRow> = sqlx::query("SELECT * FROM attachments WHERE id = $1 AND todo_id = $2")
+            .bind(attachment_id as i32)
             .bind(todo_id as i32)
-            .execute(&self.database)
+            .fetch_optional(&mut *tx)
             .await?;
-        Ok(result.rows_affected())
+        tx.commit().await?;
+        row.map(|row| attachment_from_row(&row)).ok_or_else(|| ApiError::attachment_not_found(attachment_id))
     }
 
-    pub async fn toggle_todo_completion(&self, todo_id: u64) -> Result<u32, sqlx::Error> {
-        sqlx::query("UPDATE todos SET completed = NOT completed WHERE id = $1")
+    async fn delete_attachment(&self, list_id: u32, todo_id: u64, attachment_id: u32) -> Result<Attachment, ApiError> {
+        let mut tx = self.database.begin().await?;
+        lock_todo(&mut tx, list_id, todo_id).await?;
+        let row: Option<PgRow> = sqlx::query("DELETE FROM attachments WHERE id = $1 AND todo_id = $2 RETURNING *")
+            .bind(attachment_id as i32)
             .bind(todo_id as i32)
-            .execute(&self.database)
+            .fetch_optional(&mut *tx)
             .await?;
-        Ok(todo_id as u32)
+        let attachment = row.map(|row| attachment_from_row(&row)).ok_or_else(|| ApiError::attachment_not_found(attachment_id))?;
+        tx.commit().await?;
+        Ok(attachment)
     }
 
-    pub async fn increase_todo_priority(&self, todo_id: u64) -> Result<u32, sqlx::Error> {
-        sqlx::query("UPDATE todos SET priority = priority + 1 WHERE id = $1")
+    async fn rename_todo(&self, list_id: u32, todo_id: u64, new_title: String) -> Result<Todo, ApiError> {
+        let row: Option<PgRow> = sqlx::query("UPDATE todos SET title = $1 WHERE id = $2 AND list_id = $3 RETURNING *")
+            .bind(new_title)
             .bind(todo_id as i32)
-            .execute(&self.database)
+            .bind(list_id as i32)
+            .fetch_optional(&self.database)
             .await?;
-        Ok(todo_id as u32)
+        self.with_details_or_not_found(row, todo_id).await
     }
 
-    pub async fn decrease_todo_priority(&self, todo_id: u64) -> Result<u32, sqlx::Error> {
-        sqlx::query("UPDATE todos SET priority = priority - 1 WHERE id = $1")
+    async fn delete_todo(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError> {
+        let mut tx = self.database.begin().await?;
+        let row: Option<PgRow> = sqlx::query("SELECT * FROM todos WHERE id = $1 AND list_id = $2 FOR UPDATE")
             .bind(todo_id as i32)
-            .execute(&self.database)
+            .bind(list_id as i32)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let todo = todo_or_not_found(row, todo_id)?;
+        let todo = todo_details(&mut tx, vec![todo], false).await?.remove(0);
+        sqlx::query("DELETE FROM todos WHERE id = $1")
+            .bind(todo_id as i32)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(todo)
+    }
+
+    async fn update_todo(&self, list_id: u32, todo_id: u64, update: &UpdateTodo) -> Result<Todo, ApiError> {
+        let mut tx = self.database.begin().await?;
+        let row = apply_update(&mut tx, list_id, todo_id, update).await?;
+        tx.commit().await?;
+        self.with_details_or_not_found(row, todo_id).await
+    }
+
+    async fn update_todo_checked(&self, list_id: u32, todo_id: u64, update: &UpdateTodo, cascade: bool)
+        -> Result<Todo, ApiError> {
+        let mut tx = self.database.begin().await?;
+        let todos = checked_todos(&mut tx, list_id).await?;
+        let subtasks = subtasks::check_update(&todos, todo_id as u32, update, cascade)?;
+        let row = apply_update(&mut tx, list_id, todo_id, update).await?;
+        let complete = UpdateTodo { completed: Some(true), ..UpdateTodo::default() };
+        for subtask in subtasks {
+            apply_update(&mut tx, list_id, subtask as u64, &complete).await?;
+        }
+        tx.commit().await?;
+        self.with_details_or_not_found(row, todo_id).await
+    }
+
+    async fn toggle_todo_completion(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError> {
+        let mut tx = self.database.begin().await?;
+        let todos = checked_todos(&mut tx, list_id).await?;
+        let completed = todos.iter().any(|todo| todo.id == todo_id as u32 && !todo.completed);
+        let update = UpdateTodo { completed: Some(completed), ..UpdateTodo::default() };
+        subtasks::check_update(&todos, todo_id as u32, &update, false)?;
+        let row = apply_update(&mut tx, list_id, todo_id, &update).await?;
+        tx.commit().await?;
+        self.with_details_or_not_found(row, todo_id).await
+    }
+
+    async fn increase_todo_priority(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError> {
+        let row: Option<PgRow> = sqlx::query("UPDATE todos SET priority = priority + 1 WHERE id = $1 AND list_id = $2 RETURNING *")
+            .bind(todo_id as i32)
+            .bind(list_id as i32)
+            .fetch_optional(&self.database)
+            .await?;
+        self.with_details_or_not_found(row, todo_id).await
+    }
+
+    async fn decrease_todo_priority(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError> {
+        let row: Option<PgRow> = sqlx::query("UPDATE todos SET priority = priority - 1 WHERE id = $1 AND list_id = $2 RETURNING *")
+            .bind(todo_id as i32)
+            .bind(list_id as i32)
+            .fetch_optional(&self.database)
             .await?;
-        Ok(todo_id as u32)
+        self.with_details_or_not_found(row, todo_id).await
     }
 }
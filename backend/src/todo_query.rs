@@ -0,0 +1,326 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::Todo;
+use crate::error::{ApiError, FieldError};
+use crate::validation::{self, MAX_PRIORITY, MIN_PRIORITY};
+
+pub const MAX_PAGE_SIZE: u32 = 1000;
+
+/// Orderings offered by `GET /api/todos`. Ids are handed out in creation
+/// order, so the id stands in for `created_at` and also breaks ties, which
+/// keeps every ordering total and therefore safe to page through.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// Highest priority first, soonest due first within a priority, then
+    /// oldest first. Todos without a due date come after the dated ones.
+    #[default]
+    Priority,
+    CreatedAsc,
+    CreatedDesc,
+    Title,
+}
+
+/// Query string of `GET /api/todos`.
+#[derive(Deserialize, Default)]
+pub struct ListTodosParams {
+    pub limit: Option<u32>,
+    pub after: Option<String>,
+    pub completed: Option<bool>,
+    pub min_priority: Option<u8>,
+    pub max_priority: Option<u8>,
+    pub sort: Option<SortOrder>,
+    /// Only todos that carry the tag with this name.
+    pub tag: Option<String>,
+    /// RFC 3339 timestamps; both bounds are exclusive and leave out todos
+    /// without a due date.
+    pub due_before: Option<String>,
+    pub due_after: Option<String>,
+}
+
+/// Position after the last todo of a page. It is handed to clients as an
+/// opaque token and only valid together with the sort order it was made for.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Cursor {
+    pub sort: SortOrder,
+    pub id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub due_at: Option<DateTime<Utc>>,
+}
+
+impl Cursor {
+    pub fn after(todo: &Todo, sort: SortOrder) -> Self {
+        Cursor {
+            sort,
+            id: todo.id,
+            priority: (sort == SortOrder::Priority).then_some(todo.priority),
+            title: (sort == SortOrder::Title).then(|| todo.title.clone()),
+            due_at: todo.due_at.filter(|_| sort == SortOrder::Priority),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap())
+    }
+
+    pub fn decode(token: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(token).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+/// A validated `ListTodosParams`. The default value selects every todo in
+/// priority order.
+#[derive(Clone, Debug, Default)]
+pub struct TodoQuery {
+    pub limit: Option<u32>,
+    pub after: Option<Cursor>,
+    pub completed: Option<bool>,
+    pub min_priority: Option<u8>,
+    pub max_priority: Option<u8>,
+    pub sort: SortOrder,
+    pub tag: Option<String>,
+    pub due_before: Option<DateTime<Utc>>,
+    pub due_after: Option<DateTime<Utc>>,
+}
+
+/// Parses an RFC 3339 timestamp from a query string.
+fn parse_timestamp(field: &str, value: Option<&str>, errors: &mut Vec<FieldError>) -> Option<DateTime<Utc>> {
+    let value = value?;
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(timestamp) => Some(timestamp.with_timezone(&Utc)),
+        Err(_) => {
+            errors.push(FieldError::new(field, "must be an RFC 3339 timestamp"));
+            None
+        }
+    }
+}
+
+impl TryFrom<ListTodosParams> for TodoQuery {
+    type Error = ApiError;
+
+    fn try_from(params: ListTodosParams) -> Result<Self, ApiError> {
+        let mut errors = Vec::new();
+        let sort = params.sort.unwrap_or_default();
+
+        if params.limit.is_some_and(|limit| limit == 0 || limit > MAX_PAGE_SIZE) {
+            errors.push(FieldError::new("limit", format!("must be between 1 and {}", MAX_PAGE_SIZE)));
+        }
+        for (field, value) in [("min_priority", params.min_priority), ("max_priority", params.max_priority)] {
+            if value.is_some_and(|p| !(MIN_PRIORITY..=MAX_PRIORITY).contains(&p)) {
+                errors.push(validation::priority_error(field));
+            }
+        }
+        if let (Some(min), Some(max)) = (params.min_priority, params.max_priority)
+            && min > max
+        {
+            errors.push(FieldError::new("min_priority", "must not be greater than max_priority"));
+        }
+        let tag = params.tag.map(|tag| tag.trim().to_string());
+        if tag.as_deref() == Some("") {
+            errors.push(FieldError::new("tag", "must not be blank"));
+        }
+        let due_before = parse_timestamp("due_before", params.due_before.as_deref(), &mut errors);
+        let due_after = parse_timestamp("due_after", params.due_after.as_deref(), &mut errors);
+        if let (Some(before), Some(after)) = (due_before, due_after)
+            && after >= before
+        {
+            errors.push(FieldError::new("due_after", "must be earlier than due_before"));
+        }
+        let after = match params.after.as_deref().map(Cursor::decode) {
+            None => None,
+            Some(Some(cursor)) if cursor.sort == sort => Some(cursor),
+            Some(Some(_)) => {
+                errors.push(FieldError::new("after", "was issued for a different sort order"));
+                None
+            }
+            Some(None) => {
+                errors.push(FieldError::new("after", "is not a valid cursor"));
+                None
+            }
+        };
+
+        if !errors.is_empty() {
+            return Err(ApiError::Validation(errors));
+        }
+        Ok(TodoQuery {
+            limit: params.limit,
+            after,
+            completed: params.completed,
+            min_priority: params.min_priority,
+            max_priority: params.max_priority,
+            sort,
+            tag,
+            due_before,
+            due_after,
+        })
+    }
+}
+
+/// A value to bind to one of the placeholders produced by `TodoQuery::to_sql`.
+pub enum SqlValue {
+    Int(i64),
+    Text(String),
+    Bool(bool),
+    Timestamp(DateTime<Utc>),
+}
+
+impl TodoQuery {
+    /// Stores fetch one row more than the limit so that `TodoPage` can tell
+    /// whether another page follows.
+    pub fn fetch_limit(&self) -> Option<u32> {
+        self.limit.map(|limit| limit + 1)
+    }
+
+    /// Builds the `SELECT` over the todos of one list for this query. The
+    /// placeholders are numbered `$1..` in the order of the returned values,
+    /// which both Postgres and SQLite understand.
+    pub fn to_sql(&self, list_id: u32) -> (String, Vec<SqlValue>) {
+        fn push(values: &mut Vec<SqlValue>, value: SqlValue) -> String {
+            values.push(value);
+            format!("${}", values.len())
+        }
+
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values: Vec<SqlValue> = Vec::new();
+
+        conditions.push(format!("list_id = {}", push(&mut values, SqlValue::Int(list_id as i64))));
+
+        if let Some(completed) = self.completed {
+            conditions.push(format!("completed = {}", push(&mut values, SqlValue::Bool(completed))));
+        }
+        if let Some(min) = self.min_priority {
+            conditions.push(format!("priority >= {}", push(&mut values, SqlValue::Int(min as i64))));
+        }
+        if let Some(max) = self.max_priority {
+            conditions.push(format!("priority <= {}", push(&mut values, SqlValue::Int(max as i64))));
+        }
+        if let Some(before) = self.due_before {
+            conditions.push(format!("due_at < {}", push(&mut values, SqlValue::Timestamp(before))));
+        }
+        if let Some(after) = self.due_after {
+            conditions.push(format!("due_at > {}", push(&mut values, SqlValue::Timestamp(after))));
+        }
+        if let Some(tag) = &self.tag {
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM todo_tags JOIN tags ON tags.id = todo_tags.tag_id \
+                 WHERE todo_tags.todo_id = todos.id AND tags.name = {})",
+                push(&mut values, SqlValue::Text(tag.clone())),
+            ));
+        }
+        if let Some(cursor) = &self.after {
+            let id = push(&mut values, SqlValue::Int(cursor.id as i64));
+            let condition = match self.sort {
+                SortOrder::Priority => {
+                    let priority = cursor.priority.unwrap_or_default() as i64;
+                    let first = push(&mut values, SqlValue::Int(priority));
+                    let second = push(&mut values, SqlValue::Int(priority));
+                    let later = match cursor.due_at {
+                        Some(due_at) => {
+                            let first_due = push(&mut values, SqlValue::Timestamp(due_at));
+                            let second_due = push(&mut values, SqlValue::Timestamp(due_at));
+                            format!("(due_at > {} OR due_at IS NULL OR (due_at = {} AND id > {}))", first_due, second_due, id)
+                        }
+                        None => format!("(due_at IS NULL AND id > {})", id),
+                    };
+                    format!("(priority < {} OR (priority = {} AND {}))", first, second, later)
+                }
+                SortOrder::CreatedAsc => format!("id > {}", id),
+                SortOrder::CreatedDesc => format!("id < {}", id),
+                SortOrder::Title => {
+                    let title = cursor.title.clone().unwrap_or_default();
+                    let first = push(&mut values, SqlValue::Text(title.clone()));
+                    let second = push(&mut values, SqlValue::Text(title));
+                    format!("(title > {} OR (title = {} AND id > {}))", first, second, id)
+                }
+            };
+            conditions.push(condition);
+        }
+
+        let mut sql = String::from("SELECT * FROM todos WHERE ");
+        sql.push_str(&conditions.join(" AND "));
+        sql.push_str(match self.sort {
+            SortOrder::Priority => " ORDER BY priority DESC, due_at ASC NULLS LAST, id ASC",
+            SortOrder::CreatedAsc => " ORDER BY id ASC",
+            SortOrder::CreatedDesc => " ORDER BY id DESC",
+            SortOrder::Title => " ORDER BY title ASC, id ASC",
+        });
+        if let Some(limit) = self.fetch_limit() {
+            sql.push_str(&format!(" LIMIT {}", limit));
+        }
+        (sql, values)
+    }
+
+    /// The in-memory equivalent of the `WHERE` clause built by `to_sql`,
+    /// apart from the list, which callers filter on themselves.
+    pub fn matches(&self, todo: &Todo) -> bool {
+        let after_cursor = match &self.after {
+            None => true,
+            Some(cursor) => match self.sort {
+                SortOrder::Priority => {
+                    let priority = cursor.priority.unwrap_or_default();
+                    todo.priority < priority
+                        || (todo.priority == priority && due_key(todo.due_at, todo.id) > due_key(cursor.due_at, cursor.id))
+                }
+                SortOrder::CreatedAsc => todo.id > cursor.id,
+                SortOrder::CreatedDesc => todo.id < cursor.id,
+                SortOrder::Title => {
+                    let title = cursor.title.as_deref().unwrap_or_default();
+                    todo.title.as_str() > title || (todo.title == title && todo.id > cursor.id)
+                }
+            },
+        };
+        after_cursor
+            && self.completed.is_none_or(|completed| todo.completed == completed)
+            && self.min_priority.is_none_or(|min| todo.priority >= min)
+            && self.max_priority.is_none_or(|max| todo.priority <= max)
+            && self.due_before.is_none_or(|before| todo.due_at.is_some_and(|due_at| due_at < before))
+            && self.due_after.is_none_or(|after| todo.due_at.is_some_and(|due_at| due_at > after))
+            && self.tag.as_ref().is_none_or(|name| todo.tags.iter().any(|tag| &tag.name == name))
+    }
+
+    /// The in-memory equivalent of the `ORDER BY` clause built by `to_sql`.
+    pub fn sort(&self, todos: &mut [Todo]) {
+        match self.sort {
+            SortOrder::Priority => todos.sort_by(|a, b| {
+                b.priority.cmp(&a.priority).then(due_key(a.due_at, a.id).cmp(&due_key(b.due_at, b.id)))
+            }),
+            SortOrder::CreatedAsc => todos.sort_by_key(|t| t.id),
+            SortOrder::CreatedDesc => todos.sort_by_key(|t| std::cmp::Reverse(t.id)),
+            SortOrder::Title => todos.sort_by(|a, b| a.title.cmp(&b.title).then(a.id.cmp(&b.id))),
+        }
+    }
+}
+
+/// Orders todos by due date with undated ones last and the id breaking
+/// ties, like `due_at ASC NULLS LAST, id ASC`.
+fn due_key(due_at: Option<DateTime<Utc>>, id: u32) -> (bool, Option<DateTime<Utc>>, u32) {
+    (due_at.is_none(), due_at, id)
+}
+
+/// One page of todos and the cursor of the page that follows, if any.
+#[derive(Debug)]
+pub struct TodoPage {
+    pub todos: Vec<Todo>,
+    pub next_cursor: Option<Cursor>,
+}
+
+impl TodoPage {
+    /// Cuts the rows fetched with `TodoQuery::fetch_limit` down to the page
+    /// size and derives the next cursor from the last todo that was kept.
+    pub fn from_rows(mut todos: Vec<Todo>, query: &TodoQuery) -> Self {
+        let next_cursor = match query.limit {
+            Some(limit) if todos.len() > limit as usize => {
+                todos.truncate(limit as usize);
+                todos.last().map(|last| Cursor::after(last, query.sort))
+            }
+            _ => None,
+        };
+        TodoPage { todos, next_cursor }
+    }
+}
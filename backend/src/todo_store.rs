@@ -0,0 +1,247 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use crate::{Todo, TodoList, UpdateTodo};
+use crate::api_token::{ApiToken, Scope};
+use crate::attachments::Attachment;
+use crate::auth::User;
+use crate::dependencies::Dependency;
+use crate::error::ApiError;
+use crate::membership::{ListInvite, ListMember, Role};
+use crate::search::{self, SearchHit, SearchQuery, SearchSource};
+use crate::tags::Tag;
+use crate::todo_query::{TodoPage, TodoQuery};
+use crate::sqlite_todo_list_dao::SqliteTodoListDao;
+use crate::todo_list_dao::TodoListDao;
+
+/// Storage operations used by the HTTP handlers.
+///
+/// `TodoListDao` implements this on top of Postgres, `SqliteTodoListDao` on
+/// top of a local SQLite file, and `InMemoryTodoListDao` keeps everything in
+/// process memory, which lets the router run without a database.
+#[async_trait]
+pub trait TodoStore: Send + Sync {
+    /// Wipes the whole database and rebuilds the schema from scratch.
+    async fn initialize(&self);
+
+    /// Applies pending schema migrations and returns their versions.
+    async fn migrate(&self) -> Result<Vec<i64>, sqlx::Error>;
+
+    /// Creates an account together with its default list. The first account
+    /// takes over the lists that predate accounts instead. Either way the
+    /// account becomes the owning member of its lists. Fails with
+    /// `ApiError::Conflict` when the email is already registered.
+    async fn create_user(&self, email: &str, password_hash: &str) -> Result<User, ApiError>;
+
+    /// The user registered under `email` and their password hash.
+    async fn query_credentials(&self, email: &str) -> Result<Option<(User, String)>, ApiError>;
+
+    /// The account linked to an identity of an OIDC provider.
+    async fn query_identity_user(&self, issuer: &str, subject: &str) -> Result<Option<User>, ApiError>;
+
+    async fn link_identity(&self, issuer: &str, subject: &str, user_id: u32) -> Result<(), ApiError>;
+
+    async fn create_session(&self, token_hash: &str, user_id: u32, expires_at: DateTime<Utc>) -> Result<(), ApiError>;
+
+    /// The owner of the session, unless it has expired by `now`.
+    async fn query_session_user(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<User>, ApiError>;
+
+    async fn delete_session(&self, token_hash: &str) -> Result<(), ApiError>;
+
+    async fn create_api_token(&self, user_id: u32, name: &str, token_hash: &str, scopes: &[Scope], expires_at: DateTime<Utc>)
+        -> Result<ApiToken, ApiError>;
+
+    /// The tokens of a user, newest first, expired ones included.
+    async fn query_api_tokens(&self, user_id: u32) -> Result<Vec<ApiToken>, ApiError>;
+
+    /// Looks up a token that has not expired by `now`, records `now` as its
+    /// last use and returns it together with its owner.
+    async fn use_api_token(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<(User, ApiToken)>, ApiError>;
+
+    /// Revokes a token of the user. Fails with `ApiError::NotFound` for
+    /// tokens that belong to someone else.
+    async fn delete_api_token(&self, user_id: u32, token_id: u32) -> Result<ApiToken, ApiError>;
+
+    /// The lists the user is a member of in creation order, starting with
+    /// their own default list.
+    async fn query_lists(&self, user_id: u32) -> Result<Vec<TodoList>, ApiError>;
+
+    /// The ids of every list of every user, for the background retention
+    /// task.
+    async fn query_list_ids(&self) -> Result<Vec<u32>, ApiError>;
+
+    async fn query_default_list(&self, owner_id: u32) -> Result<TodoList, ApiError>;
+
+    async fn query_list(&self, list_id: u32) -> Result<TodoList, ApiError>;
+
+    /// Creates a list with `owner_id` as its owning member.
+    async fn create_list(&self, owner_id: u32, name: String) -> Result<TodoList, ApiError>;
+
+    async fn rename_list(&self, list_id: u32, name: String) -> Result<TodoList, ApiError>;
+
+    /// Removes the list and, with it, all of its active and archived todos.
+    async fn delete_list(&self, list_id: u32) -> Result<TodoList, ApiError>;
+
+    /// The role of the user on the list, or `None` for non-members and
+    /// lists that do not exist.
+    async fn query_member_role(&self, list_id: u32, user_id: u32) -> Result<Option<Role>, ApiError>;
+
+    async fn query_members(&self, list_id: u32) -> Result<Vec<ListMember>, ApiError>;
+
+    async fn create_invite(&self, list_id: u32, email: &str, role: Role, token_hash: &str, expires_at: DateTime<Utc>)
+        -> Result<ListInvite, ApiError>;
+
+    /// Makes `user` a member of the invite's list and uses the invite up.
+    /// Members keep their role if it is higher than the invited one. Fails
+    /// with `ApiError::NotFound` for unknown and expired invites and for
+    /// invites addressed to another email.
+    async fn accept_invite(&self, token_hash: &str, user: &User, now: DateTime<Utc>) -> Result<ListMember, ApiError>;
+
+    /// Everything below works on a single list. Todos of other lists are
+    /// treated as if they did not exist.
+    async fn truncate_todos_table(&self, list_id: u32) -> Result<&'static str, ApiError>;
+
+    /// Every active todo of the list in the default priority order.
+    async fn query_todos(&self, list_id: u32) -> Result<Vec<Todo>, ApiError> {
+        Ok(self.query_todo_page(list_id, &TodoQuery::default()).await?.todos)
+    }
+
+    async fn query_todo_page(&self, list_id: u32, query: &TodoQuery) -> Result<TodoPage, ApiError>;
+
+    async fn query_archived_todos(&self, list_id: u32) -> Result<Vec<Todo>, ApiError>;
+
+    /// Active and/or archived todos whose titles and notes match every
+    /// search term, best match first. Stores without full-text search fall
+    /// back to matching in memory.
+    async fn search_todos(&self, list_id: u32, query: &SearchQuery) -> Result<Vec<SearchHit>, ApiError> {
+        let mut candidates = Vec::new();
+        if query.scope.includes_active() {
+            candidates.extend(self.query_todos(list_id).await?.into_iter().map(|t| (t, SearchSource::Active)));
+        }
+        if query.scope.includes_archived() {
+            candidates.extend(self.query_archived_todos(list_id).await?.into_iter().map(|t| (t, SearchSource::Archived)));
+        }
+        Ok(search::search_text(candidates, query))
+    }
+
+    async fn query_todo(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError>;
+
+    /// Inserts the todo into the list named by its `list_id`.
+    async fn save_todo(&self, todo: &Todo) -> Result<Todo, ApiError>;
+
+    /// Moves the completed todos of the list to the archive, tags and
+    /// attachments included, under the ids they had.
+    /// Subtasks only move together with their whole tree, see
+    /// `subtasks::archivable`.
+    async fn archive_completed_todos(&self, list_id: u32) -> Result<u64, ApiError>;
+
+    /// Moves one todo, completed or not, to the archive together with its
+    /// subtasks and returns its archived copy. Archived todos keep the id,
+    /// creation and completion time they had.
+    async fn archive_todo(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError>;
+
+    /// Like `archive_completed_todos`, for the trees whose todos were all
+    /// completed before `completed_before`, see
+    /// `subtasks::archivable_before`. They count as archived at `now`.
+    async fn archive_completed_before(&self, list_id: u32, completed_before: DateTime<Utc>, now: DateTime<Utc>)
+        -> Result<u64, ApiError>;
+
+    /// The archived todos that were archived before `archived_before`,
+    /// oldest first.
+    async fn query_archived_before(&self, list_id: u32, archived_before: DateTime<Utc>) -> Result<Vec<Todo>, ApiError>;
+
+    /// Deletes the archived todos that were archived before
+    /// `archived_before` for good, along with their tags and the metadata
+    /// of their attachments.
+    async fn purge_archived_before(&self, list_id: u32, archived_before: DateTime<Utc>) -> Result<u64, ApiError>;
+
+    /// Moves archived todos back to the list under the ids they have, each
+    /// with its archived subtasks, tags and attachments, see
+    /// `subtasks::restorable`. Title, priority, notes and the original
+    /// creation time are kept; `reset_completed` reopens them. Subtasks
+    /// whose parent stays archived come back as top-level todos.
+    async fn restore_archived_todos(&self, list_id: u32, archived_ids: &[u32], reset_completed: bool)
+        -> Result<Vec<Todo>, ApiError>;
+
+    /// The tags of the list by name, used or not.
+    async fn query_tags(&self, list_id: u32) -> Result<Vec<Tag>, ApiError>;
+
+    /// Tags the todo with the tag of the list called `name`, which is
+    /// created with `color`, or `DEFAULT_TAG_COLOR`, if it does not exist.
+    /// A `color` given for an existing tag recolors it.
+    async fn add_todo_tag(&self, list_id: u32, todo_id: u64, name: &str, color: Option<&str>) -> Result<Todo, ApiError>;
+
+    /// Fails with `ApiError::NotFound` unless the todo carries the tag.
+    async fn remove_todo_tag(&self, list_id: u32, todo_id: u64, name: &str) -> Result<Todo, ApiError>;
+
+    /// The dependencies between the todos of the list, by todo and blocker.
+    async fn query_dependencies(&self, list_id: u32) -> Result<Vec<Dependency>, ApiError>;
+
+    /// Records that the todo is blocked by `blocker_id`, another todo of the
+    /// list. Adding a dependency twice is harmless. Fails with
+    /// `ApiError::Validation` if `dependencies::check_blocker` rejects the
+    /// blocker, checked while nobody else can change the list's dependencies.
+    async fn add_dependency(&self, list_id: u32, todo_id: u64, blocker_id: u64) -> Result<Todo, ApiError>;
+
+    /// Fails with `ApiError::NotFound` unless the todo is blocked by
+    /// `blocker_id`.
+    async fn remove_dependency(&self, list_id: u32, todo_id: u64, blocker_id: u64) -> Result<Todo, ApiError>;
+
+    /// The attachments of the todo, oldest first.
+    async fn query_attachments(&self, list_id: u32, todo_id: u64) -> Result<Vec<Attachment>, ApiError>;
+
+    /// The attachments of every todo of the list, archived ones included.
+    /// The handlers that delete todos compare it before and after to find
+    /// the files that have to go as well.
+    async fn query_list_attachments(&self, list_id: u32) -> Result<Vec<Attachment>, ApiError>;
+
+    /// Records a file stored for the todo named by the attachment's
+    /// `todo_id`.
+    async fn save_attachment(&self, list_id: u32, attachment: &Attachment) -> Result<Attachment, ApiError>;
+
+    /// Fails with `ApiError::NotFound` unless the attachment belongs to the
+    /// todo.
+    async fn query_attachment(&self, list_id: u32, todo_id: u64, attachment_id: u32) -> Result<Attachment, ApiError>;
+
+    /// Removes the metadata only; the file is left to the caller.
+    async fn delete_attachment(&self, list_id: u32, todo_id: u64, attachment_id: u32) -> Result<Attachment, ApiError>;
+
+    /// The mutations below return the todo as it was persisted (or, for
+    /// `delete_todo`, as it was removed) and fail with `ApiError::NotFound`
+    /// when the list has no todo with the given id.
+    async fn rename_todo(&self, list_id: u32, todo_id: u64, new_title: String) -> Result<Todo, ApiError>;
+
+    /// Removes the todo together with its subtasks.
+    async fn delete_todo(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError>;
+
+    /// Applies the fields that are set in `update` and leaves the rest alone.
+    async fn update_todo(&self, list_id: u32, todo_id: u64, update: &UpdateTodo) -> Result<Todo, ApiError>;
+
+    /// Like `update_todo`, after `subtasks::check_update` has passed, and
+    /// with `cascade` completing the todo's open subtasks as well. The todos
+    /// of the list are locked from the check until the last update, so that
+    /// nobody moves or blocks them in between.
+    async fn update_todo_checked(&self, list_id: u32, todo_id: u64, update: &UpdateTodo, cascade: bool)
+        -> Result<Todo, ApiError>;
+
+    /// Completing a recurring todo here or in `update_todo` also creates
+    /// its next occurrence, once per todo; see `recurrence::next_occurrence`.
+    /// The completed todo stays behind for `archive_completed_todos`.
+    /// A blocked todo cannot be completed; the check and the toggle run
+    /// under the locks of `update_todo_checked`.
+    async fn toggle_todo_completion(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError>;
+
+    async fn increase_todo_priority(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError>;
+
+    async fn decrease_todo_priority(&self, list_id: u32, todo_id: u64) -> Result<Todo, ApiError>;
+}
+
+/// Picks the store from the scheme of `database_url`: `sqlite:` URLs open a
+/// local file (or `sqlite::memory:`), anything else is handed to Postgres.
+pub async fn connect(database_url: &str) -> Result<Arc<dyn TodoStore>, sqlx::Error> {
+    if database_url.starts_with("sqlite:") {
+        Ok(Arc::new(SqliteTodoListDao::new(database_url).await?))
+    } else {
+        Ok(Arc::new(TodoListDao::connect(database_url).await?))
+    }
+}
@@ -0,0 +1,200 @@
+use crate::api_token::{Scope, MAX_TOKEN_TTL_DAYS};
+use crate::error::{ApiError, FieldError};
+use crate::membership::Role;
+use crate::notes::MAX_NOTES_LENGTH;
+use crate::recurrence::Recurrence;
+use crate::tags::MAX_TAG_NAME_LENGTH;
+
+/// Priorities outside this range are rejected by the handlers and by the
+/// `*_priority_range` CHECK constraints added in migration 0002.
+pub const MIN_PRIORITY: u8 = 1;
+pub const MAX_PRIORITY: u8 = 10;
+pub const DEFAULT_PRIORITY: u8 = 1;
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+pub const MAX_EMAIL_LENGTH: usize = 254;
+
+/// Limits for user supplied titles. Titles are trimmed before their length
+/// is checked, so whitespace-only titles count as empty.
+#[derive(Clone, Debug)]
+pub struct ValidationRules {
+    pub min_title_length: usize,
+    pub max_title_length: usize,
+}
+
+impl Default for ValidationRules {
+    fn default() -> Self {
+        ValidationRules {
+            min_title_length: 1,
+            max_title_length: 200,
+        }
+    }
+}
+
+impl ValidationRules {
+    /// Reads `TITLE_MIN_LENGTH` and `TITLE_MAX_LENGTH`, falling back to the
+    /// defaults for anything that is missing or not a number.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let read = |name: &str, default: usize| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        ValidationRules {
+            min_title_length: read("TITLE_MIN_LENGTH", defaults.min_title_length).max(1),
+            max_title_length: read("TITLE_MAX_LENGTH", defaults.max_title_length),
+        }
+    }
+
+    pub fn validator(&self) -> Validator<'_> {
+        Validator { rules: self, errors: Vec::new() }
+    }
+}
+
+pub fn priority_error(field: &str) -> FieldError {
+    FieldError::new(field, format!("must be between {} and {}", MIN_PRIORITY, MAX_PRIORITY))
+}
+
+/// Collects the errors of every checked field so that a single response can
+/// report all of them at once.
+pub struct Validator<'a> {
+    rules: &'a ValidationRules,
+    errors: Vec<FieldError>,
+}
+
+impl Validator<'_> {
+    /// Returns the trimmed title, recording an error if its length is out of
+    /// bounds.
+    pub fn title(&mut self, field: &str, title: &str) -> String {
+        let trimmed = title.trim();
+        let length = trimmed.chars().count();
+        if length == 0 {
+            self.errors.push(FieldError::new(field, "must not be blank"));
+        } else if length < self.rules.min_title_length {
+            self.errors.push(FieldError::new(
+                field,
+                format!("must be at least {} characters", self.rules.min_title_length),
+            ));
+        } else if length > self.rules.max_title_length {
+            self.errors.push(FieldError::new(
+                field,
+                format!("must be at most {} characters", self.rules.max_title_length),
+            ));
+        }
+        trimmed.to_string()
+    }
+
+    pub fn priority(&mut self, field: &str, priority: u8) -> u8 {
+        if !(MIN_PRIORITY..=MAX_PRIORITY).contains(&priority) {
+            self.errors.push(priority_error(field));
+        }
+        priority
+    }
+
+    /// Returns the trimmed, lowercased address. Only the overall shape is
+    /// checked; whether the mailbox exists is none of our business.
+    pub fn email(&mut self, field: &str, email: &str) -> String {
+        let email = email.trim().to_lowercase();
+        let well_formed = email.split_once('@')
+            .is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.') && !domain.ends_with('.'));
+        if !well_formed || email.chars().any(char::is_whitespace) {
+            self.errors.push(FieldError::new(field, "must be an email address"));
+        } else if email.len() > MAX_EMAIL_LENGTH {
+            self.errors.push(FieldError::new(field, format!("must be at most {} characters", MAX_EMAIL_LENGTH)));
+        }
+        email
+    }
+
+    pub fn password(&mut self, field: &str, password: &str) {
+        if password.chars().count() < MIN_PASSWORD_LENGTH {
+            self.errors.push(FieldError::new(
+                field,
+                format!("must be at least {} characters", MIN_PASSWORD_LENGTH),
+            ));
+        }
+    }
+
+    /// Parses the scopes of an API token; at least one is required.
+    pub fn scopes(&mut self, field: &str, scopes: &[String]) -> Vec<Scope> {
+        if scopes.is_empty() {
+            self.errors.push(FieldError::new(field, "must name at least one scope"));
+        }
+        let mut parsed = Vec::new();
+        for scope in scopes {
+            match scope.parse::<Scope>() {
+                Ok(scope) if !parsed.contains(&scope) => parsed.push(scope),
+                Ok(_) => {}
+                Err(()) => self.errors.push(FieldError::new(field, format!("has an unknown scope '{}'", scope))),
+            }
+        }
+        parsed
+    }
+
+    /// Unknown roles are reported and stand in as `Role::Viewer`.
+    pub fn role(&mut self, field: &str, role: &str) -> Role {
+        role.parse().unwrap_or_else(|()| {
+            self.errors.push(FieldError::new(field, "must be one of viewer, editor and owner"));
+            Role::Viewer
+        })
+    }
+
+    pub fn token_lifetime(&mut self, field: &str, days: u32) -> u32 {
+        if !(1..=MAX_TOKEN_TTL_DAYS).contains(&days) {
+            self.errors.push(FieldError::new(field, format!("must be between 1 and {}", MAX_TOKEN_TTL_DAYS)));
+        }
+        days
+    }
+
+    /// Returns the rule in its normalized RRULE form.
+    pub fn recurrence(&mut self, field: &str, rule: &str) -> String {
+        match rule.parse::<Recurrence>() {
+            Ok(rule) => rule.to_string(),
+            Err(message) => {
+                self.errors.push(FieldError::new(field, message));
+                rule.to_string()
+            }
+        }
+    }
+
+    /// Returns the notes without trailing whitespace, or `None` for blank
+    /// notes.
+    pub fn notes(&mut self, field: &str, notes: &str) -> Option<String> {
+        let notes = notes.trim_end();
+        if notes.chars().count() > MAX_NOTES_LENGTH {
+            self.errors.push(FieldError::new(field, format!("must be at most {} characters", MAX_NOTES_LENGTH)));
+        }
+        Some(notes.to_string()).filter(|notes| !notes.trim().is_empty())
+    }
+
+    /// Returns the trimmed tag name.
+    pub fn tag_name(&mut self, field: &str, name: &str) -> String {
+        let name = name.trim();
+        if name.is_empty() {
+            self.errors.push(FieldError::new(field, "must not be blank"));
+        } else if name.chars().count() > MAX_TAG_NAME_LENGTH {
+            self.errors.push(FieldError::new(field, format!("must be at most {} characters", MAX_TAG_NAME_LENGTH)));
+        }
+        name.to_string()
+    }
+
+    /// Returns the color in lowercase; only `#rrggbb` is accepted.
+    pub fn color(&mut self, field: &str, color: &str) -> String {
+        let color = color.trim().to_lowercase();
+        let well_formed = color.len() == 7 && color.starts_with('#')
+            && color[1..].chars().all(|c| c.is_ascii_hexdigit());
+        if !well_formed {
+            self.errors.push(FieldError::new(field, "must be a color like #1a2b3c"));
+        }
+        color
+    }
+
+    pub fn finish(self) -> Result<(), ApiError> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError::Validation(self.errors))
+        }
+    }
+}
@@ -10,9 +10,64 @@ use backend::{CreateTodo,
               decrease_todo_priority,
               clear_todo_list,
               root};
-use backend::todo_list_dao::TodoListDao;
-use axum::http::StatusCode;
-use std::sync::Arc;
+use backend::{build_app, build_app_with_attachments, build_app_with_retention, build_app_with_rules, ApiJson, EditableListId, DEFAULT_LIST_ID};
+use backend::attachments::AttachmentConfig;
+use backend::error::ApiError;
+use backend::in_memory_todo_list_dao::InMemoryTodoListDao;
+use backend::retention::{self, Clock, RetentionConfig, RetentionRun};
+use backend::todo_store::TodoStore;
+use backend::validation::ValidationRules;
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use http_body_util::BodyExt;
+use chrono::{DateTime, Duration, Utc};
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+
+async fn send(app: axum::Router, method: &str, uri: &str, body: Option<&str>) -> (StatusCode, serde_json::Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.unwrap_or("").to_string()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null))
+}
+
+/// Registers an account and returns the router with its session cookie
+/// attached to every request.
+async fn sign_in_as(app: axum::Router, email: &str) -> axum::Router {
+    let cookie = register(app.clone(), email).await;
+    app.layer(axum::middleware::map_request(move |mut request: Request<Body>| {
+        let cookie = cookie.clone();
+        async move {
+            request.headers_mut().insert(header::COOKIE, cookie.parse().unwrap());
+            request
+        }
+    }))
+}
+
+/// Registers an account and returns the `name=value` part of its session cookie.
+async fn register(app: axum::Router, email: &str) -> String {
+    let body = format!(r#"{{"email":"{}","password":"correct horse"}}"#, email);
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/auth/register")
+        .header("content-type", "application/json")
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+    set_cookie.split(';').next().unwrap().to_string()
+}
+
+async fn new_app() -> axum::Router {
+    sign_in_as(build_app(Arc::new(InMemoryTodoListDao::new())), "alice@example.com").await
+}
 
 #[tokio::test]
 async fn test_root_returns_welcome_message() {
@@ -22,37 +77,51 @@ async fn test_root_returns_welcome_message() {
 
 #[tokio::test]
 async fn test_create_todo() {
-    let payload = CreateTodo { title: "Test".to_string(), priority: Some(2) };
-    let dao = TodoListDao::new().await.unwrap();
-    dao.initialize().await;
-    let (status, json) = create_todo(axum::Extension(Arc::new(dao)), axum::Json(payload)).await;
+    let payload = CreateTodo { title: "Test".to_string(), priority: Some(2), due_at: None, recurrence: None, parent_id: None, notes: None };
+    let dao = InMemoryTodoListDao::new();
+    let (status, json) = create_todo(axum::Extension(Arc::new(dao)), axum::Extension(ValidationRules::default()), EditableListId(DEFAULT_LIST_ID), ApiJson(payload)).await.unwrap();
     assert_eq!(status, StatusCode::CREATED);
     assert_eq!(json.0.id, 1);
     assert_eq!(json.0.title, "Test");
     assert_eq!(json.0.priority, 2);
-    assert_eq!(json.0.completed, false);
+    assert!(!json.0.completed);
 }
 
 #[tokio::test]
 async fn test_archive_completed_todos() {
-    let dao = TodoListDao::new().await.unwrap();
-    dao.initialize().await;
+    let dao = InMemoryTodoListDao::new();
     let todo1 = backend::Todo {
         id: 1,
+        list_id: DEFAULT_LIST_ID,
         title: "Completed Todo".to_string(),
         priority: 1,
         completed: true,
+        completed_at: None,
+        due_at: None,
+        recurrence: None,
+        parent_id: None,
+        notes: None,
+        tags: Vec::new(),
+        blocked: false,
     };
     let todo2 = backend::Todo {
         id: 2,
+        list_id: DEFAULT_LIST_ID,
         title: "Incomplete Todo".to_string(),
         priority: 1,
         completed: false,
+        completed_at: None,
+        due_at: None,
+        recurrence: None,
+        parent_id: None,
+        notes: None,
+        tags: Vec::new(),
+        blocked: false,
     };
     dao.save_todo(&todo1).await.unwrap();
     dao.save_todo(&todo2).await.unwrap();
 
-    let (status, json) = archive_completed_todos(axum::Extension(Arc::new(dao))).await;
+    let (status, json) = archive_completed_todos(axum::Extension(Arc::new(dao)), EditableListId(DEFAULT_LIST_ID)).await.unwrap();
     assert_eq!(status, StatusCode::OK);
     assert_eq!(json.0.text, "Archived 1 completed todo(s)");
 }
@@ -60,93 +129,1346 @@ async fn test_archive_completed_todos() {
 #[tokio::test]
 async fn test_rename_todo() {
     let payload = RenamePayload { id: 1, new_title: "New Title".to_string() };
-    let dao = TodoListDao::new().await.unwrap();
-    dao.initialize().await;
+    let dao = InMemoryTodoListDao::new();
     let todo = backend::Todo {
         id: 1,
+        list_id: DEFAULT_LIST_ID,
         title: "Old Title".to_string(),
         priority: 1,
         completed: false,
+        completed_at: None,
+        due_at: None,
+        recurrence: None,
+        parent_id: None,
+        notes: None,
+        tags: Vec::new(),
+        blocked: false,
     };
     dao.save_todo(&todo).await.unwrap();
     let new_title = "New Title".to_string();
-    let (status, json) = rename_todo(axum::Extension(Arc::new(dao)), axum::Json(payload)).await;
+    let (status, json) = rename_todo(axum::Extension(Arc::new(dao)), axum::Extension(ValidationRules::default()), EditableListId(DEFAULT_LIST_ID), ApiJson(payload)).await.unwrap();
     assert_eq!(status, StatusCode::ACCEPTED);
     assert_eq!(json.0.id, 1);
     assert_eq!(json.0.title, new_title);
+    assert_eq!(json.0.priority, 1);
+    assert!(!json.0.completed);
 }
 
 #[tokio::test]
 async fn test_toggle_todo_completion() {
-    let dao = TodoListDao::new().await.unwrap();
-    dao.initialize().await;
-    let (status, json) = toggle_todo_completion(axum::Extension(Arc::new(dao)), axum::Json(IdPayload { id: 1 })).await;
+    let dao = InMemoryTodoListDao::new();
+    let todo = backend::Todo {
+        id: 1,
+        list_id: DEFAULT_LIST_ID,
+        title: "Toggle".to_string(),
+        priority: 1,
+        completed: false,
+        completed_at: None,
+        due_at: None,
+        recurrence: None,
+        parent_id: None,
+        notes: None,
+        tags: Vec::new(),
+        blocked: false,
+    };
+    dao.save_todo(&todo).await.unwrap();
+    let (status, json) = toggle_todo_completion(axum::Extension(Arc::new(dao)), EditableListId(DEFAULT_LIST_ID), ApiJson(IdPayload { id: 1 })).await.unwrap();
     assert_eq!(status, StatusCode::ACCEPTED);
     assert_eq!(json.0.id, 1);
-    assert_eq!(json.0.priority, 0);
-    assert_eq!(json.0.completed, true);
+    assert_eq!(json.0.title, "Toggle");
+    assert_eq!(json.0.priority, 1);
+    assert!(json.0.completed);
 }
 
 #[tokio::test]
 async fn test_increase_todo_priority() {
-    let dao = TodoListDao::new().await.unwrap();
-    dao.initialize().await;
+    let dao = InMemoryTodoListDao::new();
     let todo = backend::Todo {
         id: 1,
+        list_id: DEFAULT_LIST_ID,
         title: "Test Priority".to_string(),
         priority: 1,
         completed: false,
+        completed_at: None,
+        due_at: None,
+        recurrence: None,
+        parent_id: None,
+        notes: None,
+        tags: Vec::new(),
+        blocked: false,
     };
     dao.save_todo(&todo).await.unwrap();
-    let (status, json) = increase_todo_priority(axum::Extension(Arc::new(dao)), axum::Json(IdPayload { id: 1 })).await;
+    let (status, json) = increase_todo_priority(axum::Extension(Arc::new(dao)), EditableListId(DEFAULT_LIST_ID), ApiJson(IdPayload { id: 1 })).await.unwrap();
     assert_eq!(status, StatusCode::ACCEPTED);
-    assert_eq!(json.0.text, "Todo with id 1 priority increased");
+    assert_eq!(json.0.id, 1);
+    assert_eq!(json.0.title, "Test Priority");
+    assert_eq!(json.0.priority, 2);
 }
 
 #[tokio::test]
 async fn test_decrease_todo_priority() {
-    let dao = TodoListDao::new().await.unwrap();
-    dao.initialize().await;
+    let dao = InMemoryTodoListDao::new();
     let todo = backend::Todo {
         id: 1,
+        list_id: DEFAULT_LIST_ID,
         title: "Test Priority".to_string(),
-        priority: 1,
+        priority: 2,
         completed: false,
+        completed_at: None,
+        due_at: None,
+        recurrence: None,
+        parent_id: None,
+        notes: None,
+        tags: Vec::new(),
+        blocked: false,
     };
     dao.save_todo(&todo).await.unwrap();
-    let (status, json) = decrease_todo_priority(axum::Extension(Arc::new(dao)), axum::Json(IdPayload { id: 1 })).await;
+    let (status, json) = decrease_todo_priority(axum::Extension(Arc::new(dao)), EditableListId(DEFAULT_LIST_ID), ApiJson(IdPayload { id: 1 })).await.unwrap();
     assert_eq!(status, StatusCode::ACCEPTED);
-    assert_eq!(json.0.text, "Todo with id 1 priority decreased");
+    assert_eq!(json.0.id, 1);
+    assert_eq!(json.0.title, "Test Priority");
+    assert_eq!(json.0.priority, 1);
 }
 
 #[tokio::test]
 async fn test_clear_todo_list() {
-    let dao = TodoListDao::new().await.unwrap();
-    dao.initialize().await;
+    let dao = InMemoryTodoListDao::new();
     let todo = backend::Todo {
         id: 1,
+        list_id: DEFAULT_LIST_ID,
         title: "Test Truncate".to_string(),
         priority: 1,
         completed: false,
+        completed_at: None,
+        due_at: None,
+        recurrence: None,
+        parent_id: None,
+        notes: None,
+        tags: Vec::new(),
+        blocked: false,
     };
     dao.save_todo(&todo).await.unwrap();
-    let (status, json) = clear_todo_list(axum::Extension(Arc::new(dao))).await;
+    let (status, json) = clear_todo_list(axum::Extension(Arc::new(dao)), axum::Extension(AttachmentConfig::default()), EditableListId(DEFAULT_LIST_ID)).await.unwrap();
     assert_eq!(status, StatusCode::OK);
     assert_eq!(json.0.text, "All todos have been deleted");
 }
 
 #[tokio::test]
 async fn test_delete_todo() {
-    let dao = TodoListDao::new().await.unwrap();
-    dao.initialize().await;
+    let dao = InMemoryTodoListDao::new();
     let todo = backend::Todo {
         id: 1,
+        list_id: DEFAULT_LIST_ID,
         title: "Test Delete".to_string(),
         priority: 1,
         completed: false,
+        completed_at: None,
+        due_at: None,
+        recurrence: None,
+        parent_id: None,
+        notes: None,
+        tags: Vec::new(),
+        blocked: false,
     };
     dao.save_todo(&todo).await.unwrap();
-    let (status, json) = delete_todo(axum::Extension(Arc::new(dao)), axum::Json(IdPayload { id: 1 })).await;
+    let (status, json) = delete_todo(axum::Extension(Arc::new(dao)), axum::Extension(AttachmentConfig::default()), EditableListId(DEFAULT_LIST_ID), ApiJson(IdPayload { id: 1 })).await.unwrap();
     assert_eq!(status, StatusCode::OK);
     assert_eq!(json.0.text, "Todo with id 1 deleted successfully");
 }
+
+#[tokio::test]
+async fn test_router_creates_and_lists_todos() {
+    let app = new_app().await;
+    let (status, _) = send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"Low","priority":1}"#)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _) = send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"High","priority":3}"#)).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, json) = send(app, "GET", "/api/todos", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let titles: Vec<&str> = json.as_array().unwrap().iter().map(|t| t["title"].as_str().unwrap()).collect();
+    assert_eq!(titles, vec!["High", "Low"]);
+}
+
+#[tokio::test]
+async fn test_router_archives_completed_todos() {
+    let app = new_app().await;
+    send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"Done"}"#)).await;
+    send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"Not done"}"#)).await;
+    send(app.clone(), "POST", "/api/todos/complete", Some(r#"{"id":1}"#)).await;
+
+    let (status, json) = send(app.clone(), "POST", "/api/todos/archive_completed", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["text"], "Archived 1 completed todo(s)");
+
+    let (_, active) = send(app.clone(), "GET", "/api/todos", None).await;
+    assert_eq!(active.as_array().unwrap().len(), 1);
+    assert_eq!(active[0]["title"], "Not done");
+    let (_, archived) = send(app, "GET", "/api/todos/complete", None).await;
+    assert_eq!(archived.as_array().unwrap().len(), 1);
+    assert_eq!(archived[0]["title"], "Done");
+}
+
+#[tokio::test]
+async fn test_toggle_missing_todo_is_not_found() {
+    let dao = InMemoryTodoListDao::new();
+    let result = toggle_todo_completion(axum::Extension(Arc::new(dao)), EditableListId(DEFAULT_LIST_ID), ApiJson(IdPayload { id: 42 })).await;
+    assert!(matches!(result, Err(ApiError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_router_returns_not_found_body_for_missing_todo() {
+    let app = new_app().await;
+    for uri in ["/api/todos/rename", "/api/todos/complete", "/api/todos/delete", "/api/todos/increase_priority", "/api/todos/decrease_priority"] {
+        let (status, json) = send(app.clone(), "POST", uri, Some(r#"{"id":42,"new_title":"Nope"}"#)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND, "Expected 404 from {}", uri);
+        assert_eq!(json["error"], "not_found");
+        assert_eq!(json["message"], "Todo with id 42 not found");
+    }
+}
+
+#[test]
+fn test_api_error_status_codes() {
+    assert_eq!(ApiError::NotFound("x".into()).status(), StatusCode::NOT_FOUND);
+    assert_eq!(ApiError::Validation(Vec::new()).status(), StatusCode::BAD_REQUEST);
+    assert_eq!(ApiError::Conflict("x".into()).status(), StatusCode::CONFLICT);
+    assert_eq!(ApiError::Forbidden("x".into()).status(), StatusCode::FORBIDDEN);
+    assert_eq!(ApiError::Database(sqlx::Error::PoolClosed).status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn test_router_gets_single_todo() {
+    let app = new_app().await;
+    send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"Single","priority":2}"#)).await;
+
+    let (status, json) = send(app.clone(), "GET", "/api/todos/1", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["title"], "Single");
+    assert_eq!(json["priority"], 2);
+
+    let (status, json) = send(app, "GET", "/api/todos/2", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(json["error"], "not_found");
+}
+
+#[tokio::test]
+async fn test_router_patches_only_given_fields() {
+    let app = new_app().await;
+    send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"Patch me","priority":2}"#)).await;
+
+    let (status, json) = send(app.clone(), "PATCH", "/api/todos/1", Some(r#"{"completed":true}"#)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["title"], "Patch me");
+    assert_eq!(json["priority"], 2);
+    assert_eq!(json["completed"], true);
+
+    let (status, json) = send(app.clone(), "PATCH", "/api/todos/1", Some(r#"{"title":"Patched","priority":5}"#)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["title"], "Patched");
+    assert_eq!(json["priority"], 5);
+    assert_eq!(json["completed"], true);
+
+    let (status, _) = send(app, "PATCH", "/api/todos/9", Some(r#"{"completed":true}"#)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_router_deletes_todo_resource() {
+    let app = new_app().await;
+    send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"Delete me"}"#)).await;
+
+    let (status, _) = send(app.clone(), "DELETE", "/api/todos/1", None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(app.clone(), "GET", "/api/todos/1", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(app, "DELETE", "/api/todos/1", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_router_marks_verb_routes_as_deprecated() {
+    let app = new_app().await;
+    send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"Legacy"}"#)).await;
+
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/todos/rename")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"id":1,"new_title":"Still works"}"#))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert_eq!(response.headers()["deprecation"], "true");
+
+    let request = Request::builder().uri("/api/todos/complete").body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get("deprecation").is_none());
+}
+
+#[tokio::test]
+async fn test_router_rejects_invalid_new_todo_with_field_details() {
+    let app = new_app().await;
+    let (status, json) = send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"   ","priority":11}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["error"], "validation_failed");
+    assert_eq!(json["details"][0]["field"], "title");
+    assert_eq!(json["details"][0]["message"], "must not be blank");
+    assert_eq!(json["details"][1]["field"], "priority");
+    assert_eq!(json["details"][1]["message"], "must be between 1 and 10");
+
+    let (_, todos) = send(app, "GET", "/api/todos", None).await;
+    assert!(todos.as_array().unwrap().is_empty(), "Expected nothing to be saved");
+}
+
+#[tokio::test]
+async fn test_router_trims_titles() {
+    let app = new_app().await;
+    let (status, json) = send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"  Padded  "}"#)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(json["title"], "Padded");
+
+    let (_, json) = send(app, "POST", "/api/todos/rename", Some(r#"{"id":1,"new_title":"  Renamed "}"#)).await;
+    assert_eq!(json["title"], "Renamed");
+}
+
+#[tokio::test]
+async fn test_router_applies_configured_title_limits() {
+    let rules = ValidationRules { min_title_length: 3, max_title_length: 5 };
+    let app = sign_in_as(build_app_with_rules(Arc::new(InMemoryTodoListDao::new()), rules), "alice@example.com").await;
+    let (status, json) = send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"ab"}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["message"], "must be at least 3 characters");
+
+    let (status, json) = send(app.clone(), "PATCH", "/api/todos/1", Some(r#"{"title":"too long"}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["message"], "must be at most 5 characters");
+
+    let (status, json) = send(app, "POST", "/api/todos/rename", Some(r#"{"id":1,"new_title":""}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "new_title");
+}
+
+#[tokio::test]
+async fn test_router_keeps_priority_within_bounds() {
+    let app = new_app().await;
+    send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"Bounded","priority":1}"#)).await;
+
+    let (status, json) = send(app.clone(), "POST", "/api/todos/decrease_priority", Some(r#"{"id":1}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "priority");
+
+    let (status, _) = send(app.clone(), "PATCH", "/api/todos/1", Some(r#"{"priority":0}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    send(app.clone(), "PATCH", "/api/todos/1", Some(r#"{"priority":10}"#)).await;
+    let (status, _) = send(app.clone(), "POST", "/api/todos/increase_priority", Some(r#"{"id":1}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, json) = send(app, "GET", "/api/todos/1", None).await;
+    assert_eq!(json["priority"], 10);
+}
+
+async fn get_page(app: axum::Router, uri: &str) -> (StatusCode, Vec<String>, Option<String>) {
+    let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let cursor = response.headers().get("x-next-cursor").map(|v| v.to_str().unwrap().to_string());
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let json: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    let titles = json.as_array()
+        .map(|todos| todos.iter().map(|t| t["title"].as_str().unwrap().to_string()).collect())
+        .unwrap_or_default();
+    (status, titles, cursor)
+}
+
+async fn app_with_todos(todos: &[(&str, u8, bool)]) -> axum::Router {
+    let app = new_app().await;
+    for (title, priority, completed) in todos {
+        let body = format!(r#"{{"title":"{}","priority":{}}}"#, title, priority);
+        let (_, json) = send(app.clone(), "POST", "/api/todos", Some(&body)).await;
+        if *completed {
+            send(app.clone(), "PATCH", &format!("/api/todos/{}", json["id"]), Some(r#"{"completed":true}"#)).await;
+        }
+    }
+    app
+}
+
+#[tokio::test]
+async fn test_router_pages_through_todos_with_cursor() {
+    let app = app_with_todos(&[("a", 1, false), ("b", 3, false), ("c", 2, false), ("d", 3, false), ("e", 1, false)]).await;
+
+    let (status, first, cursor) = get_page(app.clone(), "/api/todos?limit=2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(first, vec!["b", "d"]);
+    let (_, second, cursor) = get_page(app.clone(), &format!("/api/todos?limit=2&after={}", cursor.unwrap())).await;
+    assert_eq!(second, vec!["c", "a"]);
+    let (_, third, cursor) = get_page(app.clone(), &format!("/api/todos?limit=2&after={}", cursor.unwrap())).await;
+    assert_eq!(third, vec!["e"]);
+    assert!(cursor.is_none(), "Expected no cursor after the last page");
+
+    let (_, all, cursor) = get_page(app, "/api/todos").await;
+    assert_eq!(all, vec!["b", "d", "c", "a", "e"]);
+    assert!(cursor.is_none());
+}
+
+#[tokio::test]
+async fn test_router_filters_and_sorts_todos() {
+    let app = app_with_todos(&[("walk", 1, true), ("code", 5, false), ("shop", 3, false), ("bake", 8, true)]).await;
+
+    let (_, open, _) = get_page(app.clone(), "/api/todos?completed=false").await;
+    assert_eq!(open, vec!["code", "shop"]);
+    let (_, ranged, _) = get_page(app.clone(), "/api/todos?min_priority=3&max_priority=5").await;
+    assert_eq!(ranged, vec!["code", "shop"]);
+    let (_, by_title, _) = get_page(app.clone(), "/api/todos?sort=title").await;
+    assert_eq!(by_title, vec!["bake", "code", "shop", "walk"]);
+    let (_, newest, _) = get_page(app.clone(), "/api/todos?sort=created_desc").await;
+    assert_eq!(newest, vec!["bake", "shop", "code", "walk"]);
+
+    let (_, first, cursor) = get_page(app.clone(), "/api/todos?sort=title&limit=3").await;
+    assert_eq!(first, vec!["bake", "code", "shop"]);
+    let (_, rest, _) = get_page(app, &format!("/api/todos?sort=title&limit=3&after={}", cursor.unwrap())).await;
+    assert_eq!(rest, vec!["walk"]);
+}
+
+#[tokio::test]
+async fn test_router_rejects_invalid_list_parameters() {
+    let app = app_with_todos(&[("a", 1, false), ("b", 1, false)]).await;
+    let (_, _, cursor) = get_page(app.clone(), "/api/todos?limit=1").await;
+
+    let (status, json) = send(app.clone(), "GET", &format!("/api/todos?sort=title&after={}", cursor.unwrap()), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "after");
+
+    let (status, json) = send(app.clone(), "GET", "/api/todos?after=garbage", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["message"], "is not a valid cursor");
+
+    let (status, json) = send(app, "GET", "/api/todos?limit=0&min_priority=5&max_priority=2", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let fields: Vec<&str> = json["details"].as_array().unwrap().iter().map(|d| d["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, vec!["limit", "min_priority"]);
+}
+
+#[tokio::test]
+async fn test_router_orders_by_due_date_within_priority() {
+    let app = new_app().await;
+    for (title, priority, due_at) in [
+        ("undated", 5, None),
+        ("later", 5, Some("2030-03-01T09:00:00Z")),
+        ("sooner", 5, Some("2030-01-01T09:00:00+02:00")),
+        ("low", 1, Some("2020-01-01T00:00:00Z")),
+        ("past", 5, Some("2020-06-01T00:00:00Z")),
+    ] {
+        let due_at = due_at.map_or("null".to_string(), |due_at| format!("\"{}\"", due_at));
+        let body = format!(r#"{{"title":"{}","priority":{},"due_at":{}}}"#, title, priority, due_at);
+        let (status, _) = send(app.clone(), "POST", "/api/todos", Some(&body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+    }
+
+    let (_, all, _) = get_page(app.clone(), "/api/todos").await;
+    assert_eq!(all, vec!["past", "sooner", "later", "undated", "low"]);
+    let (_, first, cursor) = get_page(app.clone(), "/api/todos?limit=2").await;
+    assert_eq!(first, vec!["past", "sooner"]);
+    let (_, second, cursor) = get_page(app.clone(), &format!("/api/todos?limit=2&after={}", cursor.unwrap())).await;
+    assert_eq!(second, vec!["later", "undated"]);
+    let (_, third, _) = get_page(app.clone(), &format!("/api/todos?limit=2&after={}", cursor.unwrap())).await;
+    assert_eq!(third, vec!["low"]);
+
+    let (_, ranged, _) = get_page(app.clone(), "/api/todos?due_after=2020-12-31T00:00:00Z&due_before=2030-02-01T00:00:00Z").await;
+    assert_eq!(ranged, vec!["sooner"]);
+    let (_, overdue, _) = get_page(app.clone(), "/api/todos/overdue").await;
+    assert_eq!(overdue, vec!["past", "low"]);
+
+    let (_, todo) = send(app.clone(), "PATCH", "/api/todos/5", Some(r#"{"completed":true}"#)).await;
+    assert_eq!(todo["due_at"], "2020-06-01T00:00:00Z", "Expected fields that are left out to stay");
+    let (_, overdue, _) = get_page(app.clone(), "/api/todos/overdue").await;
+    assert_eq!(overdue, vec!["low"], "Expected completed todos to no longer be overdue");
+    let (_, todo) = send(app.clone(), "PATCH", "/api/todos/4", Some(r#"{"due_at":null}"#)).await;
+    assert!(todo["due_at"].is_null());
+    let (_, todo) = send(app.clone(), "GET", "/api/todos/3", None).await;
+    assert_eq!(todo["due_at"], "2030-01-01T07:00:00Z", "Expected due dates to be stored in UTC");
+    let (_, overdue, _) = get_page(app, "/api/todos/overdue").await;
+    assert!(overdue.is_empty());
+}
+
+#[tokio::test]
+async fn test_router_rejects_invalid_due_dates() {
+    let app = new_app().await;
+    let (status, json) = send(app.clone(), "GET", "/api/todos?due_before=tomorrow", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "due_before");
+    let (status, json) = send(app.clone(), "GET", "/api/todos?due_before=2030-01-01T00:00:00Z&due_after=2030-02-01T00:00:00Z", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "due_after");
+    let (status, json) = send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"a","due_at":"soon"}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["error"], "validation_failed");
+    assert_eq!(json["details"][0]["field"], "due_at");
+}
+
+#[tokio::test]
+async fn test_router_names_fields_that_do_not_deserialize() {
+    let app = new_app().await;
+    for (body, field) in [(r#"{"title":"a","priority":300}"#, "priority"), (r#"{"title":"a","priority":-1}"#, "priority"), (r#"{"priority":1}"#, "title")] {
+        let (status, json) = send(app.clone(), "POST", "/api/todos", Some(body)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert_eq!(json["details"][0]["field"], field, "{}", body);
+    }
+    let (status, json) = send(app.clone(), "PATCH", "/api/todos/1", Some(r#"{"completed":"yes"}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "completed");
+    let (status, json) = send(app.clone(), "GET", "/api/todos?min_priority=300", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "min_priority");
+    let (status, json) = send(app, "POST", "/api/todos", Some("{")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "body");
+}
+
+#[tokio::test]
+async fn test_router_answers_malformed_paths_and_uploads_with_error_bodies() {
+    let app = new_app().await;
+    send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"a"}"#)).await;
+    let (status, json) = send(app.clone(), "PATCH", "/api/todos/first", Some(r#"{"title":"b"}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["error"], "validation_failed");
+    assert_eq!(json["details"][0]["field"], "id");
+    let (status, json) = send(app, "POST", "/api/todos/1/attachments", Some("{}")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "file");
+}
+
+#[tokio::test]
+async fn test_router_creates_next_occurrence_of_recurring_todos() {
+    let app = new_app().await;
+    let body = r#"{"title":"Weekly report","due_at":"2030-01-07T09:00:00Z","recurrence":"FREQ=weekly;BYDAY=TH,MO"}"#;
+    let (status, todo) = send(app.clone(), "POST", "/api/todos", Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(todo["recurrence"], "FREQ=WEEKLY;BYDAY=MO,TH");
+    send(app.clone(), "POST", "/api/todos/1/tags", Some(r#"{"name":"work"}"#)).await;
+
+    let (_, completed) = send(app.clone(), "POST", "/api/todos/complete", Some(r#"{"id":1}"#)).await;
+    assert_eq!(completed["completed"], true);
+    let (_, open) = send(app.clone(), "GET", "/api/todos?completed=false", None).await;
+    assert_eq!(open.as_array().unwrap().len(), 1);
+    assert_eq!(open[0]["title"], "Weekly report");
+    assert_eq!(open[0]["due_at"], "2030-01-10T09:00:00Z");
+    assert_eq!(open[0]["recurrence"], "FREQ=WEEKLY;BYDAY=MO,TH");
+    assert_eq!(open[0]["tags"][0]["name"], "work");
+
+    send(app.clone(), "POST", "/api/todos/complete", Some(r#"{"id":1}"#)).await;
+    send(app.clone(), "POST", "/api/todos/complete", Some(r#"{"id":1}"#)).await;
+    let (_, all) = send(app.clone(), "GET", "/api/todos", None).await;
+    assert_eq!(all.as_array().unwrap().len(), 2, "Expected a todo to recur only once");
+
+    let next_id = open[0]["id"].as_u64().unwrap();
+    send(app.clone(), "PATCH", &format!("/api/todos/{}", next_id), Some(r#"{"completed":true}"#)).await;
+    let (_, open) = send(app.clone(), "GET", "/api/todos?completed=false", None).await;
+    assert_eq!(open[0]["due_at"], "2030-01-14T09:00:00Z", "Expected completing through PATCH to recur as well");
+
+    let (_, message) = send(app.clone(), "POST", "/api/todos/archive_completed", None).await;
+    assert_eq!(message["text"], "Archived 2 completed todo(s)");
+    let (_, stopped) = send(app.clone(), "PATCH", &format!("/api/todos/{}", open[0]["id"]), Some(r#"{"recurrence":null,"completed":true}"#)).await;
+    assert!(stopped["recurrence"].is_null());
+    let (_, open) = send(app.clone(), "GET", "/api/todos?completed=false", None).await;
+    assert!(open.as_array().unwrap().is_empty());
+
+    let body = r#"{"title":"Rent","due_at":"2030-01-31T09:00:00Z","recurrence":"monthly"}"#;
+    let (_, rent) = send(app.clone(), "POST", "/api/todos", Some(body)).await;
+    send(app.clone(), "PATCH", &format!("/api/todos/{}", rent["id"]), Some(r#"{"completed":true}"#)).await;
+    let (_, open) = send(app.clone(), "GET", "/api/todos?completed=false", None).await;
+    assert_eq!(open[0]["due_at"], "2030-02-28T09:00:00Z");
+    assert_eq!(open[0]["recurrence"], "FREQ=MONTHLY;BYMONTHDAY=31", "Expected month-end todos to keep their day");
+
+    let (status, json) = send(app, "POST", "/api/todos", Some(r#"{"title":"Yearly","recurrence":"FREQ=YEARLY"}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "recurrence");
+}
+
+#[tokio::test]
+async fn test_router_nests_subtasks_and_cascades_completion() {
+    let app = app_with_todos(&[("move", 3, false), ("walk", 1, false)]).await;
+    for (title, parent_id) in [("pack", 1), ("books", 3), ("clothes", 3), ("rent van", 1)] {
+        let body = format!(r#"{{"title":"{}","parent_id":{}}}"#, title, parent_id);
+        let (status, todo) = send(app.clone(), "POST", "/api/todos", Some(&body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(todo["parent_id"], parent_id);
+    }
+    send(app.clone(), "PATCH", "/api/todos/4", Some(r#"{"completed":true}"#)).await;
+
+    let (status, tree) = send(app.clone(), "GET", "/api/todos/tree", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let roots: Vec<&str> = tree.as_array().unwrap().iter().map(|node| node["title"].as_str().unwrap()).collect();
+    assert_eq!(roots, vec!["move", "walk"]);
+    assert_eq!(tree[0]["progress"], serde_json::json!({"completed": 0, "total": 2}));
+    assert_eq!(tree[0]["subtasks"][0]["title"], "pack");
+    assert_eq!(tree[0]["subtasks"][0]["progress"], serde_json::json!({"completed": 1, "total": 2}));
+    assert!(tree[1]["subtasks"].as_array().unwrap().is_empty());
+
+    let (status, json) = send(app.clone(), "PATCH", "/api/todos/1", Some(r#"{"parent_id":5}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "Expected a todo not to move below its own subtask");
+    assert_eq!(json["details"][0]["field"], "parent_id");
+    let (status, _) = send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"x","parent_id":99}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    send(app.clone(), "PATCH", "/api/todos/2", Some(r#"{"completed":true}"#)).await;
+    let (_, todo) = send(app.clone(), "PATCH", "/api/todos/1?cascade=true", Some(r#"{"completed":true}"#)).await;
+    assert_eq!(todo["completed"], true);
+    let (_, open, _) = get_page(app.clone(), "/api/todos?completed=false").await;
+    assert!(open.is_empty(), "Expected the whole subtree to be completed");
+
+    send(app.clone(), "PATCH", "/api/todos/5", Some(r#"{"completed":false}"#)).await;
+    let (_, message) = send(app.clone(), "POST", "/api/todos/archive_completed", None).await;
+    assert_eq!(message["text"], "Archived 1 completed todo(s)", "Expected trees to be archived only as a whole");
+    send(app.clone(), "PATCH", "/api/todos/5", Some(r#"{"completed":true}"#)).await;
+    let (_, message) = send(app.clone(), "POST", "/api/todos/archive_completed", None).await;
+    assert_eq!(message["text"], "Archived 5 completed todo(s)");
+
+    let app = app_with_todos(&[("move", 3, false)]).await;
+    send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"pack","parent_id":1}"#)).await;
+    let (_, todo) = send(app.clone(), "PATCH", "/api/todos/2", Some(r#"{"parent_id":null}"#)).await;
+    assert!(todo["parent_id"].is_null());
+    send(app.clone(), "PATCH", "/api/todos/2", Some(r#"{"parent_id":1}"#)).await;
+    send(app.clone(), "DELETE", "/api/todos/1", None).await;
+    let (_, all, _) = get_page(app, "/api/todos").await;
+    assert!(all.is_empty(), "Expected deleting a todo to delete its subtasks");
+}
+
+#[tokio::test]
+async fn test_router_restores_archived_todos() {
+    let app = app_with_todos(&[("move", 3, true), ("walk", 1, true), ("read", 2, false)]).await;
+    send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"pack","parent_id":1}"#)).await;
+    send(app.clone(), "PATCH", "/api/todos/4", Some(r#"{"completed":true}"#)).await;
+    send(app.clone(), "POST", "/api/todos/1/tags", Some(r#"{"name":"home"}"#)).await;
+    send(app.clone(), "POST", "/api/todos/archive_completed", None).await;
+    let (status, archived) = send(app.clone(), "GET", "/api/todos/archived", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let archived_id = |title: &str| archived.as_array().unwrap().iter().find(|todo| todo["title"] == title).unwrap()["id"].clone();
+
+    let body = format!(r#"{{"ids":[{}]}}"#, archived_id("move"));
+    let (status, restored) = send(app.clone(), "POST", "/api/todos/restore", Some(&body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let titles: Vec<&str> = restored.as_array().unwrap().iter().map(|todo| todo["title"].as_str().unwrap()).collect();
+    assert_eq!(titles, vec!["move", "pack"], "Expected subtasks to come back with their parent");
+    assert_eq!(restored[0]["id"], archived_id("move"), "Expected restored todos to keep their id");
+    assert_eq!(restored[0]["priority"], 3);
+    assert_eq!(restored[0]["completed"], true);
+    assert_eq!(restored[0]["tags"][0]["name"], "home");
+    assert_eq!(restored[1]["parent_id"], restored[0]["id"]);
+
+    let body = format!(r#"{{"ids":[{}],"reset_completed":true}}"#, archived_id("walk"));
+    let (_, restored) = send(app.clone(), "POST", "/api/todos/restore", Some(&body)).await;
+    assert_eq!(restored[0]["completed"], false);
+    let (_, archived) = send(app.clone(), "GET", "/api/todos/archived", None).await;
+    assert!(archived.as_array().unwrap().is_empty());
+    let (_, all, _) = get_page(app.clone(), "/api/todos?sort=created_asc").await;
+    assert_eq!(all, vec!["move", "walk", "read", "pack"], "Expected restored todos to go back to their place in creation order");
+
+    let (status, json) = send(app.clone(), "POST", "/api/todos/restore", Some(r#"{"ids":[99]}"#)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(json["message"], "Archived todo with id 99 not found");
+    let (status, json) = send(app, "POST", "/api/todos/restore", Some(r#"{"ids":[]}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "ids");
+}
+
+#[tokio::test]
+async fn test_router_archives_single_todos_under_their_id() {
+    let app = app_with_todos(&[("call mom", 1, false), ("pay rent", 2, false)]).await;
+    let (_, completed) = send(app.clone(), "PATCH", "/api/todos/2", Some(r#"{"completed":true}"#)).await;
+    assert!(completed["completed_at"].is_string());
+
+    let (status, archived) = send(app.clone(), "POST", "/api/todos/2/archive", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(archived["id"], 2);
+    assert_eq!(archived["completed_at"], completed["completed_at"]);
+    let (status, _) = send(app.clone(), "GET", "/api/todos/2", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, linked) = send(app.clone(), "GET", "/api/todos/archived/2", None).await;
+    assert_eq!(status, StatusCode::OK, "Expected the old id to lead to the archived todo");
+    assert_eq!(linked["title"], "pay rent");
+
+    let (status, archived) = send(app.clone(), "POST", "/api/todos/1/archive", None).await;
+    assert_eq!(status, StatusCode::OK, "Expected open todos to be archivable one by one");
+    assert_eq!(archived["completed"], false);
+    let (status, _) = send(app.clone(), "POST", "/api/todos/1/archive", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(app, "GET", "/api/todos/archived/99", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_router_blocks_todos_on_their_dependencies() {
+    let app = app_with_todos(&[("deploy", 3, false), ("test", 2, false), ("build", 1, false)]).await;
+    let (status, todo) = send(app.clone(), "POST", "/api/todos/1/blockers", Some(r#"{"blocker_id":2}"#)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(todo["blocked"], true);
+    send(app.clone(), "POST", "/api/todos/2/blockers", Some(r#"{"blocker_id":3}"#)).await;
+    let (_, todo) = send(app.clone(), "POST", "/api/todos/2/blockers", Some(r#"{"blocker_id":3}"#)).await;
+    assert_eq!(todo["blocked"], true, "Expected adding a blocker twice to be harmless");
+
+    let (status, json) = send(app.clone(), "POST", "/api/todos/3/blockers", Some(r#"{"blocker_id":1}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "Expected cycles to be rejected");
+    assert_eq!(json["details"][0]["field"], "blocker_id");
+    let (status, _) = send(app.clone(), "POST", "/api/todos/3/blockers", Some(r#"{"blocker_id":3}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = send(app.clone(), "POST", "/api/todos/3/blockers", Some(r#"{"blocker_id":99}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, todos) = send(app.clone(), "GET", "/api/todos", None).await;
+    let blocked: Vec<bool> = todos.as_array().unwrap().iter().map(|todo| todo["blocked"].as_bool().unwrap()).collect();
+    assert_eq!(blocked, vec![true, true, false]);
+    let (status, _) = send(app.clone(), "POST", "/api/todos/complete", Some(r#"{"id":2}"#)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = send(app.clone(), "PATCH", "/api/todos/2", Some(r#"{"completed":true}"#)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (_, graph) = send(app.clone(), "GET", "/api/todos/graph", None).await;
+    assert_eq!(graph["nodes"].as_array().unwrap().len(), 3);
+    assert_eq!(graph["edges"], serde_json::json!([{"todo_id": 1, "blocker_id": 2}, {"todo_id": 2, "blocker_id": 3}]));
+
+    let (status, _) = send(app.clone(), "POST", "/api/todos/complete", Some(r#"{"id":3}"#)).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let (status, todo) = send(app.clone(), "POST", "/api/todos/complete", Some(r#"{"id":2}"#)).await;
+    assert_eq!(status, StatusCode::ACCEPTED, "Expected a todo to be completable once its blockers are done");
+    assert_eq!(todo["blocked"], false);
+    send(app.clone(), "POST", "/api/todos/archive_completed", None).await;
+    let (_, todo) = send(app.clone(), "GET", "/api/todos/1", None).await;
+    assert_eq!(todo["blocked"], false);
+    let (_, graph) = send(app.clone(), "GET", "/api/todos/graph", None).await;
+    assert!(graph["edges"].as_array().unwrap().is_empty(), "Expected archived blockers to leave the graph");
+
+    let app = app_with_todos(&[("deploy", 3, false), ("test", 2, false)]).await;
+    send(app.clone(), "POST", "/api/todos/1/blockers", Some(r#"{"blocker_id":2}"#)).await;
+    let (status, todo) = send(app.clone(), "DELETE", "/api/todos/1/blockers/2", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(todo["blocked"], false);
+    let (status, _) = send(app, "DELETE", "/api/todos/1/blockers/2", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_router_tags_todos_and_filters_by_tag() {
+    let app = app_with_todos(&[("walk", 1, false), ("code", 5, false), ("shop", 3, true)]).await;
+
+    let (status, todo) = send(app.clone(), "POST", "/api/todos/1/tags", Some(r##"{"name":" outside ","color":"#2E8B57"}"##)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(todo["tags"][0]["name"], "outside");
+    assert_eq!(todo["tags"][0]["color"], "#2e8b57");
+    send(app.clone(), "POST", "/api/todos/1/tags", Some(r#"{"name":"errand"}"#)).await;
+    send(app.clone(), "POST", "/api/todos/3/tags", Some(r#"{"name":"errand"}"#)).await;
+    let (_, todo) = send(app.clone(), "POST", "/api/todos/3/tags", Some(r#"{"name":"errand"}"#)).await;
+    assert_eq!(todo["tags"].as_array().unwrap().len(), 1, "Expected tagging twice to be harmless");
+    assert_eq!(todo["tags"][0]["color"], "#808080");
+
+    let (_, errands, _) = get_page(app.clone(), "/api/todos?tag=errand").await;
+    assert_eq!(errands, vec!["shop", "walk"]);
+    let (_, open_errands, _) = get_page(app.clone(), "/api/todos?tag=errand&completed=false").await;
+    assert_eq!(open_errands, vec!["walk"]);
+    let (_, todo) = send(app.clone(), "GET", "/api/todos/1", None).await;
+    let names: Vec<&str> = todo["tags"].as_array().unwrap().iter().map(|t| t["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["errand", "outside"]);
+
+    let (status, todo) = send(app.clone(), "DELETE", "/api/todos/1/tags/errand", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(todo["tags"].as_array().unwrap().len(), 1);
+    let (_, errands, _) = get_page(app.clone(), "/api/todos?tag=errand").await;
+    assert_eq!(errands, vec!["shop"]);
+    let (_, tags) = send(app.clone(), "GET", "/api/tags", None).await;
+    let names: Vec<&str> = tags.as_array().unwrap().iter().map(|t| t["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["errand", "outside"], "Expected removed tags to stay available");
+
+    send(app.clone(), "POST", "/api/todos/archive_completed", None).await;
+    let (_, archived) = send(app, "GET", "/api/todos/complete", None).await;
+    assert_eq!(archived[0]["title"], "shop");
+    assert_eq!(archived[0]["tags"][0]["name"], "errand", "Expected tags to move to the archive");
+}
+
+#[tokio::test]
+async fn test_router_rejects_invalid_tags() {
+    let app = app_with_todos(&[("walk", 1, false)]).await;
+
+    let (status, json) = send(app.clone(), "POST", "/api/todos/1/tags", Some(r#"{"name":" ","color":"green"}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let fields: Vec<&str> = json["details"].as_array().unwrap().iter().map(|d| d["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, vec!["name", "color"]);
+    let (status, _) = send(app.clone(), "POST", "/api/todos/42/tags", Some(r#"{"name":"errand"}"#)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(app.clone(), "DELETE", "/api/todos/1/tags/errand", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "Expected removing a tag the todo lacks to fail");
+    let (status, json) = send(app, "GET", "/api/todos?tag=", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "tag");
+}
+
+#[tokio::test]
+async fn test_router_searches_active_and_archived_titles() {
+    let app = app_with_todos(&[("Walk the dog", 1, true), ("Buy dog food", 2, false), ("Call <Bob>", 1, false)]).await;
+    send(app.clone(), "POST", "/api/todos/archive_completed", None).await;
+
+    let (status, json) = send(app.clone(), "GET", "/api/search?q=dog", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let hits = json.as_array().unwrap();
+    assert_eq!(hits.len(), 2);
+    let sources: Vec<&str> = hits.iter().map(|h| h["source"].as_str().unwrap()).collect();
+    assert!(sources.contains(&"active") && sources.contains(&"archived"));
+
+    let (_, json) = send(app.clone(), "GET", "/api/search?q=wal+DO&scope=archived", None).await;
+    assert_eq!(json.as_array().unwrap().len(), 1);
+    assert_eq!(json[0]["snippet"], "<mark>Walk</mark> the <mark>dog</mark>");
+
+    let (_, json) = send(app.clone(), "GET", "/api/search?q=dog&scope=active", None).await;
+    assert_eq!(json.as_array().unwrap().len(), 1);
+    assert_eq!(json[0]["title"], "Buy dog food");
+
+    let (_, json) = send(app.clone(), "GET", "/api/search?q=bob", None).await;
+    assert_eq!(json[0]["snippet"], "Call <mark>&lt;Bob&gt;</mark>");
+
+    let (status, json) = send(app, "GET", "/api/search?q=%21%21&limit=500", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let fields: Vec<&str> = json["details"].as_array().unwrap().iter().map(|d| d["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, vec!["q", "limit"]);
+}
+
+#[tokio::test]
+async fn test_router_renders_and_searches_notes() {
+    let app = new_app().await;
+    let body = r#"{"title":"Plan trip","notes":"Book **flights** <script>alert(1)</script>\n\n[map](javascript:alert(2))"}"#;
+    let (status, todo) = send(app.clone(), "POST", "/api/todos", Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(todo["notes"].as_str().unwrap().starts_with("Book **flights**"));
+
+    let (status, json) = send(app.clone(), "GET", "/api/todos/1/notes", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let html = json["html"].as_str().unwrap();
+    assert!(html.contains("<strong>flights</strong>"));
+    assert!(!html.contains("<script") && !html.contains("javascript:"), "Expected the HTML to be sanitized, got {}", html);
+
+    let (_, json) = send(app.clone(), "GET", "/api/search?q=flights", None).await;
+    assert_eq!(json[0]["title"], "Plan trip");
+    assert!(json[0]["notes_snippet"].as_str().unwrap().contains("<mark>**flights**</mark>"));
+    let (_, json) = send(app.clone(), "GET", "/api/search?q=plan", None).await;
+    assert!(json[0]["notes_snippet"].is_null(), "Expected no notes snippet for a title match");
+
+    let (_, todo) = send(app.clone(), "PATCH", "/api/todos/1", Some(r#"{"notes":"- passports"}"#)).await;
+    assert_eq!(todo["notes"], "- passports");
+    let (_, todo) = send(app.clone(), "PATCH", "/api/todos/1", Some(r#"{"title":"Plan the trip"}"#)).await;
+    assert_eq!(todo["notes"], "- passports", "Expected notes that are left out to stay");
+    let (_, json) = send(app.clone(), "GET", "/api/todos/1/notes", None).await;
+    assert_eq!(json["html"], "<ul>\n<li>passports</li>\n</ul>\n");
+    let (_, todo) = send(app.clone(), "PATCH", "/api/todos/1", Some(r#"{"notes":null}"#)).await;
+    assert!(todo["notes"].is_null());
+    let (_, json) = send(app.clone(), "GET", "/api/todos/1/notes", None).await;
+    assert_eq!(json["html"], "");
+
+    let long = format!(r#"{{"notes":"{}"}}"#, "a".repeat(10_001));
+    let (status, json) = send(app, "PATCH", "/api/todos/1", Some(&long)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "notes");
+}
+
+/// Posts `content` as the `file` part of a multipart upload and returns the
+/// status with the JSON body.
+async fn upload(app: axum::Router, uri: &str, file_name: &str, content: &[u8]) -> (StatusCode, serde_json::Value) {
+    let mut body = format!(
+        "--XBOUNDARY\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: text/plain\r\n\r\n",
+        file_name).into_bytes();
+    body.extend_from_slice(content);
+    body.extend_from_slice(b"\r\n--XBOUNDARY--\r\n");
+    let request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header("content-type", "multipart/form-data; boundary=XBOUNDARY")
+        .body(Body::from(body))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null))
+}
+
+fn stored_files(dir: &std::path::Path) -> usize {
+    std::fs::read_dir(dir).map(|entries| entries.count()).unwrap_or(0)
+}
+
+#[tokio::test]
+async fn test_router_uploads_downloads_and_deletes_attachments() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = AttachmentConfig { dir: dir.path().to_path_buf(), max_bytes: 64 };
+    let db = Arc::new(InMemoryTodoListDao::new());
+    let app = build_app_with_attachments(db, ValidationRules::default(), None, config);
+    let app = sign_in_as(app, "alice@example.com").await;
+    for title in ["invoice", "receipt", "tickets"] {
+        send(app.clone(), "POST", "/api/todos", Some(&format!(r#"{{"title":"{}"}}"#, title))).await;
+    }
+
+    let (status, attachment) = upload(app.clone(), "/api/todos/1/attachments", "../../etc/invoice.txt", b"total: 42").await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(attachment["file_name"], "invoice.txt", "Expected file names to lose their path");
+    assert_eq!(attachment["content_type"], "text/plain");
+    assert_eq!(attachment["size"], 9);
+    assert_eq!(attachment["sha256"], "476394007902e84323f80bda0a97a9341db353d90df97718c3a2502dcb22ef41");
+    assert!(attachment.get("storage_key").is_none());
+    assert_eq!(stored_files(dir.path()), 1);
+
+    let (_, list) = send(app.clone(), "GET", "/api/todos/1/attachments", None).await;
+    assert_eq!(list.as_array().unwrap().len(), 1);
+    let request = Request::builder().uri("/api/todos/1/attachments/1").body(Body::empty()).unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "text/plain");
+    assert_eq!(response.headers()[header::CONTENT_LENGTH], "9");
+    assert_eq!(response.headers()[header::CONTENT_DISPOSITION], "attachment; filename=\"invoice.txt\"");
+    assert_eq!(response.headers()[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+    assert_eq!(&response.into_body().collect().await.unwrap().to_bytes()[..], b"total: 42");
+    let (status, _) = send(app.clone(), "GET", "/api/todos/2/attachments/1", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "Expected attachments to be reached through their own todo");
+
+    let (status, _) = upload(app.clone(), "/api/todos/1/attachments", "big.bin", &[0; 65]).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(stored_files(dir.path()), 1, "Expected oversized uploads to leave nothing behind");
+    let (status, _) = upload(app.clone(), "/api/todos/99/attachments", "a.txt", b"a").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/todos/1/attachments")
+        .header("content-type", "multipart/form-data; boundary=XBOUNDARY")
+        .body(Body::from("--XBOUNDARY--\r\n"))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST, "Expected uploads without a file part to be rejected");
+
+    let (status, _) = send(app.clone(), "DELETE", "/api/todos/1/attachments/1", None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(stored_files(dir.path()), 0);
+
+    upload(app.clone(), "/api/todos/2/attachments", "a.txt", b"a").await;
+    upload(app.clone(), "/api/todos/3/attachments", "b.txt", b"b").await;
+    send(app.clone(), "PATCH", "/api/todos/3", Some(r#"{"completed":true}"#)).await;
+    send(app.clone(), "POST", "/api/todos/archive_completed", None).await;
+    assert_eq!(stored_files(dir.path()), 2, "Expected archived todos to keep their files");
+    send(app.clone(), "DELETE", "/api/todos/2", None).await;
+    assert_eq!(stored_files(dir.path()), 1, "Expected deleting a todo to delete its files");
+}
+
+#[tokio::test]
+async fn test_router_reports_attachments_whose_file_is_gone_as_not_found() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = AttachmentConfig { dir: dir.path().to_path_buf(), ..AttachmentConfig::default() };
+    let app = build_app_with_attachments(Arc::new(InMemoryTodoListDao::new()), ValidationRules::default(), None, config);
+    let app = sign_in_as(app, "alice@example.com").await;
+    send(app.clone(), "POST", "/api/todos", Some(r#"{"title":"invoice"}"#)).await;
+    upload(app.clone(), "/api/todos/1/attachments", "invoice.txt", b"total: 42").await;
+    for entry in std::fs::read_dir(dir.path()).unwrap() {
+        std::fs::remove_file(entry.unwrap().path()).unwrap();
+    }
+
+    let (status, json) = send(app.clone(), "GET", "/api/todos/1/attachments/1", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(json["error"], "not_found");
+    let (status, _) = send(app, "DELETE", "/api/todos/1/attachments/1", None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT, "Expected the dangling metadata to still be deletable");
+}
+
+#[tokio::test]
+async fn test_router_manages_lists() {
+    let app = new_app().await;
+    let (status, json) = send(app.clone(), "POST", "/api/lists", Some(r#"{"name":"  Groceries "}"#)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(json["id"], 2);
+    assert_eq!(json["name"], "Groceries");
+
+    let (status, json) = send(app.clone(), "PATCH", "/api/lists/2", Some(r#"{"name":"Shopping"}"#)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["name"], "Shopping");
+    let (_, json) = send(app.clone(), "GET", "/api/lists", None).await;
+    let names: Vec<&str> = json.as_array().unwrap().iter().map(|l| l["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["Default", "Shopping"]);
+
+    let (status, json) = send(app.clone(), "POST", "/api/lists", Some(r#"{"name":" "}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "name");
+    let (status, _) = send(app.clone(), "DELETE", "/api/lists/1", None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _) = send(app.clone(), "DELETE", "/api/lists/2", None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, json) = send(app.clone(), "GET", "/api/lists/2", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(json["message"], "List with id 2 not found");
+    let (status, json) = send(app, "GET", "/api/lists/abc/todos", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(json["details"][0]["field"], "list_id");
+}
+
+#[tokio::test]
+async fn test_router_scopes_todos_to_their_list() {
+    let app = app_with_todos(&[("Default todo", 1, true)]).await;
+    send(app.clone(), "POST", "/api/lists", Some(r#"{"name":"Work"}"#)).await;
+    let (status, json) = send(app.clone(), "POST", "/api/lists/2/todos", Some(r#"{"title":"Work todo"}"#)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(json["list_id"], 2);
+    let work_id = json["id"].clone();
+    send(app.clone(), "PATCH", &format!("/api/lists/2/todos/{}", work_id), Some(r#"{"completed":true}"#)).await;
+
+    let (_, titles, _) = get_page(app.clone(), "/api/todos").await;
+    assert_eq!(titles, vec!["Default todo"]);
+    let (_, titles, _) = get_page(app.clone(), "/api/lists/2/todos").await;
+    assert_eq!(titles, vec!["Work todo"]);
+    let (status, _) = send(app.clone(), "GET", &format!("/api/todos/{}", work_id), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "Expected todos of other lists to be hidden");
+
+    let (_, json) = send(app.clone(), "POST", "/api/lists/2/todos/archive_completed", None).await;
+    assert_eq!(json["text"], "Archived 1 completed todo(s)");
+    let (_, archived) = send(app.clone(), "GET", "/api/lists/2/todos/complete", None).await;
+    assert_eq!(archived[0]["title"], "Work todo");
+    let (_, archived) = send(app.clone(), "GET", "/api/todos/complete", None).await;
+    assert!(archived.as_array().unwrap().is_empty(), "Expected the default list to keep its completed todo");
+    let (_, hits) = send(app.clone(), "GET", "/api/lists/2/search?q=todo", None).await;
+    assert_eq!(hits.as_array().unwrap().len(), 1);
+
+    send(app.clone(), "POST", "/api/lists/2/todos", Some(r#"{"title":"Another"}"#)).await;
+    send(app.clone(), "POST", "/api/lists/2/todos/clear", None).await;
+    let (_, titles, _) = get_page(app.clone(), "/api/lists/2/todos").await;
+    assert!(titles.is_empty());
+    let (_, titles, _) = get_page(app.clone(), "/api/todos").await;
+    assert_eq!(titles, vec!["Default todo"], "Expected clearing a list to leave other lists alone");
+
+    let (status, _) = send(app, "POST", "/api/lists/7/todos", Some(r#"{"title":"Nowhere"}"#)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+async fn send_with_cookie(app: axum::Router, method: &str, uri: &str, body: Option<&str>, cookie: Option<&str>)
+    -> (StatusCode, serde_json::Value, Option<String>) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json");
+    if let Some(cookie) = cookie {
+        request = request.header(header::COOKIE, cookie);
+    }
+    let response = app.oneshot(request.body(Body::from(body.unwrap_or("").to_string())).unwrap()).await.unwrap();
+    let status = response.status();
+    let set_cookie = response.headers().get(header::SET_COOKIE).map(|v| v.to_str().unwrap().to_string());
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null), set_cookie)
+}
+
+#[tokio::test]
+async fn test_router_requires_a_session() {
+    let app = build_app(Arc::new(InMemoryTodoListDao::new()));
+    let (status, json) = send(app.clone(), "GET", "/api/todos", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(json["error"], "unauthorized");
+    let (status, _) = send(app.clone(), "GET", "/api/lists", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = send_with_cookie(app.clone(), "GET", "/api/todos", None, Some("session=forged")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = send(app, "GET", "/", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_router_registers_logs_in_and_out() {
+    let app = build_app(Arc::new(InMemoryTodoListDao::new()));
+    let credentials = r#"{"email":" Alice@Example.com ","password":"correct horse"}"#;
+    let (status, json, cookie) = send_with_cookie(app.clone(), "POST", "/api/auth/register", Some(credentials), None).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(json["email"], "alice@example.com");
+    let cookie = cookie.unwrap();
+    assert!(cookie.contains("HttpOnly"), "Expected the session cookie to be hidden from scripts");
+
+    let (status, _, _) = send_with_cookie(app.clone(), "POST", "/api/auth/register", Some(credentials), None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, json, _) = send_with_cookie(app.clone(), "POST", "/api/auth/register", Some(r#"{"email":"nope","password":"short"}"#), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let fields: Vec<&str> = json["details"].as_array().unwrap().iter().map(|d| d["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, vec!["email", "password"]);
+
+    let (status, json, _) = send_with_cookie(app.clone(), "POST", "/api/auth/login", Some(r#"{"email":"alice@example.com","password":"wrong horse"}"#), None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(json["message"], "Invalid email or password");
+    let (status, _, cookie) = send_with_cookie(app.clone(), "POST", "/api/auth/login", Some(credentials), None).await;
+    assert_eq!(status, StatusCode::OK);
+    let session = cookie.unwrap().split(';').next().unwrap().to_string();
+
+    let (status, json, _) = send_with_cookie(app.clone(), "GET", "/api/auth/me", None, Some(&session)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["email"], "alice@example.com");
+    let (status, _, cookie) = send_with_cookie(app.clone(), "POST", "/api/auth/logout", None, Some(&session)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(cookie.unwrap().contains("Max-Age=0"));
+    let (status, _, _) = send_with_cookie(app, "GET", "/api/auth/me", None, Some(&session)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED, "Expected the session to end with logout");
+}
+
+#[tokio::test]
+async fn test_router_keeps_users_apart() {
+    let app = build_app(Arc::new(InMemoryTodoListDao::new()));
+    let alice = register(app.clone(), "alice@example.com").await;
+    let bob = register(app.clone(), "bob@example.com").await;
+
+    send_with_cookie(app.clone(), "POST", "/api/todos", Some(r#"{"title":"Alice's secret"}"#), Some(&alice)).await;
+    let (_, todos, _) = send_with_cookie(app.clone(), "GET", "/api/todos", None, Some(&bob)).await;
+    assert!(todos.as_array().unwrap().is_empty(), "Expected bob to start with an empty default list");
+
+    let (_, lists, _) = send_with_cookie(app.clone(), "GET", "/api/lists", None, Some(&alice)).await;
+    let alice_list = lists[0]["id"].as_u64().unwrap();
+    assert_eq!(lists[0]["is_default"], true);
+    let (status, _, _) = send_with_cookie(app.clone(), "GET", &format!("/api/lists/{}/todos", alice_list), None, Some(&bob)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "Expected other users' lists to look like they do not exist");
+    let (status, _, _) = send_with_cookie(app, "GET", "/api/todos/1", None, Some(&bob)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+async fn send_with_token(app: axum::Router, method: &str, uri: &str, body: Option<&str>, token: &str) -> (StatusCode, serde_json::Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", "application/json")
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .body(Body::from(body.unwrap_or("").to_string()))
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null))
+}
+
+#[tokio::test]
+async fn test_router_checks_api_token_scopes() {
+    let app = build_app(Arc::new(InMemoryTodoListDao::new()));
+    let alice = register(app.clone(), "alice@example.com").await;
+    let (status, reader, _) = send_with_cookie(app.clone(), "POST", "/api/tokens",
+        Some(r#"{"name":"dashboard","scopes":["todos:read"]}"#), Some(&alice)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(reader["scopes"], serde_json::json!(["todos:read"]));
+    let reader = reader["token"].as_str().unwrap().to_string();
+    assert!(reader.starts_with("todo_"));
+    let (_, writer, _) = send_with_cookie(app.clone(), "POST", "/api/tokens",
+        Some(r#"{"name":"ci","scopes":["todos:read","todos:write"],"expires_in_days":7}"#), Some(&alice)).await;
+    let writer = writer["token"].as_str().unwrap().to_string();
+
+    let (status, _) = send_with_token(app.clone(), "POST", "/api/todos", Some(r#"{"title":"From CI"}"#), &writer).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, todos) = send_with_token(app.clone(), "GET", "/api/todos", None, &reader).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(todos[0]["title"], "From CI");
+
+    let (status, json) = send_with_token(app.clone(), "POST", "/api/todos", Some(r#"{"title":"Nope"}"#), &reader).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(json["message"], "The token lacks the todos:write scope");
+    let (status, _) = send_with_token(app.clone(), "POST", "/api/todos/archive_completed", None, &writer).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "Expected archiving to need the archive:write scope");
+    let (status, _) = send_with_token(app.clone(), "POST", "/api/todos/restore", Some(r#"{"ids":[1]}"#), &writer).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "Expected restoring to need the archive:write scope");
+    let (status, _) = send_with_token(app.clone(), "POST", "/api/todos/1/archive", None, &writer).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send_with_token(app.clone(), "GET", "/api/tokens", None, &writer).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "Expected tokens to be unable to manage tokens");
+
+    let (_, todo) = send_with_token(app.clone(), "POST", "/api/todos", Some(r#"{"title":"Tagged"}"#), &writer).await;
+    for tag in ["archive", "restore", "invites"] {
+        let uri = format!("/api/todos/{}/tags", todo["id"]);
+        let (status, _) = send_with_token(app.clone(), "POST", &uri, Some(&format!(r#"{{"name":"{}"}}"#, tag)), &writer).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = send_with_token(app.clone(), "DELETE", &format!("{}/{}", uri, tag), None, &writer).await;
+        assert_eq!(status, StatusCode::OK, "Expected removing the tag '{}' to need only todos:write", tag);
+    }
+    let (_, list, _) = send_with_cookie(app.clone(), "POST", "/api/lists", Some(r#"{"name":"Scratch"}"#), Some(&alice)).await;
+    let (status, json) = send_with_token(app.clone(), "DELETE", &format!("/api/lists/{}", list["id"]), None, &writer).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "Expected deleting a list and its archive to need archive:write");
+    assert_eq!(json["message"], "The token lacks the archive:write scope");
+    let (status, _) = send_with_token(app.clone(), "POST", &format!("/api/lists/{}/invites", list["id"]),
+        Some(r#"{"email":"bob@example.com","role":"viewer"}"#), &writer).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "Expected tokens to be unable to share lists");
+    let (status, _) = send_with_token(app.clone(), "GET", "/api/todos", None, "todo_forged").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (_, tokens, _) = send_with_cookie(app.clone(), "GET", "/api/tokens", None, Some(&alice)).await;
+    let tokens = tokens.as_array().unwrap();
+    assert_eq!(tokens.len(), 2);
+    assert!(tokens.iter().all(|t| t.get("token").is_none() && !t["last_used_at"].is_null()));
+    let reader_id = tokens.iter().find(|t| t["name"] == "dashboard").unwrap()["id"].as_u64().unwrap();
+
+    let bob = register(app.clone(), "bob@example.com").await;
+    let (status, _, _) = send_with_cookie(app.clone(), "DELETE", &format!("/api/tokens/{}", reader_id), None, Some(&bob)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, _) = send_with_cookie(app.clone(), "DELETE", &format!("/api/tokens/{}", reader_id), None, Some(&alice)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send_with_token(app, "GET", "/api/todos", None, &reader).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED, "Expected revoked tokens to stop working");
+}
+
+#[tokio::test]
+async fn test_router_rejects_invalid_api_tokens_requests() {
+    let app = build_app(Arc::new(InMemoryTodoListDao::new()));
+    let alice = register(app.clone(), "alice@example.com").await;
+    let (status, json, _) = send_with_cookie(app, "POST", "/api/tokens",
+        Some(r#"{"name":" ","scopes":["todos:delete"],"expires_in_days":0}"#), Some(&alice)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let fields: Vec<&str> = json["details"].as_array().unwrap().iter().map(|d| d["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, vec!["name", "scopes", "expires_in_days"]);
+}
+
+#[tokio::test]
+async fn test_router_shares_lists_by_role() {
+    let app = build_app(Arc::new(InMemoryTodoListDao::new()));
+    let alice = register(app.clone(), "alice@example.com").await;
+    let bob = register(app.clone(), "bob@example.com").await;
+    let carol = register(app.clone(), "carol@example.com").await;
+    let (_, list, _) = send_with_cookie(app.clone(), "POST", "/api/lists", Some(r#"{"name":"Trip"}"#), Some(&alice)).await;
+    let list = format!("/api/lists/{}", list["id"]);
+    send_with_cookie(app.clone(), "POST", &format!("{}/todos", list), Some(r#"{"title":"Book flights"}"#), Some(&alice)).await;
+
+    let invite = |email: &str, role: &str| format!(r#"{{"email":"{}","role":"{}"}}"#, email, role);
+    let (status, viewer_invite, _) = send_with_cookie(app.clone(), "POST", &format!("{}/invites", list), Some(&invite("bob@example.com", "viewer")), Some(&alice)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(viewer_invite["role"], "viewer");
+    let (_, editor_invite, _) = send_with_cookie(app.clone(), "POST", &format!("{}/invites", list), Some(&invite("carol@example.com", "editor")), Some(&alice)).await;
+    let accept = |invite: &serde_json::Value| format!(r#"{{"token":"{}"}}"#, invite["token"].as_str().unwrap());
+
+    let (status, _, _) = send_with_cookie(app.clone(), "POST", "/api/invites/accept", Some(&accept(&viewer_invite)), Some(&carol)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "Expected invites to only work for the invited email");
+    let (status, member, _) = send_with_cookie(app.clone(), "POST", "/api/invites/accept", Some(&accept(&viewer_invite)), Some(&bob)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(member["role"], "viewer");
+    let (status, _, _) = send_with_cookie(app.clone(), "POST", "/api/invites/accept", Some(&accept(&viewer_invite)), Some(&bob)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "Expected invites to be used up");
+    send_with_cookie(app.clone(), "POST", "/api/invites/accept", Some(&accept(&editor_invite)), Some(&carol)).await;
+
+    let (status, todos, _) = send_with_cookie(app.clone(), "GET", &format!("{}/todos", list), None, Some(&bob)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(todos[0]["title"], "Book flights");
+    let todo_id = todos[0]["id"].as_u64().unwrap();
+    let (status, json, _) = send_with_cookie(app.clone(), "DELETE", &format!("{}/todos/{}", list, todo_id), None, Some(&bob)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(json["error"], "forbidden");
+    let (status, _, _) = send_with_cookie(app.clone(), "POST", &format!("{}/todos/clear", list), None, Some(&bob)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _, _) = send_with_cookie(app.clone(), "PATCH", &format!("{}/todos/{}", list, todo_id), Some(r#"{"completed":true}"#), Some(&carol)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send_with_cookie(app.clone(), "POST", &format!("{}/todos/archive_completed", list), None, Some(&carol)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send_with_cookie(app.clone(), "POST", &format!("{}/invites", list), Some(&invite("dave@example.com", "viewer")), Some(&carol)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "Expected only owners to invite");
+    let (status, _, _) = send_with_cookie(app.clone(), "DELETE", &list, None, Some(&carol)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (_, members, _) = send_with_cookie(app.clone(), "GET", &format!("{}/members", list), None, Some(&bob)).await;
+    let roles: Vec<(&str, &str)> = members.as_array().unwrap().iter()
+        .map(|m| (m["email"].as_str().unwrap(), m["role"].as_str().unwrap()))
+        .collect();
+    assert_eq!(roles, vec![("alice@example.com", "owner"), ("bob@example.com", "viewer"), ("carol@example.com", "editor")]);
+    let (_, lists, _) = send_with_cookie(app, "GET", "/api/lists", None, Some(&bob)).await;
+    let names: Vec<&str> = lists.as_array().unwrap().iter().map(|l| l["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["Default", "Trip"], "Expected shared lists to follow the member's own default list");
+}
+
+#[tokio::test]
+async fn test_router_rejects_invalid_invites() {
+    let app = new_app().await;
+    let (status, json) = send(app, "POST", "/api/lists/1/invites", Some(r#"{"email":"bob","role":"admin"}"#)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let fields: Vec<&str> = json["details"].as_array().unwrap().iter().map(|d| d["field"].as_str().unwrap()).collect();
+    assert_eq!(fields, vec!["email", "role"]);
+}
+
+/// A clock that stands still until the test moves it.
+struct TestClock(Mutex<DateTime<Utc>>);
+
+impl TestClock {
+    fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+#[tokio::test]
+async fn test_router_judges_overdue_todos_by_the_app_clock() {
+    let clock = Arc::new(TestClock(Mutex::new("2030-01-01T00:00:00Z".parse().unwrap())));
+    let app = build_app_with_retention(Arc::new(InMemoryTodoListDao::new()), ValidationRules::default(), None,
+        AttachmentConfig::default(), RetentionConfig::default(), clock.clone());
+    let app = sign_in_as(app, "alice@example.com").await;
+    for body in [r#"{"title":"sooner","due_at":"2030-01-02T00:00:00Z"}"#, r#"{"title":"later","due_at":"2030-02-01T00:00:00Z"}"#] {
+        send(app.clone(), "POST", "/api/todos", Some(body)).await;
+    }
+
+    let (_, overdue, _) = get_page(app.clone(), "/api/todos/overdue").await;
+    assert!(overdue.is_empty(), "Expected nothing to be overdue yet");
+    clock.advance(Duration::days(2));
+    let (_, overdue, _) = get_page(app.clone(), "/api/todos/overdue").await;
+    assert_eq!(overdue, vec!["sooner"]);
+    clock.advance(Duration::days(31));
+    let (_, overdue, _) = get_page(app, "/api/todos/overdue?due_before=2030-01-15T00:00:00Z").await;
+    assert_eq!(overdue, vec!["sooner"], "Expected an earlier due_before to still narrow the list");
+}
+
+#[tokio::test]
+async fn test_retention_archives_old_completed_todos_and_purges_old_archived_ones() {
+    let dir = tempfile::tempdir().unwrap();
+    let attachments = AttachmentConfig { dir: dir.path().to_path_buf(), ..AttachmentConfig::default() };
+    let config = RetentionConfig { archive_after_days: Some(30), purge_after_days: Some(90), ..RetentionConfig::default() };
+    let clock = Arc::new(TestClock(Mutex::new(Utc::now())));
+    let db: Arc<dyn TodoStore> = Arc::new(InMemoryTodoListDao::new());
+    let app = build_app_with_retention(db.clone(), ValidationRules::default(), None, attachments.clone(), config.clone(),
+        clock.clone());
+    let app = sign_in_as(app, "alice@example.com").await;
+    for body in [r#"{"title":"file taxes"}"#, r#"{"title":"water plants"}"#, r#"{"title":"trip"}"#, r#"{"title":"pack","parent_id":3}"#] {
+        send(app.clone(), "POST", "/api/todos", Some(body)).await;
+    }
+    for id in [1, 3] {
+        send(app.clone(), "PATCH", &format!("/api/todos/{}", id), Some(r#"{"completed":true}"#)).await;
+    }
+    upload(app.clone(), "/api/todos/1/attachments", "return.pdf", b"%PDF").await;
+
+    let (status, report) = send(app.clone(), "GET", "/api/todos/retention", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["archive_after_days"], 30);
+    assert_eq!(report["purge_after_days"], 90);
+    assert!(report["archive"].as_array().unwrap().is_empty(), "Expected fresh completions to stay");
+
+    clock.advance(Duration::days(31));
+    let (_, report) = send(app.clone(), "GET", "/api/todos/retention", None).await;
+    let titles: Vec<&str> = report["archive"].as_array().unwrap().iter().map(|todo| todo["title"].as_str().unwrap()).collect();
+    assert_eq!(titles, vec!["file taxes"], "Expected trees with open subtasks to stay");
+    let (_, all, _) = get_page(app.clone(), "/api/todos").await;
+    assert_eq!(all.len(), 4, "Expected the report to leave the todos alone");
+
+    let done = retention::run(&db, &attachments, &config, clock.now()).await.unwrap();
+    assert_eq!(done, RetentionRun { archived: 1, purged: 0 });
+    let (_, archived) = send(app.clone(), "GET", "/api/todos/archived", None).await;
+    assert_eq!(archived[0]["title"], "file taxes");
+    assert_eq!(stored_files(dir.path()), 1);
+
+    clock.advance(Duration::days(60));
+    let (_, report) = send(app.clone(), "GET", "/api/todos/retention", None).await;
+    assert!(report["purge"].as_array().unwrap().is_empty(), "Expected archive time to count, not completion time");
+    clock.advance(Duration::days(31));
+    let (_, report) = send(app.clone(), "GET", "/api/todos/retention", None).await;
+    assert_eq!(report["purge"][0]["title"], "file taxes");
+
+    let done = retention::run(&db, &attachments, &config, clock.now()).await.unwrap();
+    assert_eq!(done, RetentionRun { archived: 0, purged: 1 });
+    let (_, archived) = send(app.clone(), "GET", "/api/todos/archived", None).await;
+    assert!(archived.as_array().unwrap().is_empty());
+    assert_eq!(stored_files(dir.path()), 0, "Expected purged attachments to lose their files");
+    let (_, all, _) = get_page(app, "/api/todos").await;
+    assert_eq!(all.len(), 3);
+}
+
+#[tokio::test]
+async fn test_router_reports_switched_off_retention_rules() {
+    let app = app_with_todos(&[("old", 1, true)]).await;
+    let (status, report) = send(app, "GET", "/api/todos/retention", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["archive_after_days"], serde_json::Value::Null);
+    assert!(report["archive"].as_array().unwrap().is_empty());
+    assert!(report["purge"].as_array().unwrap().is_empty());
+}
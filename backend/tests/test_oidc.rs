@@ -0,0 +1,263 @@
+use axum::body::Body;
+use axum::extract::{Form, State};
+use axum::http::{header, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use backend::build_app_with_oidc;
+use backend::in_memory_todo_list_dao::InMemoryTodoListDao;
+use backend::oidc::OidcConfig;
+use backend::validation::ValidationRules;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use http_body_util::BodyExt;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tower::ServiceExt;
+
+const CLIENT_ID: &str = "todo-app";
+const SIGNING_KEY: &str = include_str!("fixtures/oidc_test_key.pem");
+/// The modulus of `fixtures/oidc_test_key.pem`, as published in the JWKS.
+const SIGNING_KEY_MODULUS: &str = "qbjAHnccUAoWb1R3Q_m63MPEb4q932snUshDow8n27QkZuO08wt5y52JhLhWSetmiF3eD7LwHO22O_sOqeJBrVaFDnFVcXaiJ-_cWw8Egaz-RV7RweaquubIqCEIYKy7aUTJSonaQfC5t5h6YIFv0mV3p43c9yYZHT54zHKbxf02N2FIB1ZcyVUQtttabhaqCeVKvk8NyK0UZ1mX6HU2PpZcK9rv66FgtsKIaS_p2cORLieE8C1BeuMzFReS02NddR4evud3boGosS6mGtkot3-WowuK6r3Rl5m2QiThqGhC9RL8g29eJ9cn2cjpJI0hCCm2Tnx-trk6LJ6XXkve6Q";
+
+/// A code the mock provider handed out, with what the login asked for.
+struct Grant {
+    code_challenge: String,
+    nonce: String,
+    subject: String,
+    email: String,
+}
+
+#[derive(Default)]
+struct Provider {
+    issuer: String,
+    grants: HashMap<String, Grant>,
+    audience: Option<String>,
+}
+
+type SharedProvider = Arc<Mutex<Provider>>;
+
+/// A local identity provider that serves discovery, JWKS and the token
+/// endpoint. The authorization step is played by `authorize`.
+async fn start_provider() -> SharedProvider {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let provider = Arc::new(Mutex::new(Provider { issuer, ..Provider::default() }));
+    let routes = Router::new()
+        .route("/.well-known/openid-configuration", get(discovery))
+        .route("/jwks", get(jwks))
+        .route("/token", post(token))
+        .with_state(provider.clone());
+    tokio::spawn(async move { axum::serve(listener, routes).await.unwrap() });
+    provider
+}
+
+async fn discovery(State(provider): State<SharedProvider>) -> Json<serde_json::Value> {
+    let issuer = provider.lock().unwrap().issuer.clone();
+    Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/authorize", issuer),
+        "token_endpoint": format!("{}/token", issuer),
+        "jwks_uri": format!("{}/jwks", issuer),
+    }))
+}
+
+async fn jwks() -> Json<serde_json::Value> {
+    Json(json!({
+        "keys": [{ "kty": "RSA", "kid": "test-key", "use": "sig", "alg": "RS256", "n": SIGNING_KEY_MODULUS, "e": "AQAB" }]
+    }))
+}
+
+async fn token(State(provider): State<SharedProvider>, Form(form): Form<HashMap<String, String>>) -> Response {
+    let mut provider = provider.lock().unwrap();
+    let Some(grant) = provider.grants.remove(&form["code"]) else {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": "invalid_grant" }))).into_response();
+    };
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(form["code_verifier"].as_bytes()));
+    if challenge != grant.code_challenge || form["client_id"] != CLIENT_ID {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": "invalid_grant" }))).into_response();
+    }
+    let now = chrono::Utc::now().timestamp();
+    let claims = json!({
+        "iss": provider.issuer,
+        "sub": grant.subject,
+        "aud": provider.audience.clone().unwrap_or(CLIENT_ID.to_string()),
+        "iat": now,
+        "exp": now + 300,
+        "email": grant.email,
+        "email_verified": true,
+        "nonce": grant.nonce,
+    });
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some("test-key".to_string());
+    let key = EncodingKey::from_rsa_pem(SIGNING_KEY.as_bytes()).unwrap();
+    let id_token = jsonwebtoken::encode(&header, &claims, &key).unwrap();
+    Json(json!({ "access_token": "unused", "token_type": "Bearer", "id_token": id_token })).into_response()
+}
+
+fn app_for(provider: &SharedProvider) -> Router {
+    let config = OidcConfig {
+        issuer: provider.lock().unwrap().issuer.clone(),
+        client_id: CLIENT_ID.to_string(),
+        client_secret: None,
+        redirect_url: "http://localhost:3001/api/auth/oidc/callback".to_string(),
+    };
+    build_app_with_oidc(Arc::new(InMemoryTodoListDao::new()), ValidationRules::default(), Some(config))
+}
+
+async fn send_get(app: Router, uri: &str, cookie: Option<&str>) -> Response {
+    let mut request = Request::builder().uri(uri);
+    if let Some(cookie) = cookie {
+        request = request.header(header::COOKIE, cookie);
+    }
+    app.oneshot(request.body(Body::empty()).unwrap()).await.unwrap()
+}
+
+/// The `name=value` parts of every cookie the response sets.
+fn cookies(response: &Response) -> Vec<String> {
+    response.headers().get_all(header::SET_COOKIE).iter()
+        .map(|value| value.to_str().unwrap().split(';').next().unwrap().to_string())
+        .collect()
+}
+
+/// Starts a login and returns the login cookie and the query of the
+/// authorization URL.
+async fn start_login(app: Router) -> (String, HashMap<String, String>) {
+    let response = send_get(app, "/api/auth/oidc/login", None).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let location = url::Url::parse(response.headers()[header::LOCATION].to_str().unwrap()).unwrap();
+    assert_eq!(location.path(), "/authorize");
+    let params = location.query_pairs().into_owned().collect();
+    (cookies(&response).remove(0), params)
+}
+
+/// Plays the user approving the login at the provider and returns the code.
+fn authorize(provider: &SharedProvider, params: &HashMap<String, String>, subject: &str, email: &str) -> String {
+    assert_eq!(params["code_challenge_method"], "S256");
+    let code = format!("code-for-{}", subject);
+    provider.lock().unwrap().grants.insert(code.clone(), Grant {
+        code_challenge: params["code_challenge"].clone(),
+        nonce: params["nonce"].clone(),
+        subject: subject.to_string(),
+        email: email.to_string(),
+    });
+    code
+}
+
+async fn log_in(app: Router, provider: &SharedProvider, subject: &str, email: &str) -> Response {
+    let (cookie, params) = start_login(app.clone()).await;
+    let code = authorize(provider, &params, subject, email);
+    send_get(app, &format!("/api/auth/oidc/callback?code={}&state={}", code, params["state"]), Some(&cookie)).await
+}
+
+async fn current_user(app: Router, response: &Response) -> serde_json::Value {
+    let session = cookies(response).into_iter().find(|cookie| cookie.starts_with("session=")).unwrap();
+    let response = send_get(app, "/api/auth/me", Some(&session)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn test_oidc_login_creates_an_account_and_signs_in() {
+    let provider = start_provider().await;
+    let app = app_for(&provider);
+
+    let response = log_in(app.clone(), &provider, "subject-1", "Alice@Example.com").await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(response.headers()[header::LOCATION], "http://localhost:3000");
+    assert!(cookies(&response).contains(&"oidc_login=".to_string()), "Expected the login cookie to be cleared");
+    let user = current_user(app.clone(), &response).await;
+    assert_eq!(user["email"], "alice@example.com");
+
+    let again = log_in(app.clone(), &provider, "subject-1", "alice@example.com").await;
+    assert_eq!(current_user(app, &again).await["id"], user["id"], "Expected the identity to stay linked to its account");
+}
+
+async fn register(app: Router, email: &str) -> Response {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/auth/register")
+        .header("content-type", "application/json")
+        .body(Body::from(json!({ "email": email, "password": "correct horse" }).to_string()))
+        .unwrap();
+    app.oneshot(request).await.unwrap()
+}
+
+#[tokio::test]
+async fn test_oidc_login_links_existing_account_while_signed_in() {
+    let provider = start_provider().await;
+    let app = app_for(&provider);
+    let registered = register(app.clone(), "alice@example.com").await;
+    let session = cookies(&registered).into_iter().find(|cookie| cookie.starts_with("session=")).unwrap();
+    let registered = current_user(app.clone(), &registered).await;
+
+    let (cookie, params) = start_login(app.clone()).await;
+    let code = authorize(&provider, &params, "subject-1", "alice@example.com");
+    let uri = format!("/api/auth/oidc/callback?code={}&state={}", code, params["state"]);
+    let response = send_get(app.clone(), &uri, Some(&format!("{}; {}", session, cookie))).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(current_user(app.clone(), &response).await["id"], registered["id"]);
+
+    let again = log_in(app.clone(), &provider, "subject-1", "alice@example.com").await;
+    assert_eq!(current_user(app, &again).await["id"], registered["id"], "Expected the identity to stay linked to its account");
+}
+
+#[tokio::test]
+async fn test_oidc_login_does_not_take_over_accounts_with_a_password() {
+    let provider = start_provider().await;
+    let app = app_for(&provider);
+    register(app.clone(), "alice@example.com").await;
+
+    let response = log_in(app.clone(), &provider, "subject-1", "alice@example.com").await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert!(!cookies(&response).iter().any(|cookie| cookie.starts_with("session=")), "Expected no session for the password account");
+
+    let other = register(app.clone(), "mallory@example.com").await;
+    let session = cookies(&other).into_iter().find(|cookie| cookie.starts_with("session=")).unwrap();
+    let (cookie, params) = start_login(app.clone()).await;
+    let code = authorize(&provider, &params, "subject-1", "alice@example.com");
+    let uri = format!("/api/auth/oidc/callback?code={}&state={}", code, params["state"]);
+    let response = send_get(app, &uri, Some(&format!("{}; {}", session, cookie))).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT, "Expected a session of another account not to allow linking");
+}
+
+#[tokio::test]
+async fn test_oidc_callback_rejects_forged_logins() {
+    let provider = start_provider().await;
+    let app = app_for(&provider);
+
+    let (cookie, params) = start_login(app.clone()).await;
+    let code = authorize(&provider, &params, "subject-1", "alice@example.com");
+    let response = send_get(app.clone(), &format!("/api/auth/oidc/callback?code={}&state=forged", code), Some(&cookie)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "Expected a mismatched state to be rejected");
+    let response = send_get(app.clone(), &format!("/api/auth/oidc/callback?code={}&state={}", code, params["state"]), None).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "Expected callbacks without a pending login to be rejected");
+
+    let (other_cookie, other_params) = start_login(app.clone()).await;
+    let forged_cookie = other_cookie.replace(&other_params["state"], &params["state"]);
+    let response = send_get(app.clone(), &format!("/api/auth/oidc/callback?code={}&state={}", code, params["state"]), Some(&forged_cookie)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "Expected the provider to refuse a foreign PKCE verifier");
+
+    let response = send_get(app, "/api/auth/oidc/callback?error=access_denied", Some(&cookie)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_oidc_login_rejects_tokens_for_another_client() {
+    let provider = start_provider().await;
+    provider.lock().unwrap().audience = Some("someone-else".to_string());
+    let app = app_for(&provider);
+    let response = log_in(app, &provider, "subject-1", "alice@example.com").await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(cookies(&response).is_empty(), "Expected no session for a token issued to another client");
+}
+
+#[tokio::test]
+async fn test_oidc_routes_are_off_without_configuration() {
+    let app = backend::build_app(Arc::new(InMemoryTodoListDao::new()));
+    let response = send_get(app, "/api/auth/oidc/login", None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,64 @@
+use backend::Todo;
+use backend::recurrence::{next_occurrence, Frequency, Recurrence};
+use chrono::{DateTime, TimeZone, Utc};
+
+fn at(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(year, month, day, 9, 30, 0).unwrap()
+}
+
+fn rule(value: &str) -> Recurrence {
+    value.parse().unwrap()
+}
+
+#[test]
+fn test_rules_are_parsed_and_normalized() {
+    assert_eq!(rule("weekly"), Recurrence { frequency: Frequency::Weekly, interval: 1, by_day: Vec::new(), by_month_day: None });
+    assert_eq!(rule("RRULE:freq=weekly;byday=TH,MO,TH").to_string(), "FREQ=WEEKLY;BYDAY=MO,TH");
+    assert_eq!(rule("FREQ=MONTHLY;INTERVAL=3").to_string(), "FREQ=MONTHLY;INTERVAL=3");
+    assert_eq!(rule("FREQ=DAILY;INTERVAL=1").to_string(), "FREQ=DAILY");
+    assert_eq!(rule("freq=monthly;bymonthday=31").to_string(), "FREQ=MONTHLY;BYMONTHDAY=31");
+
+    for invalid in ["", "yearly", "FREQ=YEARLY", "FREQ=DAILY;INTERVAL=0", "FREQ=DAILY;BYDAY=MO", "FREQ=WEEKLY;BYDAY=XX", "FREQ=DAILY;COUNT=3", "FREQ=MONTHLY;BYMONTHDAY=32", "FREQ=WEEKLY;BYMONTHDAY=1"] {
+        assert!(invalid.parse::<Recurrence>().is_err(), "Expected '{}' to be rejected", invalid);
+    }
+}
+
+#[test]
+fn test_occurrences_follow_the_rule() {
+    // 2030-01-07 is a Monday.
+    assert_eq!(rule("FREQ=DAILY;INTERVAL=2").after(at(2030, 1, 7)), at(2030, 1, 9));
+    assert_eq!(rule("weekly").after(at(2030, 1, 7)), at(2030, 1, 14));
+    let mondays_and_thursdays = rule("FREQ=WEEKLY;BYDAY=MO,TH");
+    assert_eq!(mondays_and_thursdays.after(at(2030, 1, 7)), at(2030, 1, 10));
+    assert_eq!(mondays_and_thursdays.after(at(2030, 1, 10)), at(2030, 1, 14));
+    assert_eq!(rule("FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH").after(at(2030, 1, 10)), at(2030, 1, 21));
+    assert_eq!(rule("FREQ=WEEKLY;BYDAY=MO").after(at(2030, 1, 12)), at(2030, 1, 14), "Expected off-rule due dates to snap to the rule");
+    assert_eq!(rule("monthly").after(at(2030, 1, 31)), at(2030, 2, 28), "Expected short months to clamp to their last day");
+}
+
+#[test]
+fn test_next_due_skips_occurrences_that_have_passed() {
+    let weekly = rule("weekly");
+    assert_eq!(weekly.next_due(Some(at(2030, 1, 7)), at(2030, 1, 8)), at(2030, 1, 14));
+    assert_eq!(weekly.next_due(Some(at(2030, 1, 7)), at(2030, 1, 20)), at(2030, 1, 21), "Expected late completions not to come back overdue");
+    assert_eq!(weekly.next_due(None, at(2030, 1, 8)), at(2030, 1, 15), "Expected undated todos to recur from their completion");
+}
+
+#[test]
+fn test_monthly_todos_return_to_their_day_after_short_months() {
+    let first = Todo {
+        id: 1, list_id: 1, title: "Pay rent".to_string(), priority: 3, completed: true, completed_at: None,
+        due_at: Some(at(2030, 1, 31)), recurrence: Some("monthly".to_string()), parent_id: None, notes: None,
+        tags: Vec::new(), blocked: false,
+    };
+    let mut due = Vec::new();
+    let mut todo = first;
+    for _ in 0..4 {
+        todo = next_occurrence(&todo, todo.due_at.unwrap()).unwrap();
+        due.push(todo.due_at.unwrap());
+    }
+    assert_eq!(due, [at(2030, 2, 28), at(2030, 3, 31), at(2030, 4, 30), at(2030, 5, 31)]);
+    assert_eq!(todo.recurrence.as_deref(), Some("FREQ=MONTHLY;BYMONTHDAY=31"));
+    assert_eq!(rule("monthly").next_due(Some(at(2030, 1, 31)), at(2030, 3, 1)), at(2030, 3, 31), "Expected skipped occurrences not to drift");
+    assert_eq!(rule("FREQ=MONTHLY;BYMONTHDAY=30").after(at(2030, 1, 10)), at(2030, 2, 28));
+}
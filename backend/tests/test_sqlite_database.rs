@@ -0,0 +1,976 @@
+use backend::{UpdateTodo, DEFAULT_LIST_ID};
+use backend::api_token::Scope;
+use backend::attachments::Attachment;
+use backend::dependencies::Dependency;
+use backend::error::ApiError;
+use backend::membership::Role;
+use chrono::{Duration, TimeZone, Utc};
+use backend::migrations;
+use backend::search::{SearchQuery, SearchScope, SearchSource};
+use backend::todo_query::{SortOrder, TodoQuery};
+use backend::sqlite_todo_list_dao::SqliteTodoListDao;
+use backend::todo_store::{self, TodoStore};
+use sqlx::Executor;
+use sqlx::sqlite::SqliteConnectOptions;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+async fn new_dao() -> SqliteTodoListDao {
+    let dao = SqliteTodoListDao::new("sqlite::memory:").await.unwrap();
+    dao.initialize().await;
+    dao
+}
+
+fn new_todo(title: &str, priority: u8, completed: bool) -> backend::Todo {
+    backend::Todo {
+        id: 0,
+        list_id: DEFAULT_LIST_ID,
+        title: title.to_string(),
+        priority,
+        completed,
+        completed_at: None,
+        due_at: None,
+        recurrence: None,
+        parent_id: None,
+        notes: None,
+        tags: Vec::new(),
+        blocked: false,
+    }
+}
+
+#[tokio::test]
+async fn test_create_dao() {
+    let dao = SqliteTodoListDao::new("sqlite::memory:").await.unwrap();
+    assert!(dao.is_open(), "Expected database connection to be open");
+}
+
+#[tokio::test]
+async fn test_migrate_applies_pending_migrations_once() {
+    let dao = SqliteTodoListDao::new("sqlite::memory:").await.unwrap();
+    let all_versions: Vec<i64> = migrations::SQLITE_MIGRATIONS.iter().map(|m| m.version).collect();
+    assert_eq!(dao.migrate().await.unwrap(), all_versions, "Expected every migration to be applied to an empty database");
+    assert!(dao.migrate().await.unwrap().is_empty(), "Expected no migrations to be pending on the second run");
+}
+
+#[tokio::test]
+async fn test_initialize_wipes_existing_todos() {
+    let dao = new_dao().await;
+    dao.save_todo(&new_todo("Wiped", 1, false)).await.unwrap();
+    dao.initialize().await;
+    assert!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap().is_empty(), "Expected initialize to start from an empty schema");
+}
+
+#[tokio::test]
+async fn test_connect_selects_sqlite_by_scheme() {
+    let store = todo_store::connect("sqlite::memory:").await.unwrap();
+    store.initialize().await;
+    store.save_todo(&new_todo("From scheme", 1, false)).await.unwrap();
+    assert_eq!(store.query_todos(DEFAULT_LIST_ID).await.unwrap().len(), 1, "Expected the todo to be saved through the selected store");
+}
+
+#[tokio::test]
+async fn test_data_survives_reopening_the_file() {
+    let path = std::env::temp_dir().join(format!("todos-{}.db", std::process::id()));
+    let url = format!("sqlite://{}", path.display());
+    {
+        let dao = SqliteTodoListDao::new(&url).await.unwrap();
+        dao.initialize().await;
+        dao.save_todo(&new_todo("Persisted", 1, false)).await.unwrap();
+    }
+    let dao = SqliteTodoListDao::new(&url).await.unwrap();
+    dao.migrate().await.unwrap();
+    let todos = dao.query_todos(DEFAULT_LIST_ID).await.unwrap();
+    std::fs::remove_file(&path).ok();
+    assert_eq!(todos.len(), 1, "Expected the todo to be read back from the file");
+    assert_eq!(todos[0].title, "Persisted");
+}
+
+/// Brings an empty database to the schema an older release left behind:
+/// every migration before `version`, recorded as applied.
+async fn migrate_before(pool: &sqlx::SqlitePool, version: i64) {
+    sqlx::query("CREATE TABLE schema_migrations (version BIGINT PRIMARY KEY, name TEXT NOT NULL, applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP)")
+        .execute(pool)
+        .await
+        .unwrap();
+    for migration in migrations::SQLITE_MIGRATIONS.iter().filter(|m| m.version < version) {
+        pool.execute(migration.sql).await.unwrap();
+        sqlx::query("INSERT INTO schema_migrations (version, name) VALUES ($1, $2)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_migrate_moves_archived_ids_out_of_the_way_of_todos() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("sqlite://{}", dir.path().join("todos.db").display());
+    let options = SqliteConnectOptions::from_str(&url).unwrap().create_if_missing(true);
+    let pool = sqlx::SqlitePool::connect_with(options).await.unwrap();
+    migrate_before(&pool, 17).await;
+    pool.execute("
+        INSERT INTO todos (id, list_id, title, priority, completed) VALUES (1, 1, 'Done', 1, TRUE), (2, 1, 'Open', 1, FALSE);
+        INSERT INTO archived (id, list_id, title, priority, completed, parent_id)
+            VALUES (1, 1, 'Old parent', 1, TRUE, NULL), (2, 1, 'Old child', 1, TRUE, 1), (3, 1, 'Old single', 1, TRUE, NULL);
+        INSERT INTO tags (id, list_id, name, color) VALUES (1, 1, 'home', '#808080');
+        INSERT INTO archived_tags (archived_id, tag_id) VALUES (1, 1);
+        INSERT INTO attachments (archived_id, file_name, content_type, size, sha256, storage_key, created_at)
+            VALUES (1, 'plan.txt', 'text/plain', 1, 'ab', 'key-plan', CURRENT_TIMESTAMP);
+    ").await.unwrap();
+    pool.close().await;
+
+    let dao = SqliteTodoListDao::new(&url).await.unwrap();
+    assert_eq!(dao.migrate().await.unwrap(), vec![17]);
+    assert_eq!(dao.archive_completed_todos(DEFAULT_LIST_ID).await.unwrap(), 1, "Expected archiving to find its id free");
+    let archived = dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap();
+    let by_title = |title: &str| archived.iter().find(|todo| todo.title == title).unwrap().clone();
+    assert_eq!(by_title("Done").id, 1);
+    let parent = by_title("Old parent");
+    assert!(parent.id > 2, "Expected colliding archived ids to move above the todos");
+    assert_eq!(parent.tags[0].name, "home");
+    assert_eq!(by_title("Old child").parent_id, Some(parent.id));
+    assert_eq!(by_title("Old single").id, 3, "Expected archived ids without a collision to stay");
+    let attachments = dao.query_list_attachments(DEFAULT_LIST_ID).await.unwrap();
+    assert_eq!(attachments[0].todo_id, parent.id);
+    let saved = dao.save_todo(&new_todo("New", 1, false)).await.unwrap();
+    assert!(archived.iter().all(|todo| todo.id < saved.id), "Expected new todos to take ids above the archive");
+}
+
+#[tokio::test]
+async fn test_constraint_errors_leave_the_database_message_out() {
+    let pool = sqlx::sqlite::SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+    migrate_before(&pool, i64::MAX).await;
+    let add_user = "INSERT INTO users (email, password_hash) VALUES ('ann@example.com', '')";
+    pool.execute(add_user).await.unwrap();
+
+    let error = ApiError::from(pool.execute(add_user).await.unwrap_err());
+    assert_eq!(error.to_string(), "An account with this email already exists");
+    let error = ApiError::from(pool.execute("INSERT INTO list_members (list_id, user_id, role) VALUES (1, 1, 'admin')").await.unwrap_err());
+    assert!(matches!(&error, ApiError::Validation(errors) if errors[0].field == "role"), "Expected the role to be named, got {:?}", error);
+    assert!(!error.to_string().contains("list_members"), "Expected the constraint name to stay in the log");
+}
+
+#[tokio::test]
+async fn test_query_todos_orders_by_priority_then_creation() {
+    let dao = new_dao().await;
+    dao.save_todo(&new_todo("First low", 1, false)).await.unwrap();
+    dao.save_todo(&new_todo("High", 3, false)).await.unwrap();
+    dao.save_todo(&new_todo("Second low", 1, false)).await.unwrap();
+    let titles: Vec<String> = dao.query_todos(DEFAULT_LIST_ID).await.unwrap().into_iter().map(|t| t.title).collect();
+    assert_eq!(titles, vec!["High", "First low", "Second low"]);
+}
+
+#[tokio::test]
+async fn test_save_todo() {
+    let dao = new_dao().await;
+    let result = dao.save_todo(&new_todo("Test Save", 1, false)).await.unwrap();
+    assert_eq!(result.id, 1, "Expected the first todo to get id 1");
+    assert_eq!(result.title, "Test Save", "Expected the saved row to be returned");
+}
+
+#[tokio::test]
+async fn test_archive_completed_todos() {
+    let dao = new_dao().await;
+    dao.save_todo(&new_todo("Done", 1, true)).await.unwrap();
+    dao.save_todo(&new_todo("Not done", 1, false)).await.unwrap();
+
+    let archived_count = dao.archive_completed_todos(DEFAULT_LIST_ID).await.unwrap();
+    assert_eq!(archived_count, 1, "Expected one todo to be archived");
+
+    let archived_todos = dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap();
+    let queried_todos = dao.query_todos(DEFAULT_LIST_ID).await.unwrap();
+    assert_eq!(archived_todos.len(), 1, "Expected one todo in the archive");
+    assert_eq!(archived_todos[0].title, "Done");
+    assert_eq!(queried_todos.len(), 1, "Expected the incomplete todo to stay active");
+    assert_eq!(queried_todos[0].title, "Not done");
+}
+
+#[tokio::test]
+async fn test_rename_todo() {
+    let dao = new_dao().await;
+    let id = dao.save_todo(&new_todo("Old Title", 1, false)).await.unwrap().id;
+    dao.rename_todo(DEFAULT_LIST_ID, id as u64, "New Title".to_string()).await.unwrap();
+    let todos = dao.query_todos(DEFAULT_LIST_ID).await.unwrap();
+    assert_eq!(todos[0].title, "New Title", "Expected the todo title to be updated");
+}
+
+#[tokio::test]
+async fn test_toggle_todo_completion() {
+    let dao = new_dao().await;
+    let id = dao.save_todo(&new_todo("Test Complete", 1, false)).await.unwrap().id;
+    let toggled = dao.toggle_todo_completion(DEFAULT_LIST_ID, id as u64).await.unwrap();
+    assert_eq!((toggled.title.as_str(), toggled.completed), ("Test Complete", true), "Expected the toggled row to be returned");
+    assert!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap()[0].completed, "Expected the todo to be marked as completed");
+    dao.toggle_todo_completion(DEFAULT_LIST_ID, id as u64).await.unwrap();
+    assert!(!dao.query_todos(DEFAULT_LIST_ID).await.unwrap()[0].completed, "Expected the todo to be marked as not completed");
+}
+
+#[tokio::test]
+async fn test_change_todo_priority() {
+    let dao = new_dao().await;
+    let id = dao.save_todo(&new_todo("Priority", 3, false)).await.unwrap().id;
+    dao.increase_todo_priority(DEFAULT_LIST_ID, id as u64).await.unwrap();
+    assert_eq!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap()[0].priority, 4, "Expected the todo priority to be increased by 1");
+    dao.decrease_todo_priority(DEFAULT_LIST_ID, id as u64).await.unwrap();
+    dao.decrease_todo_priority(DEFAULT_LIST_ID, id as u64).await.unwrap();
+    assert_eq!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap()[0].priority, 2, "Expected the todo priority to be decreased by 2");
+}
+
+#[tokio::test]
+async fn test_delete_and_truncate() {
+    let dao = new_dao().await;
+    let id = dao.save_todo(&new_todo("Delete me", 1, false)).await.unwrap().id;
+    dao.save_todo(&new_todo("Truncate me", 1, false)).await.unwrap();
+    assert_eq!(dao.delete_todo(DEFAULT_LIST_ID, id as u64).await.unwrap().title, "Delete me", "Expected the deleted row to be returned");
+    assert_eq!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap().len(), 1);
+    dao.truncate_todos_table(DEFAULT_LIST_ID).await.unwrap();
+    assert_eq!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap().len(), 0, "Expected no todos after truncating");
+}
+
+#[tokio::test]
+async fn test_mutating_missing_todo_reports_not_found() {
+    let dao = new_dao().await;
+    assert!(matches!(dao.rename_todo(DEFAULT_LIST_ID, 42, "Missing".to_string()).await, Err(ApiError::NotFound(_))));
+    assert!(matches!(dao.toggle_todo_completion(DEFAULT_LIST_ID, 42).await, Err(ApiError::NotFound(_))));
+    assert!(matches!(dao.delete_todo(DEFAULT_LIST_ID, 42).await, Err(ApiError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_query_and_update_single_todo() {
+    let todo = backend::Todo {
+        id: 0,
+        list_id: DEFAULT_LIST_ID,
+        title: "Single".to_string(),
+        priority: 2,
+        completed: false,
+        completed_at: None,
+        due_at: None,
+        recurrence: None,
+        parent_id: None,
+        notes: None,
+        tags: Vec::new(),
+        blocked: false,
+    };
+    let dao = new_dao().await;
+    let id = dao.save_todo(&todo).await.unwrap().id as u64;
+    assert_eq!(dao.query_todo(DEFAULT_LIST_ID, id).await.unwrap().title, "Single");
+
+    let update = UpdateTodo { completed: Some(true), ..Default::default() };
+    let updated = dao.update_todo(DEFAULT_LIST_ID, id, &update).await.unwrap();
+    assert_eq!(updated.title, "Single", "Expected the title to be left alone");
+    assert_eq!(updated.priority, 2, "Expected the priority to be left alone");
+    assert!(updated.completed, "Expected the todo to be marked as completed");
+
+    assert!(matches!(dao.query_todo(DEFAULT_LIST_ID, id + 1).await, Err(ApiError::NotFound(_))));
+    assert!(matches!(dao.update_todo(DEFAULT_LIST_ID, id + 1, &update).await, Err(ApiError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn test_priority_check_constraint() {
+    let todo = backend::Todo {
+        id: 0,
+        list_id: DEFAULT_LIST_ID,
+        title: "Bounded".to_string(),
+        priority: 1,
+        completed: false,
+        completed_at: None,
+        due_at: None,
+        recurrence: None,
+        parent_id: None,
+        notes: None,
+        tags: Vec::new(),
+        blocked: false,
+    };
+    let dao = new_dao().await;
+    let id = dao.save_todo(&todo).await.unwrap().id as u64;
+    let result = dao.decrease_todo_priority(DEFAULT_LIST_ID, id).await;
+    match result {
+        Err(ApiError::Validation(errors)) => assert_eq!(errors[0].field, "priority"),
+        other => panic!("Expected a priority validation error, got {:?}", other),
+    }
+    assert_eq!(dao.query_todo(DEFAULT_LIST_ID, id).await.unwrap().priority, 1, "Expected the priority to stay unchanged");
+}
+
+#[tokio::test]
+async fn test_query_todo_page() {
+    let dao = new_dao().await;
+    for (title, priority, completed) in [("a", 1, false), ("b", 3, true), ("c", 2, false), ("d", 3, false), ("e", 1, true)] {
+        let todo = backend::Todo { id: 0, list_id: DEFAULT_LIST_ID, title: title.to_string(), priority, completed, completed_at: None, due_at: None, recurrence: None, parent_id: None, notes: None, tags: Vec::new(), blocked: false };
+        dao.save_todo(&todo).await.unwrap();
+    }
+
+    for sort in [SortOrder::Priority, SortOrder::CreatedAsc, SortOrder::CreatedDesc, SortOrder::Title] {
+        let everything = TodoQuery { sort, ..Default::default() };
+        let expected: Vec<u32> = dao.query_todo_page(DEFAULT_LIST_ID, &everything).await.unwrap().todos.iter().map(|t| t.id).collect();
+
+        let mut paged = Vec::new();
+        let mut query = TodoQuery { sort, limit: Some(2), ..Default::default() };
+        loop {
+            let page = dao.query_todo_page(DEFAULT_LIST_ID, &query).await.unwrap();
+            paged.extend(page.todos.iter().map(|t| t.id));
+            match page.next_cursor {
+                Some(cursor) => query.after = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(paged, expected, "Expected paging by {:?} to visit every todo once, in order", sort);
+    }
+
+    let filtered = TodoQuery { completed: Some(false), min_priority: Some(2), ..Default::default() };
+    let titles: Vec<String> = dao.query_todo_page(DEFAULT_LIST_ID, &filtered).await.unwrap().todos.into_iter().map(|t| t.title).collect();
+    assert_eq!(titles, vec!["d", "c"], "Expected only open todos with priority 2 or more");
+}
+
+#[tokio::test]
+async fn test_search_todos() {
+    let dao = new_dao().await;
+    for (title, priority, completed) in [("Walking the dog", 1, true), ("Buy dog food", 2, false), ("Dog dog dog", 1, false), ("Read a book", 1, false)] {
+        let todo = backend::Todo { id: 0, list_id: DEFAULT_LIST_ID, title: title.to_string(), priority, completed, completed_at: None, due_at: None, recurrence: None, parent_id: None, notes: None, tags: Vec::new(), blocked: false };
+        dao.save_todo(&todo).await.unwrap();
+    }
+    dao.archive_completed_todos(DEFAULT_LIST_ID).await.unwrap();
+
+    let search = |terms: &[&str], scope| SearchQuery {
+        terms: terms.iter().map(|t| t.to_string()).collect(),
+        scope,
+        limit: 10,
+    };
+    let hits = dao.search_todos(DEFAULT_LIST_ID, &search(&["dog"], SearchScope::Both)).await.unwrap();
+    let titles: Vec<&str> = hits.iter().map(|h| h.title.as_str()).collect();
+    assert_eq!(titles.len(), 3, "Expected every title mentioning dogs, got {:?}", titles);
+    assert_eq!(titles[0], "Dog dog dog", "Expected the best match first");
+    assert!(hits.iter().any(|h| h.source == SearchSource::Archived));
+
+    let hits = dao.search_todos(DEFAULT_LIST_ID, &search(&["walk", "do"], SearchScope::Archived)).await.unwrap();
+    assert_eq!(hits.len(), 1, "Expected terms to match as prefixes");
+    assert_eq!(hits[0].snippet, "<mark>Walking</mark> the <mark>dog</mark>");
+
+    let hits = dao.search_todos(DEFAULT_LIST_ID, &search(&["walk"], SearchScope::Active)).await.unwrap();
+    assert!(hits.is_empty(), "Expected archived todos to be left out of active searches");
+}
+
+#[tokio::test]
+async fn test_lists_scope_todos() {
+    let dao = new_dao().await;
+    assert!(dao.query_list(DEFAULT_LIST_ID).await.unwrap().is_default, "Expected the default list after migrating");
+    let owner = dao.create_user("owner@example.com", "hash").await.unwrap();
+    let work = dao.create_list(owner.id, "Work".to_string()).await.unwrap();
+    assert_eq!(dao.rename_list(work.id, "Job".to_string()).await.unwrap().name, "Job");
+
+    let mut todo = backend::Todo { id: 0, list_id: DEFAULT_LIST_ID, title: "Home".to_string(), priority: 1, completed: true, completed_at: None, due_at: None, recurrence: None, parent_id: None, notes: None, tags: Vec::new(), blocked: false };
+    dao.save_todo(&todo).await.unwrap();
+    todo.list_id = work.id;
+    todo.title = "Office".to_string();
+    let office = dao.save_todo(&todo).await.unwrap();
+    assert_eq!(office.list_id, work.id);
+
+    let titles: Vec<String> = dao.query_todos(work.id).await.unwrap().into_iter().map(|t| t.title).collect();
+    assert_eq!(titles, vec!["Office"]);
+    assert!(matches!(dao.query_todo(DEFAULT_LIST_ID, office.id as u64).await, Err(ApiError::NotFound(_))));
+    assert!(matches!(dao.delete_todo(DEFAULT_LIST_ID, office.id as u64).await, Err(ApiError::NotFound(_))));
+
+    assert_eq!(dao.archive_completed_todos(work.id).await.unwrap(), 1);
+    assert_eq!(dao.query_archived_todos(work.id).await.unwrap()[0].title, "Office");
+    assert!(dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap().is_empty());
+
+    dao.delete_list(work.id).await.unwrap();
+    assert!(matches!(dao.query_list(work.id).await, Err(ApiError::NotFound(_))));
+    assert!(dao.query_archived_todos(work.id).await.unwrap().is_empty(), "Expected archived todos to go with their list");
+    assert_eq!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap().len(), 1, "Expected other lists to be untouched");
+}
+
+#[tokio::test]
+async fn test_users_and_sessions() {
+    let dao = new_dao().await;
+    let first = dao.create_user("first@example.com", "first-hash").await.unwrap();
+    assert_eq!(dao.query_default_list(first.id).await.unwrap().id, DEFAULT_LIST_ID, "Expected the first user to take over the old default list");
+    let second = dao.create_user("second@example.com", "second-hash").await.unwrap();
+    let second_default = dao.query_default_list(second.id).await.unwrap();
+    assert_ne!(second_default.id, DEFAULT_LIST_ID);
+    assert_eq!(dao.query_lists(second.id).await.unwrap().len(), 1, "Expected later users to start with a fresh default list");
+    assert!(matches!(dao.create_user("first@example.com", "other").await, Err(ApiError::Conflict(_))));
+
+    let (user, hash) = dao.query_credentials("second@example.com").await.unwrap().unwrap();
+    assert_eq!((user.id, hash.as_str()), (second.id, "second-hash"));
+    assert!(dao.query_credentials("nobody@example.com").await.unwrap().is_none());
+
+    let now = Utc::now();
+    dao.create_session("token-hash", first.id, now + Duration::hours(1)).await.unwrap();
+    assert_eq!(dao.query_session_user("token-hash", now).await.unwrap(), Some(first.clone()));
+    assert!(dao.query_session_user("token-hash", now + Duration::hours(2)).await.unwrap().is_none(), "Expected expired sessions to be ignored");
+    dao.delete_session("token-hash").await.unwrap();
+    assert!(dao.query_session_user("token-hash", now).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_api_tokens() {
+    let dao = new_dao().await;
+    let alice = dao.create_user("alice@example.com", "hash").await.unwrap();
+    let bob = dao.create_user("bob@example.com", "hash").await.unwrap();
+    let now = Utc::now();
+    let scopes = [Scope::TodosRead, Scope::ArchiveWrite];
+    let created = dao.create_api_token(alice.id, "ci", "token-hash", &scopes, now + Duration::days(1)).await.unwrap();
+    assert_eq!(created.scopes, scopes);
+    assert!(created.last_used_at.is_none());
+
+    let (user, used) = dao.use_api_token("token-hash", now).await.unwrap().unwrap();
+    assert_eq!(user, alice);
+    assert_eq!(used.id, created.id);
+    assert!(used.last_used_at.is_some(), "Expected the use to be recorded");
+    assert!(dao.use_api_token("token-hash", now + Duration::days(2)).await.unwrap().is_none(), "Expected expired tokens to be ignored");
+    assert!(dao.use_api_token("other-hash", now).await.unwrap().is_none());
+
+    let listed = dao.query_api_tokens(alice.id).await.unwrap();
+    assert_eq!(listed.len(), 1);
+    assert!(listed[0].last_used_at.is_some());
+    assert!(matches!(dao.delete_api_token(bob.id, created.id).await, Err(ApiError::NotFound(_))));
+    dao.delete_api_token(alice.id, created.id).await.unwrap();
+    assert!(dao.query_api_tokens(alice.id).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_list_members_and_invites() {
+    let dao = new_dao().await;
+    let alice = dao.create_user("alice@example.com", "hash").await.unwrap();
+    let bob = dao.create_user("bob@example.com", "hash").await.unwrap();
+    let list = dao.create_list(alice.id, "Trip".to_string()).await.unwrap();
+    assert_eq!(dao.query_member_role(list.id, alice.id).await.unwrap(), Some(Role::Owner));
+    assert_eq!(dao.query_member_role(list.id, bob.id).await.unwrap(), None);
+
+    let now = Utc::now();
+    dao.create_invite(list.id, "bob@example.com", Role::Editor, "invite-hash", now + Duration::days(1)).await.unwrap();
+    assert!(matches!(dao.accept_invite("invite-hash", &alice, now).await, Err(ApiError::NotFound(_))), "Expected invites to be bound to their email");
+    assert!(matches!(dao.accept_invite("invite-hash", &bob, now + Duration::days(2)).await, Err(ApiError::NotFound(_))), "Expected expired invites to be rejected");
+    let member = dao.accept_invite("invite-hash", &bob, now).await.unwrap();
+    assert_eq!((member.list_id, member.role), (list.id, Role::Editor));
+    assert!(matches!(dao.accept_invite("invite-hash", &bob, now).await, Err(ApiError::NotFound(_))));
+
+    dao.create_invite(list.id, "bob@example.com", Role::Viewer, "second-hash", now + Duration::days(1)).await.unwrap();
+    assert_eq!(dao.accept_invite("second-hash", &bob, now).await.unwrap().role, Role::Editor, "Expected a lower invite to keep the higher role");
+
+    let members: Vec<(String, Role)> = dao.query_members(list.id).await.unwrap().into_iter().map(|m| (m.email, m.role)).collect();
+    assert_eq!(members, vec![("alice@example.com".to_string(), Role::Owner), ("bob@example.com".to_string(), Role::Editor)]);
+    let bob_lists: Vec<String> = dao.query_lists(bob.id).await.unwrap().into_iter().map(|l| l.name).collect();
+    assert_eq!(bob_lists, vec!["Default".to_string(), "Trip".to_string()]);
+}
+
+#[tokio::test]
+async fn test_tags_follow_todos_into_the_archive() {
+    let dao = new_dao().await;
+    let done = dao.save_todo(&new_todo("Buy stamps", 1, true)).await.unwrap();
+    let open = dao.save_todo(&new_todo("Post letter", 1, false)).await.unwrap();
+    dao.add_todo_tag(DEFAULT_LIST_ID, done.id as u64, "errand", None).await.unwrap();
+    let tagged = dao.add_todo_tag(DEFAULT_LIST_ID, done.id as u64, "urgent", Some("#ff0000")).await.unwrap();
+    dao.add_todo_tag(DEFAULT_LIST_ID, open.id as u64, "errand", Some("#00ff00")).await.unwrap();
+    let names: Vec<&str> = tagged.tags.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, vec!["errand", "urgent"]);
+
+    let errands = dao.query_todo_page(DEFAULT_LIST_ID, &TodoQuery { tag: Some("errand".to_string()), ..TodoQuery::default() }).await.unwrap();
+    assert_eq!(errands.todos.len(), 2);
+    assert!(errands.todos.iter().all(|t| t.tags.iter().any(|tag| tag.color == "#00ff00")), "Expected a new color to recolor the tag everywhere");
+    assert!(matches!(dao.remove_todo_tag(DEFAULT_LIST_ID, open.id as u64, "urgent").await, Err(ApiError::NotFound(_))));
+
+    assert_eq!(dao.archive_completed_todos(DEFAULT_LIST_ID).await.unwrap(), 1);
+    let archived = dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap();
+    let names: Vec<&str> = archived[0].tags.iter().map(|t| t.name.as_str()).collect();
+    assert_eq!(names, vec!["errand", "urgent"], "Expected the tags to move with the todo");
+    let active = dao.query_todos(DEFAULT_LIST_ID).await.unwrap();
+    assert_eq!(active[0].tags.len(), 1);
+
+    let removed = dao.delete_todo(DEFAULT_LIST_ID, open.id as u64).await.unwrap();
+    assert_eq!(removed.tags.len(), 1, "Expected the deleted todo to be returned with its tags");
+    assert_eq!(dao.query_tags(DEFAULT_LIST_ID).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_due_dates_order_and_filter_todos() {
+    let dao = new_dao().await;
+    let due = |day: u32| Some(Utc.with_ymd_and_hms(2030, 1, day, 12, 0, 0).unwrap());
+    for (title, priority, due_at) in [("undated", 5, None), ("later", 5, due(20)), ("sooner", 5, due(10)), ("low", 1, due(1))] {
+        dao.save_todo(&backend::Todo { due_at, ..new_todo(title, priority, false) }).await.unwrap();
+    }
+    let titles = |todos: Vec<backend::Todo>| todos.into_iter().map(|t| t.title).collect::<Vec<_>>();
+    assert_eq!(titles(dao.query_todos(DEFAULT_LIST_ID).await.unwrap()), vec!["sooner", "later", "undated", "low"]);
+
+    let first = dao.query_todo_page(DEFAULT_LIST_ID, &TodoQuery { limit: Some(2), ..TodoQuery::default() }).await.unwrap();
+    let rest = dao.query_todo_page(DEFAULT_LIST_ID, &TodoQuery { after: first.next_cursor, ..TodoQuery::default() }).await.unwrap();
+    assert_eq!(titles(rest.todos), vec!["undated", "low"], "Expected the cursor to continue behind the dated todos");
+    let after_undated = dao.query_todo_page(DEFAULT_LIST_ID, &TodoQuery { limit: Some(3), ..TodoQuery::default() }).await.unwrap();
+    let rest = dao.query_todo_page(DEFAULT_LIST_ID, &TodoQuery { after: after_undated.next_cursor, ..TodoQuery::default() }).await.unwrap();
+    assert_eq!(titles(rest.todos), vec!["low"]);
+
+    let query = TodoQuery { due_after: due(5), due_before: due(20), ..TodoQuery::default() };
+    assert_eq!(titles(dao.query_todo_page(DEFAULT_LIST_ID, &query).await.unwrap().todos), vec!["sooner"]);
+
+    let update = UpdateTodo { due_at: Some(None), ..Default::default() };
+    let cleared = dao.update_todo(DEFAULT_LIST_ID, 3, &update).await.unwrap();
+    assert_eq!((cleared.title.as_str(), cleared.due_at), ("sooner", None));
+    let kept = dao.update_todo(DEFAULT_LIST_ID, 2, &UpdateTodo { priority: Some(6), ..Default::default() }).await.unwrap();
+    assert_eq!(kept.due_at, due(20));
+}
+
+#[tokio::test]
+async fn test_completing_recurring_todo_creates_next_occurrence() {
+    let dao = new_dao().await;
+    let due_at = Some(Utc.with_ymd_and_hms(2030, 1, 31, 9, 0, 0).unwrap());
+    let todo = dao.save_todo(&backend::Todo { due_at, recurrence: Some("FREQ=MONTHLY".to_string()), ..new_todo("Pay rent", 3, false) }).await.unwrap();
+    dao.add_todo_tag(DEFAULT_LIST_ID, todo.id as u64, "bills", None).await.unwrap();
+
+    let completed = dao.toggle_todo_completion(DEFAULT_LIST_ID, todo.id as u64).await.unwrap();
+    assert!(completed.completed);
+    let open = dao.query_todo_page(DEFAULT_LIST_ID, &TodoQuery { completed: Some(false), ..TodoQuery::default() }).await.unwrap().todos;
+    assert_eq!(open.len(), 1);
+    assert_eq!(open[0].due_at, Some(Utc.with_ymd_and_hms(2030, 2, 28, 9, 0, 0).unwrap()));
+    assert_eq!((open[0].title.as_str(), open[0].priority), ("Pay rent", 3));
+    assert_eq!(open[0].recurrence.as_deref(), Some("FREQ=MONTHLY;BYMONTHDAY=31"), "Expected the rule to keep the day it was first due on");
+    assert_eq!(open[0].tags[0].name, "bills");
+
+    dao.toggle_todo_completion(DEFAULT_LIST_ID, todo.id as u64).await.unwrap();
+    dao.update_todo(DEFAULT_LIST_ID, todo.id as u64, &UpdateTodo { completed: Some(true), ..Default::default() }).await.unwrap();
+    assert_eq!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap().len(), 2, "Expected a todo to recur only once");
+
+    assert_eq!(dao.archive_completed_todos(DEFAULT_LIST_ID).await.unwrap(), 1);
+    let archived = dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap();
+    assert_eq!(archived[0].recurrence.as_deref(), Some("FREQ=MONTHLY"));
+}
+
+#[tokio::test]
+async fn test_subtasks_move_to_the_archive_as_a_tree() {
+    let dao = new_dao().await;
+    let parent = dao.save_todo(&new_todo("Move", 3, true)).await.unwrap();
+    let child = dao.save_todo(&backend::Todo { parent_id: Some(parent.id), ..new_todo("Pack", 1, false) }).await.unwrap();
+    dao.save_todo(&backend::Todo { parent_id: Some(child.id), ..new_todo("Books", 1, true) }).await.unwrap();
+    assert_eq!(dao.archive_completed_todos(DEFAULT_LIST_ID).await.unwrap(), 0, "Expected an open subtask to keep its tree");
+
+    dao.update_todo(DEFAULT_LIST_ID, child.id as u64, &UpdateTodo { completed: Some(true), ..Default::default() }).await.unwrap();
+    assert_eq!(dao.archive_completed_todos(DEFAULT_LIST_ID).await.unwrap(), 3);
+    let archived = dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap();
+    let id_of = |title: &str| archived.iter().find(|todo| todo.title == title).unwrap().id;
+    let parent_of = |title: &str| archived.iter().find(|todo| todo.title == title).unwrap().parent_id;
+    assert_eq!(parent_of("Move"), None);
+    assert_eq!(parent_of("Pack"), Some(id_of("Move")));
+    assert_eq!(parent_of("Books"), Some(id_of("Pack")));
+
+    let parent = dao.save_todo(&new_todo("Trip", 1, false)).await.unwrap();
+    let child = dao.save_todo(&backend::Todo { parent_id: Some(parent.id), ..new_todo("Tickets", 1, false) }).await.unwrap();
+    let update = UpdateTodo { parent_id: Some(None), ..Default::default() };
+    assert_eq!(dao.update_todo(DEFAULT_LIST_ID, child.id as u64, &update).await.unwrap().parent_id, None);
+    let update = UpdateTodo { parent_id: Some(Some(parent.id)), ..Default::default() };
+    assert_eq!(dao.update_todo(DEFAULT_LIST_ID, child.id as u64, &update).await.unwrap().parent_id, Some(parent.id));
+    dao.delete_todo(DEFAULT_LIST_ID, parent.id as u64).await.unwrap();
+    assert!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap().is_empty(), "Expected subtasks to be deleted with their parent");
+}
+
+#[tokio::test]
+async fn test_dependencies_block_todos_until_blockers_are_done() {
+    let dao = new_dao().await;
+    let deploy = dao.save_todo(&new_todo("Deploy", 3, false)).await.unwrap();
+    let test = dao.save_todo(&new_todo("Test", 1, false)).await.unwrap();
+    let blocked = dao.add_dependency(DEFAULT_LIST_ID, deploy.id as u64, test.id as u64).await.unwrap();
+    assert!(blocked.blocked);
+    dao.add_dependency(DEFAULT_LIST_ID, deploy.id as u64, test.id as u64).await.unwrap();
+    let dependencies = dao.query_dependencies(DEFAULT_LIST_ID).await.unwrap();
+    assert_eq!(dependencies, vec![Dependency { todo_id: deploy.id, blocker_id: test.id }]);
+    assert!(dao.add_dependency(DEFAULT_LIST_ID, deploy.id as u64, 99).await.is_err());
+
+    let completed = dao.toggle_todo_completion(DEFAULT_LIST_ID, test.id as u64).await.unwrap();
+    assert!(!completed.blocked);
+    assert!(!dao.query_todo(DEFAULT_LIST_ID, deploy.id as u64).await.unwrap().blocked);
+    dao.toggle_todo_completion(DEFAULT_LIST_ID, test.id as u64).await.unwrap();
+    assert!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap()[0].blocked);
+
+    dao.remove_dependency(DEFAULT_LIST_ID, deploy.id as u64, test.id as u64).await.unwrap();
+    assert!(dao.remove_dependency(DEFAULT_LIST_ID, deploy.id as u64, test.id as u64).await.is_err());
+    dao.add_dependency(DEFAULT_LIST_ID, deploy.id as u64, test.id as u64).await.unwrap();
+    dao.delete_todo(DEFAULT_LIST_ID, test.id as u64).await.unwrap();
+    assert!(dao.query_dependencies(DEFAULT_LIST_ID).await.unwrap().is_empty(), "Expected dependencies to go with their blocker");
+}
+
+#[tokio::test]
+async fn test_notes_are_kept_and_searched() {
+    let dao = new_dao().await;
+    let notes = Some("Check the *oil* and the tyres".to_string());
+    let todo = dao.save_todo(&backend::Todo { notes, ..new_todo("Service the car", 2, true) }).await.unwrap();
+    assert_eq!(todo.notes.as_deref(), Some("Check the *oil* and the tyres"));
+    dao.save_todo(&new_todo("Buy oil", 1, false)).await.unwrap();
+
+    let search = SearchQuery { terms: vec!["oil".to_string()], scope: SearchScope::Both, limit: 10 };
+    let hits = dao.search_todos(DEFAULT_LIST_ID, &search).await.unwrap();
+    let titles: Vec<&str> = hits.iter().map(|h| h.title.as_str()).collect();
+    assert_eq!(titles, vec!["Buy oil", "Service the car"], "Expected title matches to rank above notes matches");
+    assert_eq!(hits[0].notes_snippet, None);
+    assert!(hits[1].notes_snippet.as_deref().unwrap().contains("<mark>"));
+
+    dao.archive_completed_todos(DEFAULT_LIST_ID).await.unwrap();
+    let archived = dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap();
+    assert_eq!(archived[0].notes.as_deref(), Some("Check the *oil* and the tyres"));
+    let search = SearchQuery { terms: vec!["tyre".to_string()], scope: SearchScope::Archived, limit: 10 };
+    assert_eq!(dao.search_todos(DEFAULT_LIST_ID, &search).await.unwrap().len(), 1);
+
+    let open = dao.query_todos(DEFAULT_LIST_ID).await.unwrap();
+    let update = UpdateTodo { notes: Some(Some("Synthetic".to_string())), ..Default::default() };
+    let updated = dao.update_todo(DEFAULT_LIST_ID, open[0].id as u64, &update).await.unwrap();
+    assert_eq!(updated.notes.as_deref(), Some("Synthetic"));
+    let update = UpdateTodo { notes: Some(None), ..Default::default() };
+    assert_eq!(dao.update_todo(DEFAULT_LIST_ID, open[0].id as u64, &update).await.unwrap().notes, None);
+}
+
+#[tokio::test]
+async fn test_attachments_follow_their_todo() {
+    let dao = new_dao().await;
+    let invoice = dao.save_todo(&new_todo("Pay invoice", 1, true)).await.unwrap();
+    let receipt = dao.save_todo(&new_todo("File receipt", 1, false)).await.unwrap();
+    let attachment = Attachment {
+        id: 0,
+        todo_id: invoice.id,
+        archived: false,
+        file_name: "invoice.pdf".to_string(),
+        content_type: "application/pdf".to_string(),
+        size: 9,
+        sha256: "ab".repeat(32),
+        created_at: Utc.with_ymd_and_hms(2024, 5, 1, 9, 0, 0).unwrap(),
+        storage_key: "key-1".to_string(),
+    };
+    let saved = dao.save_attachment(DEFAULT_LIST_ID, &attachment).await.unwrap();
+    assert_eq!(saved, Attachment { id: saved.id, ..attachment.clone() });
+    assert_eq!(dao.query_attachments(DEFAULT_LIST_ID, invoice.id as u64).await.unwrap(), vec![saved.clone()]);
+    assert_eq!(dao.query_attachment(DEFAULT_LIST_ID, invoice.id as u64, saved.id).await.unwrap(), saved);
+    let result = dao.query_attachment(DEFAULT_LIST_ID, receipt.id as u64, saved.id).await;
+    assert!(matches!(result, Err(ApiError::NotFound(_))));
+    let result = dao.save_attachment(DEFAULT_LIST_ID, &Attachment { todo_id: 999, ..attachment.clone() }).await;
+    assert!(matches!(result, Err(ApiError::NotFound(_))));
+
+    let other = dao.save_attachment(DEFAULT_LIST_ID, &Attachment { todo_id: receipt.id, storage_key: "key-2".to_string(), ..attachment.clone() }).await.unwrap();
+    assert_eq!(dao.delete_attachment(DEFAULT_LIST_ID, receipt.id as u64, other.id).await.unwrap(), other);
+    assert!(dao.query_attachments(DEFAULT_LIST_ID, receipt.id as u64).await.unwrap().is_empty());
+
+    dao.save_attachment(DEFAULT_LIST_ID, &Attachment { todo_id: receipt.id, storage_key: "key-3".to_string(), ..attachment.clone() }).await.unwrap();
+    dao.archive_completed_todos(DEFAULT_LIST_ID).await.unwrap();
+    let archived = dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap();
+    let listed = dao.query_list_attachments(DEFAULT_LIST_ID).await.unwrap();
+    assert_eq!(listed.len(), 2);
+    let moved = listed.iter().find(|listed| listed.storage_key == "key-1").unwrap();
+    assert!(moved.archived, "Expected attachments to move to the archive with their todo");
+    assert_eq!(moved.todo_id, archived[0].id);
+
+    dao.delete_todo(DEFAULT_LIST_ID, receipt.id as u64).await.unwrap();
+    let listed = dao.query_list_attachments(DEFAULT_LIST_ID).await.unwrap();
+    assert_eq!(listed.iter().map(|listed| listed.storage_key.as_str()).collect::<Vec<_>>(), vec!["key-1"]);
+}
+
+#[tokio::test]
+async fn test_restoring_archived_todos_brings_back_their_details() {
+    let dao = new_dao().await;
+    let parent = dao.save_todo(&new_todo("Move", 3, true)).await.unwrap();
+    let child = dao.save_todo(&backend::Todo { parent_id: Some(parent.id), ..new_todo("Pack", 1, true) }).await.unwrap();
+    dao.save_todo(&backend::Todo { recurrence: Some("FREQ=WEEKLY".to_string()), ..new_todo("Water plants", 2, true) }).await.unwrap();
+    dao.add_todo_tag(DEFAULT_LIST_ID, parent.id as u64, "home", None).await.unwrap();
+    let attachment = Attachment {
+        id: 0,
+        todo_id: child.id,
+        archived: false,
+        file_name: "list.txt".to_string(),
+        content_type: "text/plain".to_string(),
+        size: 1,
+        sha256: "ab".repeat(32),
+        created_at: Utc::now(),
+        storage_key: "key-1".to_string(),
+    };
+    dao.save_attachment(DEFAULT_LIST_ID, &attachment).await.unwrap();
+    assert_eq!(dao.archive_completed_todos(DEFAULT_LIST_ID).await.unwrap(), 3);
+    let archived = dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap();
+    let archived_id = |title: &str| archived.iter().find(|todo| todo.title == title).unwrap().id;
+
+    let result = dao.restore_archived_todos(DEFAULT_LIST_ID, &[999], false).await;
+    assert!(matches!(result, Err(ApiError::NotFound(_))));
+    assert_eq!(dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap().len(), 3);
+
+    let restored = dao.restore_archived_todos(DEFAULT_LIST_ID, &[archived_id("Move")], false).await.unwrap();
+    let titles: Vec<&str> = restored.iter().map(|todo| todo.title.as_str()).collect();
+    assert_eq!(titles, vec!["Move", "Pack"]);
+    assert!(restored.iter().all(|todo| todo.completed));
+    assert_eq!(restored[0].priority, 3);
+    assert_eq!(restored[0].tags[0].name, "home");
+    assert_eq!(restored[1].parent_id, Some(restored[0].id));
+    let attachments = dao.query_attachments(DEFAULT_LIST_ID, restored[1].id as u64).await.unwrap();
+    assert_eq!(attachments.len(), 1, "Expected attachments to come back with their todo");
+    assert!(!attachments[0].archived);
+
+    let restored = dao.restore_archived_todos(DEFAULT_LIST_ID, &[archived_id("Water plants")], false).await.unwrap();
+    let update = UpdateTodo { title: Some("Water the plants".to_string()), ..Default::default() };
+    dao.update_todo(DEFAULT_LIST_ID, restored[0].id as u64, &update).await.unwrap();
+    assert_eq!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap().len(), 3, "Expected no second occurrence of a restored todo");
+    assert!(dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap().is_empty());
+
+    dao.archive_completed_todos(DEFAULT_LIST_ID).await.unwrap();
+    let archived = dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap();
+    let pack = archived.iter().find(|todo| todo.title == "Pack").unwrap().id;
+    let restored = dao.restore_archived_todos(DEFAULT_LIST_ID, &[pack], true).await.unwrap();
+    assert_eq!(restored[0].parent_id, None, "Expected a subtask whose parent stays archived to become top-level");
+    assert!(!restored[0].completed);
+}
+
+#[tokio::test]
+async fn test_checked_updates_move_and_complete_whole_trees() {
+    let dao = new_dao().await;
+    let trip = dao.save_todo(&new_todo("Trip", 2, false)).await.unwrap();
+    let pack = dao.save_todo(&backend::Todo { parent_id: Some(trip.id), ..new_todo("Pack", 1, false) }).await.unwrap();
+    let socks = dao.save_todo(&backend::Todo { parent_id: Some(pack.id), ..new_todo("Socks", 1, false) }).await.unwrap();
+    let tickets = dao.save_todo(&new_todo("Tickets", 1, false)).await.unwrap();
+
+    let move_below = |parent_id: u32| UpdateTodo { parent_id: Some(Some(parent_id)), ..Default::default() };
+    let result = dao.update_todo_checked(DEFAULT_LIST_ID, trip.id as u64, &move_below(socks.id), false).await;
+    assert!(matches!(result, Err(ApiError::Validation(_))), "Expected a todo not to move below its own subtask");
+    let result = dao.update_todo_checked(DEFAULT_LIST_ID, 999, &move_below(trip.id), false).await;
+    assert!(matches!(result, Err(ApiError::NotFound(_))));
+
+    dao.add_dependency(DEFAULT_LIST_ID, socks.id as u64, tickets.id as u64).await.unwrap();
+    let complete = UpdateTodo { completed: Some(true), ..Default::default() };
+    let result = dao.update_todo_checked(DEFAULT_LIST_ID, trip.id as u64, &complete, true).await;
+    assert!(matches!(result, Err(ApiError::Conflict(_))), "Expected a blocked subtask to stop the cascade");
+    assert!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap().iter().all(|todo| !todo.completed), "Expected nothing to be completed");
+
+    dao.update_todo(DEFAULT_LIST_ID, tickets.id as u64, &complete).await.unwrap();
+    let done = dao.update_todo_checked(DEFAULT_LIST_ID, trip.id as u64, &complete, true).await.unwrap();
+    assert!(done.completed);
+    assert!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap().iter().all(|todo| todo.completed));
+}
+
+#[tokio::test]
+async fn test_restored_todos_keep_their_id_and_do_not_recur_twice() {
+    let dao = new_dao().await;
+    let todo = dao.save_todo(&new_todo("Move", 3, true)).await.unwrap();
+    dao.archive_completed_todos(DEFAULT_LIST_ID).await.unwrap();
+    let restored = dao.restore_archived_todos(DEFAULT_LIST_ID, &[todo.id], false).await.unwrap();
+    assert_eq!(restored[0].id, todo.id, "Expected a restored todo to keep its id");
+    dao.archive_todo(DEFAULT_LIST_ID, todo.id as u64).await.unwrap();
+    assert_eq!(dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap()[0].id, todo.id);
+
+    let plants = dao.save_todo(&backend::Todo { recurrence: Some("FREQ=WEEKLY".to_string()), ..new_todo("Water plants", 2, false) }).await.unwrap();
+    dao.toggle_todo_completion(DEFAULT_LIST_ID, plants.id as u64).await.unwrap();
+    dao.archive_todo(DEFAULT_LIST_ID, plants.id as u64).await.unwrap();
+    let restored = dao.restore_archived_todos(DEFAULT_LIST_ID, &[plants.id], true).await.unwrap();
+    assert_eq!(restored[0].id, plants.id);
+    assert!(!restored[0].completed);
+    dao.toggle_todo_completion(DEFAULT_LIST_ID, plants.id as u64).await.unwrap();
+    let todos = dao.query_todos(DEFAULT_LIST_ID).await.unwrap();
+    assert_eq!(todos.iter().filter(|todo| todo.title == "Water plants").count(), 2, "Expected a reopened occurrence not to recur again");
+}
+
+#[tokio::test]
+async fn test_archiving_a_todo_keeps_its_id_and_completion_time() {
+    let dao = new_dao().await;
+    let todo = dao.save_todo(&new_todo("Call mom", 1, false)).await.unwrap();
+    assert_eq!(todo.completed_at, None);
+    let completed = dao.toggle_todo_completion(DEFAULT_LIST_ID, todo.id as u64).await.unwrap();
+    assert!(completed.completed_at.is_some());
+    let update = UpdateTodo { title: Some("Call grandma".to_string()), completed: Some(true), ..Default::default() };
+    let renamed = dao.update_todo(DEFAULT_LIST_ID, todo.id as u64, &update).await.unwrap();
+    assert_eq!(renamed.completed_at, completed.completed_at, "Expected completing a completed todo to keep its time");
+    let child = dao.save_todo(&backend::Todo { parent_id: Some(todo.id), ..new_todo("Buy flowers", 1, false) }).await.unwrap();
+
+    let result = dao.archive_todo(DEFAULT_LIST_ID, 999).await;
+    assert!(matches!(result, Err(ApiError::NotFound(_))));
+    let archived = dao.archive_todo(DEFAULT_LIST_ID, todo.id as u64).await.unwrap();
+    assert_eq!(archived.id, todo.id);
+    assert_eq!(archived.completed_at, completed.completed_at);
+    let archived = dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap();
+    let subtask = archived.iter().find(|archived| archived.title == "Buy flowers").unwrap();
+    assert_eq!((subtask.id, subtask.parent_id), (child.id, Some(todo.id)), "Expected subtasks to be archived along");
+    assert!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap().is_empty());
+
+    let next = dao.save_todo(&new_todo("Call dad", 1, true)).await.unwrap();
+    assert!(next.id > child.id);
+    assert!(next.completed_at.is_some());
+    let restored = dao.restore_archived_todos(DEFAULT_LIST_ID, &[todo.id], false).await.unwrap();
+    assert_eq!(restored[0].completed_at, completed.completed_at);
+    let reopened = dao.toggle_todo_completion(DEFAULT_LIST_ID, restored[0].id as u64).await.unwrap();
+    assert_eq!(reopened.completed_at, None);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_archiving_while_todos_are_completed_loses_no_todo() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("sqlite://{}", dir.path().join("todos.db").display());
+    let dao = Arc::new(SqliteTodoListDao::new(&url).await.unwrap());
+    dao.initialize().await;
+    let mut ids = Vec::new();
+    for i in 0..40 {
+        ids.push(dao.save_todo(&new_todo(&format!("Todo {}", i), 1, false)).await.unwrap().id);
+    }
+
+    let completer = {
+        let dao = dao.clone();
+        let ids = ids.clone();
+        tokio::spawn(async move {
+            for id in ids {
+                dao.toggle_todo_completion(DEFAULT_LIST_ID, id as u64).await.unwrap();
+            }
+        })
+    };
+    let archivers: Vec<_> = (0..3).map(|_| {
+        let dao = dao.clone();
+        tokio::spawn(async move {
+            for _ in 0..15 {
+                dao.archive_completed_todos(DEFAULT_LIST_ID).await.unwrap();
+            }
+        })
+    }).collect();
+    completer.await.unwrap();
+    for archiver in archivers {
+        archiver.await.unwrap();
+    }
+    dao.archive_completed_todos(DEFAULT_LIST_ID).await.unwrap();
+
+    assert!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap().is_empty());
+    let archived = dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap();
+    assert!(archived.iter().all(|todo| todo.completed));
+    let mut archived_ids: Vec<u32> = archived.iter().map(|todo| todo.id).collect();
+    archived_ids.sort();
+    assert_eq!(archived_ids, ids, "Expected every todo to be archived exactly once");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_attaching_to_a_todo_being_deleted_fails_cleanly() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("sqlite://{}", dir.path().join("todos.db").display());
+    let dao = Arc::new(SqliteTodoListDao::new(&url).await.unwrap());
+    dao.initialize().await;
+    for round in 0..10 {
+        let todo = dao.save_todo(&new_todo(&format!("Receipt {}", round), 1, false)).await.unwrap();
+        let uploader = {
+            let dao = dao.clone();
+            tokio::spawn(async move {
+                let mut results = Vec::new();
+                for i in 0..5 {
+                    let attachment = Attachment {
+                        id: 0,
+                        todo_id: todo.id,
+                        archived: false,
+                        file_name: "receipt.pdf".to_string(),
+                        content_type: "application/pdf".to_string(),
+                        size: 1,
+                        sha256: "ab".repeat(32),
+                        created_at: Utc::now(),
+                        storage_key: format!("key-{}-{}", todo.id, i),
+                    };
+                    results.push(dao.save_attachment(DEFAULT_LIST_ID, &attachment).await);
+                }
+                results
+            })
+        };
+        dao.delete_todo(DEFAULT_LIST_ID, todo.id as u64).await.unwrap();
+        for result in uploader.await.unwrap() {
+            assert!(matches!(result, Ok(_) | Err(ApiError::NotFound(_))), "Expected uploads to a deleted todo to be not found");
+        }
+    }
+    assert!(dao.query_list_attachments(DEFAULT_LIST_ID).await.unwrap().is_empty(), "Expected no attachment to outlive its todo");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_blocking_each_other_at_once_makes_no_cycle() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("sqlite://{}", dir.path().join("todos.db").display());
+    let dao = Arc::new(SqliteTodoListDao::new(&url).await.unwrap());
+    dao.initialize().await;
+    let mut pairs = Vec::new();
+    for i in 0..20 {
+        let first = dao.save_todo(&new_todo(&format!("First {}", i), 1, false)).await.unwrap().id as u64;
+        let second = dao.save_todo(&new_todo(&format!("Second {}", i), 1, false)).await.unwrap().id as u64;
+        pairs.push((first, second));
+    }
+
+    let link = |flip: bool| {
+        let dao = dao.clone();
+        let pairs = pairs.clone();
+        tokio::spawn(async move {
+            let mut added = 0;
+            for (first, second) in pairs {
+                let (todo_id, blocker_id) = if flip { (second, first) } else { (first, second) };
+                if dao.add_dependency(DEFAULT_LIST_ID, todo_id, blocker_id).await.is_ok() {
+                    added += 1;
+                }
+            }
+            added
+        })
+    };
+    let (forward, backward) = (link(false), link(true));
+    let added = forward.await.unwrap() + backward.await.unwrap();
+
+    assert_eq!(added, pairs.len(), "Expected only one direction of each pair to be added");
+    assert_eq!(dao.query_dependencies(DEFAULT_LIST_ID).await.unwrap().len(), pairs.len());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_reopening_a_blocker_while_toggling_keeps_the_todo_blocked() {
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("sqlite://{}", dir.path().join("todos.db").display());
+    let dao = Arc::new(SqliteTodoListDao::new(&url).await.unwrap());
+    dao.initialize().await;
+    let blocker = dao.save_todo(&new_todo("Test", 1, true)).await.unwrap();
+    let todo = dao.save_todo(&new_todo("Deploy", 1, false)).await.unwrap();
+    dao.add_dependency(DEFAULT_LIST_ID, todo.id as u64, blocker.id as u64).await.unwrap();
+
+    let reopened = Arc::new(AtomicBool::new(false));
+    let toggler = {
+        let (dao, reopened) = (dao.clone(), reopened.clone());
+        tokio::spawn(async move {
+            loop {
+                let after_reopening = reopened.load(Ordering::SeqCst);
+                match dao.toggle_todo_completion(DEFAULT_LIST_ID, todo.id as u64).await {
+                    Ok(toggled) => assert!(!(after_reopening && toggled.completed), "Expected a reopened blocker to block"),
+                    Err(error) => return error,
+                }
+            }
+        })
+    };
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    dao.toggle_todo_completion(DEFAULT_LIST_ID, blocker.id as u64).await.unwrap();
+    reopened.store(true, Ordering::SeqCst);
+
+    assert!(matches!(toggler.await.unwrap(), ApiError::Conflict(_)));
+    assert!(!dao.query_todo(DEFAULT_LIST_ID, todo.id as u64).await.unwrap().completed);
+}
+
+#[tokio::test]
+async fn test_retention_archives_and_purges_by_age() {
+    let dao = new_dao().await;
+    let now = Utc::now();
+    let chore = dao.save_todo(&new_todo("Old chore", 1, true)).await.unwrap();
+    dao.save_todo(&new_todo("Open chore", 1, false)).await.unwrap();
+    let trip = dao.save_todo(&new_todo("Trip", 2, true)).await.unwrap();
+    dao.save_todo(&backend::Todo { parent_id: Some(trip.id), ..new_todo("Pack", 1, false) }).await.unwrap();
+    let attachment = Attachment {
+        id: 0,
+        todo_id: chore.id,
+        archived: false,
+        file_name: "chore.txt".to_string(),
+        content_type: "text/plain".to_string(),
+        size: 1,
+        sha256: "ab".repeat(32),
+        created_at: now,
+        storage_key: "key-chore".to_string(),
+    };
+    dao.save_attachment(DEFAULT_LIST_ID, &attachment).await.unwrap();
+    assert_eq!(dao.query_list_ids().await.unwrap(), vec![DEFAULT_LIST_ID]);
+
+    let archived = dao.archive_completed_before(DEFAULT_LIST_ID, now - Duration::days(30), now).await.unwrap();
+    assert_eq!(archived, 0, "Expected fresh completions to stay");
+    let later = now + Duration::days(31);
+    let archived = dao.archive_completed_before(DEFAULT_LIST_ID, later - Duration::days(30), later).await.unwrap();
+    assert_eq!(archived, 1, "Expected trees with open subtasks to stay");
+    assert_eq!(dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap()[0].title, "Old chore");
+    let stale = dao.query_archived_before(DEFAULT_LIST_ID, later - Duration::minutes(1)).await.unwrap();
+    assert!(stale.is_empty(), "Expected the todo to count as archived at the given time");
+
+    let much_later = later + Duration::days(91);
+    let stale = dao.query_archived_before(DEFAULT_LIST_ID, much_later - Duration::days(90)).await.unwrap();
+    assert_eq!(stale.iter().map(|todo| todo.title.as_str()).collect::<Vec<_>>(), vec!["Old chore"]);
+    assert_eq!(dao.purge_archived_before(DEFAULT_LIST_ID, much_later - Duration::days(90)).await.unwrap(), 1);
+    assert!(dao.query_archived_todos(DEFAULT_LIST_ID).await.unwrap().is_empty());
+    assert!(dao.query_list_attachments(DEFAULT_LIST_ID).await.unwrap().is_empty(), "Expected purged attachments to go too");
+    assert_eq!(dao.query_todos(DEFAULT_LIST_ID).await.unwrap().len(), 3);
+}
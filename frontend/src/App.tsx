@@ -1,11 +1,22 @@
+import { useEffect, useState } from 'react';
 import TodoList from './TodoList';
+import Login, { User } from './Login';
+import apiClient from './client';
 import './App.css';
 
 function App() {
+  const [user, setUser] = useState<User | null>(null);
+
+  useEffect(() => {
+    apiClient.get<User>('/api/auth/me')
+      .then((response) => setUser(response.data))
+      .catch(() => setUser(null));
+  }, []);
+
   return (
     <div className="App">
       <header className="App-header">
-        <TodoList />
+        {user ? <TodoList /> : <Login onSignedIn={setUser} />}
       </header>
     </div>
   );
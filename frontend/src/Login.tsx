@@ -0,0 +1,85 @@
+import React, { useState } from 'react';
+import apiClient, { API_BASE_URL } from './client';
+
+export interface User {
+  id: number;
+  email: string;
+}
+
+interface LoginProps {
+  onSignedIn: (user: User) => void;
+}
+
+const inputStyle: React.CSSProperties = {
+  padding: '8px 12px',
+  borderRadius: 4,
+  border: '1px solid #ddd',
+  fontSize: 14,
+  fontFamily: 'inherit',
+};
+
+const buttonStyle: React.CSSProperties = {
+  padding: '8px 16px',
+  borderRadius: 4,
+  border: 'none',
+  backgroundColor: '#007bff',
+  color: 'white',
+  cursor: 'pointer',
+  fontSize: 14,
+  fontWeight: 500,
+};
+
+const Login: React.FC<LoginProps> = ({ onSignedIn }) => {
+  const [email, setEmail] = useState('');
+  const [password, setPassword] = useState('');
+  const [error, setError] = useState('');
+
+  const submit = (path: string) => async () => {
+    try {
+      const response = await apiClient.post<User>(path, { email, password });
+      setError('');
+      onSignedIn(response.data);
+    } catch (error: any) {
+      setError(error.response?.data?.message ?? 'Could not reach the server');
+    }
+  };
+
+  return (
+    <div>
+      <h1>Todo List</h1>
+      <div style={{ display: 'flex', flexDirection: 'column', gap: 8, minWidth: 280 }}>
+        <input
+          type="email"
+          placeholder="Email"
+          value={email}
+          onChange={(e) => setEmail(e.target.value)}
+          style={inputStyle}
+        />
+        <input
+          type="password"
+          placeholder="Password"
+          value={password}
+          onChange={(e) => setPassword(e.target.value)}
+          style={inputStyle}
+        />
+        <div style={{ display: 'flex', gap: 8 }}>
+          <button onClick={submit('/api/auth/login')} style={{ ...buttonStyle, flex: 1 }}>
+            Sign in
+          </button>
+          <button
+            onClick={submit('/api/auth/register')}
+            style={{ ...buttonStyle, flex: 1, backgroundColor: '#6c757d' }}
+          >
+            Register
+          </button>
+        </div>
+        <a href={`${API_BASE_URL}/api/auth/oidc/login`} style={{ color: '#61dafb', fontSize: 14 }}>
+          Sign in with SSO
+        </a>
+        {error && <div style={{ color: '#ff6b6b', fontSize: 14 }}>{error}</div>}
+      </div>
+    </div>
+  );
+};
+
+export default Login;
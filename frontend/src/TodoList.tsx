@@ -83,7 +83,7 @@ const TodoList: React.FC = () => {
     if (todos.find((t) => t.id === id)?.priority === 10 ||
       todos.find((t) => t.id === id)?.completed === true) return;
     try {
-      await apiClient.post<{ text: string }>('/api/todos/increase_priority', { id });
+      await apiClient.post<Todo>('/api/todos/increase_priority', { id });
       setTodos((prev) => prev.map((t) => t.id === id ? { ...t, priority: t.priority + 1 } : t));
     } catch (error) {
       console.error('Error increasing todo priority:', error);
@@ -94,7 +94,7 @@ const TodoList: React.FC = () => {
     if (todos.find((t) => t.id === id)?.priority === 1 ||
       todos.find((t) => t.id === id)?.completed === true) return;
     try {
-      await apiClient.post<{ text: string }>('/api/todos/decrease_priority', { id });
+      await apiClient.post<Todo>('/api/todos/decrease_priority', { id });
       setTodos((prev) => prev.map((t) => t.id === id ? { ...t, priority: t.priority - 1 } : t));
     } catch (error) {
       console.error('Error decreasing todo priority:', error);